@@ -0,0 +1,311 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+
+use clap::ArgMatches;
+use serde::Deserialize;
+
+use crate::cli::CommandErrorKind::{InvalidConfig, UnknownPreset};
+use crate::cli::UserInputError;
+
+/// Name of the project-local configuration file, searched for in the current working directory.
+pub const PROJECT_CONFIG_FILE: &str = ".h2ok.toml";
+
+type Validator = fn(String) -> Result<(), String>;
+
+/// Arguments of the `deploy` and `preflight` commands whose values may be set by a configuration file, each with the validator
+/// of its value. The keys of a configuration file are the same as the names of the arguments.
+pub const CONFIGURABLE_ARGS: [(&str, Validator); 7] = [
+    ("cluster_size", super::validate_int_greater_than_zero),
+    ("memory", super::validate_memory),
+    ("cpus", super::validate_int_greater_than_zero),
+    ("memory_percentage", super::validate_percentage),
+    ("namespace", accept_any),
+    ("kubeconfig", super::validate_path),
+    ("context", accept_any),
+];
+
+fn accept_any(_: String) -> Result<(), String> {
+    return Ok(());
+}
+
+/// Structure of a configuration file, e.g.:
+///
+/// ```toml
+/// [defaults]
+/// kubeconfig = "~/.kube/production"
+/// memory = "4Gi"
+///
+/// [presets.xgboost]
+/// memory = "32Gi"
+/// cpus = 8
+/// memory_percentage = 50
+/// ```
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct ConfigFileContent {
+    #[serde(default)]
+    defaults: BTreeMap<String, toml::Value>,
+    #[serde(default)]
+    presets: BTreeMap<String, BTreeMap<String, toml::Value>>,
+}
+
+/// A single configuration file loaded.
+#[derive(Debug)]
+pub struct ConfigFile {
+    pub path: PathBuf,
+    defaults: BTreeMap<String, String>,
+    presets: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl ConfigFile {
+    /// Parses the content of the configuration file found under the given path. Unknown keys are rejected, so typos are reported.
+    pub fn parse(path: &Path, content: &str) -> Result<Self, UserInputError> {
+        let invalid = |reason: String| UserInputError::new(InvalidConfig { path: path.to_path_buf(), reason });
+        let content: ConfigFileContent = toml::from_str(content).map_err(|e| invalid(e.to_string()))?;
+        let mut presets: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+        for (name, values) in content.presets.into_iter() {
+            presets.insert(name, to_strings(values).map_err(&invalid)?);
+        }
+        return Ok(ConfigFile { path: path.to_path_buf(), defaults: to_strings(content.defaults).map_err(&invalid)?, presets });
+    }
+}
+
+/// Converts the values of a configuration file table into the textual form the command line arguments have.
+fn to_strings(values: BTreeMap<String, toml::Value>) -> Result<BTreeMap<String, String>, String> {
+    let mut strings: BTreeMap<String, String> = BTreeMap::new();
+    for (key, value) in values.into_iter() {
+        if !CONFIGURABLE_ARGS.iter().any(|(name, _)| *name == key) {
+            let names: Vec<&str> = CONFIGURABLE_ARGS.iter().map(|(name, _)| *name).collect();
+            return Err(format!("Unknown key '{}', expected one of: {}.", key, names.join(", ")));
+        }
+        let string: String = match value {
+            toml::Value::String(string) => { string }
+            toml::Value::Integer(integer) => { integer.to_string() }
+            _ => { return Err(format!("Value of '{}' must be a string or an integer.", key)); }
+        };
+        strings.insert(key, string);
+    }
+    return Ok(strings);
+}
+
+/// Configuration files loaded, providing defaults of the arguments and named presets.
+#[derive(Debug, Default)]
+pub struct Config {
+    /// Configuration files loaded, in the order of increasing precedence - the user configuration file first.
+    pub files: Vec<ConfigFile>,
+}
+
+impl Config {
+    /// Loads the user configuration file and the project-local configuration file, if they exist.
+    pub fn load() -> Result<Self, UserInputError> {
+        let mut files: Vec<ConfigFile> = vec!();
+        for path in config_file_paths().iter().filter(|path| path.is_file()) {
+            let content: String = std::fs::read_to_string(path)
+                .map_err(|e| UserInputError::new(InvalidConfig { path: path.clone(), reason: e.to_string() }))?;
+            files.push(ConfigFile::parse(path, &content)?);
+        }
+        return Ok(Config { files });
+    }
+
+    /// Names of the presets defined by any of the configuration files.
+    pub fn preset_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.files.iter()
+            .flat_map(|file| file.presets.keys().cloned())
+            .collect();
+        names.sort();
+        names.dedup();
+        return names;
+    }
+
+    /// Resolves the effective value of each configurable argument. A value comes from the command line, then the preset,
+    /// then the configuration files and then the default of the argument. A project-local configuration file takes precedence
+    /// over the user configuration file, both for presets and defaults.
+    pub fn resolve(&self, args: &ArgMatches, preset: Option<&str>) -> Result<Vec<EffectiveValue>, UserInputError> {
+        if let Some(preset) = preset {
+            if !self.files.iter().any(|file| file.presets.contains_key(preset)) {
+                return Err(UserInputError::new(UnknownPreset(preset.to_string())));
+            }
+        }
+
+        let mut effective_values: Vec<EffectiveValue> = vec!();
+        for (name, validator) in CONFIGURABLE_ARGS.iter() {
+            if args.occurrences_of(name) > 0 {
+                effective_values.push(EffectiveValue { name, value: args.value_of(name).map(String::from), source: Source::Flag });
+                continue;
+            }
+            let from_preset = preset.and_then(|preset| self.files.iter().rev()
+                .find_map(|file| file.presets.get(preset)?.get(*name).map(|value| (value, Source::Preset { name: preset.to_string(), path: file.path.clone() }))));
+            let from_file = || self.files.iter().rev()
+                .find_map(|file| file.defaults.get(*name).map(|value| (value, Source::ConfigFile(file.path.clone()))));
+            let effective_value: EffectiveValue = match from_preset.or_else(from_file) {
+                Some((value, source)) => {
+                    let value: String = expand_home(value);
+                    validator(value.clone()).map_err(|reason| UserInputError::new(InvalidConfig {
+                        path: source.path().unwrap().to_path_buf(),
+                        reason: format!("Invalid value '{}' of '{}'. {}", value, name, reason),
+                    }))?;
+                    EffectiveValue { name, value: Some(value), source }
+                }
+                None => {
+                    match args.value_of(name) {
+                        Some(value) => { EffectiveValue { name, value: Some(value.to_string()), source: Source::Default } }
+                        None => { EffectiveValue { name, value: None, source: Source::Unset } }
+                    }
+                }
+            };
+            effective_values.push(effective_value);
+        }
+        return Ok(effective_values);
+    }
+}
+
+/// Paths to the configuration files, in the order of increasing precedence. The user configuration file is located
+/// in `$XDG_CONFIG_HOME/h2ok`, defaulting to `~/.config/h2ok`.
+fn config_file_paths() -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = vec!();
+    let config_home: Option<PathBuf> = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| dirs::home_dir().map(|home| home.join(".config")));
+    if let Some(config_home) = config_home {
+        paths.push(config_home.join("h2ok").join("config.toml"));
+    }
+    paths.push(PathBuf::from(PROJECT_CONFIG_FILE));
+    return paths;
+}
+
+/// Expands the leading `~/` of a path to the home directory of the user, the same way a shell does for command line arguments.
+fn expand_home(value: &str) -> String {
+    return match (value.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(relative), Some(home)) => { home.join(relative).to_string_lossy().to_string() }
+        _ => { value.to_string() }
+    };
+}
+
+/// Effective value of a configurable argument and where it comes from.
+#[derive(Debug, Clone)]
+pub struct EffectiveValue {
+    pub name: &'static str,
+    /// None if the argument has neither been set nor has a default.
+    pub value: Option<String>,
+    pub source: Source,
+}
+
+/// Origin of an effective value of an argument.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    /// Given on the command line.
+    Flag,
+    /// Set by the preset with the given name in the configuration file under the given path.
+    Preset { name: String, path: PathBuf },
+    /// Set by the defaults of the configuration file under the given path.
+    ConfigFile(PathBuf),
+    /// Default of the argument.
+    Default,
+    /// Not set at all.
+    Unset,
+}
+
+impl Source {
+    /// Path to the configuration file the value comes from, if any.
+    pub fn path(&self) -> Option<&Path> {
+        return match self {
+            Source::Preset { path, .. } | Source::ConfigFile(path) => { Some(path) }
+            _ => { None }
+        };
+    }
+
+    /// Machine-readable name of the kind of the source.
+    pub fn kind(&self) -> &'static str {
+        return match self {
+            Source::Flag => { "flag" }
+            Source::Preset { .. } => { "preset" }
+            Source::ConfigFile(_) => { "config" }
+            Source::Default => { "default" }
+            Source::Unset => { "unset" }
+        };
+    }
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            Source::Flag => { write!(f, "command line") }
+            Source::Preset { name, path } => { write!(f, "preset '{}' in '{}'", name, path.display()) }
+            Source::ConfigFile(path) => { write!(f, "defaults in '{}'", path.display()) }
+            Source::Default => { write!(f, "built-in default") }
+            Source::Unset => { write!(f, "not set") }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use clap::ArgMatches;
+
+    use super::{Config, ConfigFile, EffectiveValue, Source};
+
+    const USER_CONFIG: &str = r#"
+[defaults]
+memory = "4Gi"
+cpus = 2
+namespace = "h2o"
+
+[presets.xgboost]
+memory = "32Gi"
+cpus = 8
+memory_percentage = 50
+"#;
+
+    const PROJECT_CONFIG: &str = r#"
+[defaults]
+namespace = "team"
+
+[presets.xgboost]
+cpus = 16
+"#;
+
+    fn value<'a>(values: &'a [EffectiveValue], name: &str) -> &'a EffectiveValue {
+        return values.iter().find(|value| value.name == name).unwrap();
+    }
+
+    #[test]
+    fn test_resolve() {
+        let config: Config = Config {
+            files: vec!(ConfigFile::parse(Path::new("user.toml"), USER_CONFIG).unwrap(),
+                        ConfigFile::parse(Path::new(".h2ok.toml"), PROJECT_CONFIG).unwrap()),
+        };
+        assert_eq!(vec!("xgboost".to_string()), config.preset_names());
+
+        let matches: ArgMatches = crate::cli::build_app().get_matches_from(vec!["h2ok", "deploy", "--memory", "64Gi", "--preset", "xgboost"]);
+        let values: Vec<EffectiveValue> = config.resolve(matches.subcommand_matches("deploy").unwrap(), Some("xgboost")).unwrap();
+        // The command line takes precedence over the preset, the preset over the defaults and the project over the user.
+        assert_eq!(Some("64Gi".to_string()), value(&values, "memory").value);
+        assert_eq!(Source::Flag, value(&values, "memory").source);
+        assert_eq!(Some("16".to_string()), value(&values, "cpus").value);
+        assert_eq!(Some("50".to_string()), value(&values, "memory_percentage").value);
+        assert_eq!(Some("team".to_string()), value(&values, "namespace").value);
+        assert_eq!(Source::ConfigFile(Path::new(".h2ok.toml").to_path_buf()), value(&values, "namespace").source);
+        assert_eq!(Source::Unset, value(&values, "cluster_size").source);
+
+        let matches: ArgMatches = crate::cli::build_app().get_matches_from(vec!["h2ok", "deploy", "--cluster_size", "3"]);
+        let values: Vec<EffectiveValue> = config.resolve(matches.subcommand_matches("deploy").unwrap(), None).unwrap();
+        assert_eq!(Some("4Gi".to_string()), value(&values, "memory").value);
+        assert_eq!(Source::Default, value(&values, "memory_percentage").source);
+        assert!(config.resolve(matches.subcommand_matches("deploy").unwrap(), Some("automl-large")).is_err());
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!(ConfigFile::parse(Path::new("config.toml"), "[defaults]\nmemmory = \"4Gi\"\n").is_err());
+        assert!(ConfigFile::parse(Path::new("config.toml"), "[default]\nmemory = \"4Gi\"\n").is_err());
+        assert!(ConfigFile::parse(Path::new("config.toml"), "[defaults]\ncpus = true\n").is_err());
+
+        let config: Config = Config { files: vec!(ConfigFile::parse(Path::new("config.toml"), "[defaults]\nmemory = \"lots\"\n").unwrap()) };
+        let matches: ArgMatches = crate::cli::build_app().get_matches_from(vec!["h2ok", "deploy"]);
+        assert!(config.resolve(matches.subcommand_matches("deploy").unwrap(), None).is_err());
+    }
+}
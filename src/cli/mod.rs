@@ -1,14 +1,27 @@
+use std::fmt::{Display, Formatter};
 use std::io;
 use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::Duration;
 
 use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use kube::api::PropagationPolicy;
 use names::Generator;
 use num::Num;
 use regex::Regex;
 
-use crate::cli::CommandErrorKind::{MissingDeploymentDescriptor, UnreachableDeploymentDescriptor};
+use crate::cli::CommandErrorKind::{ConfirmationRequired, InvalidArgument, InvalidConfig, MissingArgument, MissingDeploymentDescriptor, UnknownCommand,
+                                   UnknownPreset, UnreachableDeploymentDescriptor, UnknownDeployment, AmbiguousDeployment};
+use crate::cli::config::{Config, EffectiveValue};
+use crate::k8s::{ClusterConnection, DeployOptions, DryRun, UndeployOptions};
+use crate::k8s::export::ExportFormat;
+use crate::k8s::retry::RetryPolicy;
+use crate::k8s::wait::WaitCondition;
+use crate::state;
+use crate::state::StateDir;
+
+pub mod config;
 
 const APP_NAME: &str = "H2O Kubernetes CLI";
 const APP_VERSION: &str = "0.1.0";
@@ -19,70 +32,317 @@ pub fn get_command() -> Result<Command, UserInputError> {
     let args: ArgMatches = app.get_matches();
 
     if let Some(deploy_args) = args.subcommand_matches("deploy") {
-        let deployment_name: String = extract_string(deploy_args, "name").unwrap_or_else(|| {
-            let mut generator: Generator = Generator::default();
-            return format!("h2o-{}", generator.next().unwrap());
-        });
-        let namespace: Option<String> = extract_string(deploy_args, "namespace");
-        // Args below have defaults, it is therefore safe to unwrap.
-        let cluster_size: u32 = extract_num(deploy_args, "cluster_size").unwrap();
-        let jvm_memory_percentage: u8 = extract_num(deploy_args, "memory_percentage").unwrap();
-        let memory: String = extract_string(deploy_args, "memory").unwrap();
-        let num_cpus: u32 = extract_num(deploy_args, "cpus").unwrap();
-        let kubeconfig_path: Option<PathBuf> = match extract_string(deploy_args, "kubeconfig") {
-            None => { Option::None }
-            Some(kubeconfig) => { Some(PathBuf::from(kubeconfig)) }
-        };
-
-        let deployment: UserDeploymentSpecification = UserDeploymentSpecification::new(deployment_name, namespace, jvm_memory_percentage,
-                                                                                       memory, num_cpus, cluster_size, kubeconfig_path);
+        let mut deployment: UserDeploymentSpecification = extract_deployment_specification(deploy_args, &Config::load()?)?;
+        deployment.preflight = !deploy_args.is_present("skip_preflight");
+        deployment.state_dir = extract_state_dir(deploy_args);
+        deployment.descriptor_out = extract_string(deploy_args, "descriptor_out").map(PathBuf::from);
+        deployment.store = extract_descriptor_store(deploy_args);
         return Ok(Command::Deployment(deployment));
+    } else if let Some(preflight_args) = args.subcommand_matches("preflight") {
+        return Ok(Command::Preflight(extract_deployment_specification(preflight_args, &Config::load()?)?));
+    } else if let Some(show_args) = args.subcommand_matches("config").and_then(|config_args| config_args.subcommand_matches("show")) {
+        let config: Config = Config::load()?;
+        let values: Vec<EffectiveValue> = config.resolve(show_args, show_args.value_of("preset"))?;
+        return Ok(Command::Config(ConfigSpecification::new(config, values, extract_output_format(show_args))));
     } else if let Some(undeploy_args) = args.subcommand_matches("undeploy") {
-        return match undeploy_args.value_of("file") {
-            None => {
-                // If there is no file passed as an argument, try to parse file name from stdin.
-                let mut deployment_path_stdin_buf = String::new();
-                io::stdin().read_to_string(&mut deployment_path_stdin_buf).unwrap();
-                if deployment_path_stdin_buf.len() == 0 {
-                    return Err(UserInputError::new(MissingDeploymentDescriptor));
-                }
-                let deployment_descriptor_path: PathBuf = PathBuf::from(&deployment_path_stdin_buf);
-                if deployment_descriptor_path.exists() && deployment_descriptor_path.is_file() {
-                    Ok(Command::Undeploy(deployment_descriptor_path))
-                } else {
-                    let mut pwd_relative_path: PathBuf = std::env::current_dir().unwrap();
-                    pwd_relative_path.push(deployment_descriptor_path);
-
-                    if pwd_relative_path.exists() && pwd_relative_path.is_file() {
-                        Ok(Command::Undeploy(pwd_relative_path))
-                    } else {
-                        Err(UserInputError::new(UnreachableDeploymentDescriptor))
-                    }
-                }
-            }
-            Some(file) => {
-                Ok(Command::Undeploy(PathBuf::from(file)))
-            }
+        let target: UndeployTarget = if let Some(files) = undeploy_args.values_of("file") {
+            UndeployTarget::Descriptors(resolve_descriptors(undeploy_args, files)?)
+        } else if let Some(name) = extract_string(undeploy_args, "name") {
+            UndeployTarget::Name(name)
+        } else if let Some(selector) = extract_string(undeploy_args, "selector") {
+            UndeployTarget::Selector(selector)
+        } else if undeploy_args.is_present("all") {
+            UndeployTarget::All
+        } else {
+            // If there is no file passed as an argument, try to parse file names from stdin.
+            UndeployTarget::Descriptors(descriptor_paths_from_stdin()?)
+        };
+        let namespace: Option<String> = extract_string(undeploy_args, "namespace");
+        let kubeconfig_path: Option<PathBuf> = extract_string(undeploy_args, "kubeconfig").map(PathBuf::from);
+        let assume_yes: bool = undeploy_args.is_present("yes");
+        let propagation_policy: PropagationPolicy = match undeploy_args.value_of("propagation").unwrap() {
+            "foreground" => PropagationPolicy::Foreground,
+            "orphan" => PropagationPolicy::Orphan,
+            _ => PropagationPolicy::Background
+        };
+        let options: UndeployOptions = UndeployOptions {
+            propagation_policy,
+            purge: undeploy_args.is_present("purge"),
+            force: undeploy_args.is_present("force"),
+            retry: extract_retry_policy(undeploy_args)?,
         };
+        let wait_timeout: Option<Duration> = if undeploy_args.is_present("wait") {
+            parse_duration(undeploy_args.value_of("timeout").unwrap())
+        } else {
+            None
+        };
+        let output: OutputFormat = extract_output_format(undeploy_args);
+        let mut undeploy_specification: UndeploySpecification = UndeploySpecification::new(target, namespace, kubeconfig_path, assume_yes, options,
+                                                                                           wait_timeout, output);
+        undeploy_specification.connection = extract_connection(undeploy_args);
+        undeploy_specification.state_dir = extract_state_dir(undeploy_args);
+        undeploy_specification.ignore_cluster_mismatch = undeploy_args.is_present("ignore_cluster_mismatch");
+        return Ok(Command::Undeploy(undeploy_specification));
     } else if let Some(ingress_args) = args.subcommand_matches("ingress") {
-        return match ingress_args.value_of("file") {
-            None => {
-                Err(UserInputError::new(UnreachableDeploymentDescriptor))
-            }
-            Some(file) => {
-                Ok(Command::Ingress(PathBuf::from(file))) // Safe to do, as the file is checked for existence
-            }
+        let mut ingress_specification: IngressSpecification = IngressSpecification::new(extract_descriptor_source(ingress_args)?, extract_retry_policy(ingress_args)?,
+                                                                                         extract_output_format(ingress_args));
+        ingress_specification.ignore_cluster_mismatch = ingress_args.is_present("ignore_cluster_mismatch");
+        return Ok(Command::Ingress(ingress_specification));
+    } else if let Some(wait_args) = args.subcommand_matches("wait") {
+        // All the args are either required or have defaults and are validated, it is therefore safe to unwrap.
+        let descriptor: DescriptorSource = extract_descriptor_source(wait_args)?;
+        let condition: WaitCondition = WaitCondition::from_name(wait_args.value_of("for").unwrap()).unwrap();
+        let timeout: Duration = parse_duration(wait_args.value_of("timeout").unwrap()).unwrap();
+        let mut wait_specification: WaitSpecification = WaitSpecification::new(descriptor, condition, timeout, extract_output_format(wait_args));
+        wait_specification.retry_policy = extract_retry_policy(wait_args)?;
+        return Ok(Command::Wait(wait_specification));
+    } else if let Some(status_args) = args.subcommand_matches("status") {
+        let descriptor: DescriptorSource = extract_descriptor_source(status_args)?;
+        let output: OutputFormat = extract_output_format(status_args);
+        let watch: bool = status_args.is_present("watch");
+        let mut status_specification: StatusSpecification = StatusSpecification::new(descriptor, watch, output);
+        status_specification.retry_policy = extract_retry_policy(status_args)?;
+        return Ok(Command::Status(status_specification));
+    } else if let Some(list_args) = args.subcommand_matches("list") {
+        let namespace: Option<String> = extract_string(list_args, "namespace");
+        let all_namespaces: bool = list_args.is_present("all_namespaces");
+        let kubeconfig_path: Option<PathBuf> = extract_string(list_args, "kubeconfig").map(PathBuf::from);
+        let mut list_specification: ListSpecification = ListSpecification::new(namespace, all_namespaces, kubeconfig_path, extract_output_format(list_args));
+        list_specification.connection = extract_connection(list_args);
+        list_specification.state_dir = extract_state_dir(list_args);
+        return Ok(Command::List(list_specification));
+    } else if let Some(adopt_args) = args.subcommand_matches("adopt") {
+        let name: String = extract_string(adopt_args, "name").unwrap(); // Required argument
+        let namespace: Option<String> = extract_string(adopt_args, "namespace");
+        let kubeconfig_path: Option<PathBuf> = extract_string(adopt_args, "kubeconfig").map(PathBuf::from);
+        let mut adopt_specification: AdoptSpecification = AdoptSpecification::new(name, namespace, kubeconfig_path, extract_output_format(adopt_args));
+        adopt_specification.connection = extract_connection(adopt_args);
+        adopt_specification.retry_policy = extract_retry_policy(adopt_args)?;
+        adopt_specification.state_dir = extract_state_dir(adopt_args);
+        adopt_specification.descriptor_out = extract_string(adopt_args, "descriptor_out").map(PathBuf::from);
+        adopt_specification.store = extract_descriptor_store(adopt_args);
+        return Ok(Command::Adopt(adopt_specification));
+    } else if let Some(export_args) = args.subcommand_matches("export") {
+        // All the args are either required or have defaults and are validated, it is therefore safe to unwrap.
+        let descriptor: DescriptorSource = extract_descriptor_source(export_args)?;
+        let format: ExportFormat = ExportFormat::from_name(export_args.value_of("format").unwrap()).unwrap();
+        let out: Option<PathBuf> = extract_string(export_args, "out").map(PathBuf::from);
+        let mut export_specification: ExportSpecification = ExportSpecification::new(descriptor, format, out, extract_output_format(export_args));
+        export_specification.retry_policy = extract_retry_policy(export_args)?;
+        return Ok(Command::Export(export_specification));
+    } else if let Some(apply_args) = args.subcommand_matches("apply") {
+        let spec_path: PathBuf = PathBuf::from(apply_args.value_of("file").unwrap()); // Required argument
+        let kubeconfig_path: Option<PathBuf> = extract_string(apply_args, "kubeconfig").map(PathBuf::from);
+        let mut apply_specification: ApplySpecification = ApplySpecification::new(spec_path, kubeconfig_path, extract_output_format(apply_args));
+        apply_specification.connection = extract_connection(apply_args);
+        apply_specification.retry_policy = extract_retry_policy(apply_args)?;
+        apply_specification.plan = apply_args.is_present("plan");
+        apply_specification.recreate = apply_args.is_present("recreate");
+        apply_specification.ignore_cluster_mismatch = apply_args.is_present("ignore_cluster_mismatch");
+        apply_specification.state_dir = extract_state_dir(apply_args);
+        apply_specification.descriptor_out = extract_string(apply_args, "descriptor_out").map(PathBuf::from);
+        apply_specification.store = extract_descriptor_store(apply_args);
+        return Ok(Command::Apply(apply_specification));
+    } else if let Some(descriptor_args) = args.subcommand_matches("descriptor") {
+        let action: DescriptorAction = match descriptor_args.subcommand() {
+            ("migrate", Some(migrate_args)) => { DescriptorAction::Migrate(resolve_descriptors(migrate_args, migrate_args.values_of("file").unwrap())?) }
+            ("validate", Some(validate_args)) => { DescriptorAction::Validate(resolve_descriptors(validate_args, validate_args.values_of("file").unwrap())?) }
+            ("schema", Some(_)) => { DescriptorAction::Schema }
+            _ => { return Err(UserInputError::new(UnknownCommand)); }
         };
+        return Ok(Command::Descriptor(DescriptorSpecification::new(action, extract_output_format(descriptor_args))));
+    } else if let Some(protect_args) = args.subcommand_matches("protect") {
+        let mut protect_specification: ProtectSpecification = ProtectSpecification::new(extract_descriptor_source(protect_args)?, true, extract_output_format(protect_args));
+        protect_specification.ignore_cluster_mismatch = protect_args.is_present("ignore_cluster_mismatch");
+        protect_specification.retry_policy = extract_retry_policy(protect_args)?;
+        return Ok(Command::Protect(protect_specification));
+    } else if let Some(unprotect_args) = args.subcommand_matches("unprotect") {
+        let mut protect_specification: ProtectSpecification = ProtectSpecification::new(extract_descriptor_source(unprotect_args)?, false, extract_output_format(unprotect_args));
+        protect_specification.ignore_cluster_mismatch = unprotect_args.is_present("ignore_cluster_mismatch");
+        protect_specification.retry_policy = extract_retry_policy(unprotect_args)?;
+        return Ok(Command::Protect(protect_specification));
     } else {
-        panic!("Unknown command.");
+        return Err(UserInputError::new(UnknownCommand));
+    }
+}
+
+/// Extracts the specification of an H2O deployment from the arguments of the `deploy` or `preflight` command. Arguments not given
+/// on the command line are taken from the preset selected and the configuration files, if set there.
+fn extract_deployment_specification(args: &ArgMatches, config: &Config) -> Result<UserDeploymentSpecification, UserInputError> {
+    let deployment_name: String = extract_string(args, "name").unwrap_or_else(|| {
+        let mut generator: Generator = Generator::default();
+        return format!("h2o-{}", generator.next().unwrap());
+    });
+    let values: Vec<EffectiveValue> = config.resolve(args, args.value_of("preset"))?;
+    let value = |name: &str| values.iter().find(|value| value.name == name).and_then(|value| value.value.clone());
+    let namespace: Option<String> = value("namespace");
+    // Args below have defaults, it is therefore safe to unwrap. The cluster size has none, yet it is required.
+    let cluster_size: u32 = parse_num("cluster_size", &value("cluster_size").ok_or_else(|| UserInputError::new(MissingArgument("cluster_size".to_string())))?)?;
+    let jvm_memory_percentage: u8 = parse_num("memory_percentage", &value("memory_percentage").unwrap())?;
+    let memory: String = value("memory").unwrap();
+    let num_cpus: u32 = parse_num("cpus", &value("cpus").unwrap())?;
+    // The kubeconfig and context of the configuration files do not apply when connecting using the in-cluster configuration.
+    let in_cluster: bool = args.is_present("in_cluster");
+    let kubeconfig_path: Option<PathBuf> = value("kubeconfig").filter(|_| !in_cluster).map(PathBuf::from);
+
+    let mut deployment: UserDeploymentSpecification = UserDeploymentSpecification::new(deployment_name, namespace, jvm_memory_percentage,
+                                                                                       memory, num_cpus, cluster_size, kubeconfig_path);
+    deployment.options = DeployOptions {
+        protect: args.is_present("protect"),
+        create_namespace: args.is_present("create_namespace"),
+        retry: extract_retry_policy(args)?,
+        // Possible values are validated by clap.
+        dry_run: args.value_of("dry_run").and_then(DryRun::from_name),
+    };
+    deployment.output = extract_output_format(args);
+    deployment.connection = extract_connection(args);
+    deployment.connection.context = value("context").filter(|_| !in_cluster);
+    return Ok(deployment);
+}
+
+/// Parses newline-separated paths to deployment descriptors from stdin. If a path is not found, it is also searched for
+/// relatively to the current working directory.
+fn descriptor_paths_from_stdin() -> Result<Vec<PathBuf>, UserInputError> {
+    let mut deployment_path_stdin_buf = String::new();
+    io::stdin().read_to_string(&mut deployment_path_stdin_buf)
+        .map_err(|_| UserInputError::new(MissingDeploymentDescriptor))?;
+    let deployment_paths: Vec<&str> = deployment_path_stdin_buf.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    if deployment_paths.is_empty() {
+        return Err(UserInputError::new(MissingDeploymentDescriptor));
+    }
+
+    let mut deployment_descriptor_paths: Vec<PathBuf> = vec!();
+    for deployment_path in deployment_paths {
+        let deployment_descriptor_path: PathBuf = PathBuf::from(deployment_path);
+        if deployment_descriptor_path.exists() && deployment_descriptor_path.is_file() {
+            deployment_descriptor_paths.push(deployment_descriptor_path);
+        } else {
+            let mut pwd_relative_path: PathBuf = std::env::current_dir()
+                .map_err(|_| UserInputError::new(UnreachableDeploymentDescriptor))?;
+            pwd_relative_path.push(deployment_descriptor_path);
+
+            if pwd_relative_path.exists() && pwd_relative_path.is_file() {
+                deployment_descriptor_paths.push(pwd_relative_path);
+            } else {
+                return Err(UserInputError::new(UnreachableDeploymentDescriptor));
+            }
+        }
+    }
+    return Ok(deployment_descriptor_paths);
+}
+
+/// Extracts the state directory deployment descriptors are stored in. Defaults to the XDG state directory.
+fn extract_state_dir(args: &ArgMatches) -> Option<StateDir> {
+    return match extract_string(args, "state_dir") {
+        Some(state_dir) => { Some(StateDir::new(PathBuf::from(state_dir))) }
+        None => { StateDir::default_location() }
+    };
+}
+
+/// Extracts where the deployment descriptor of a newly made deployment is stored. Defaults to the local store.
+fn extract_descriptor_store(args: &ArgMatches) -> DescriptorStore {
+    return args.value_of("store")
+        .and_then(DescriptorStore::from_name)
+        .unwrap_or(DescriptorStore::Local);
+}
+
+/// Extracts where the deployment descriptor of an existing deployment is read from - the Kubernetes cluster if the deployment
+/// is given by `--name`, otherwise the deployment descriptor file or deployment name given by `--file`.
+fn extract_descriptor_source(args: &ArgMatches) -> Result<DescriptorSource, UserInputError> {
+    if let Some(name) = extract_string(args, "name") {
+        return Ok(DescriptorSource::Cluster {
+            name,
+            namespace: extract_string(args, "namespace"),
+            kubeconfig_path: extract_string(args, "kubeconfig").map(PathBuf::from),
+            connection: extract_connection(args),
+        });
+    }
+    return match args.value_of("file") {
+        Some(file) => { Ok(DescriptorSource::File(resolve_descriptor(args, file)?)) }
+        None => { Err(UserInputError::new(UnreachableDeploymentDescriptor)) }
+    };
+}
+
+/// Resolves a user-provided reference to a single deployment descriptor. An existing file is used as is, any other value
+/// is a name of a deployment whose deployment descriptor is looked up in the state directory.
+fn resolve_descriptor(args: &ArgMatches, reference: &str) -> Result<PathBuf, UserInputError> {
+    let path: &Path = Path::new(reference);
+    if path.is_file() {
+        return Ok(path.to_path_buf());
+    }
+    let mut found: Vec<PathBuf> = find_descriptors(args, reference);
+    return match found.len() {
+        0 => { Err(UserInputError::new(UnknownDeployment(reference.to_string()))) }
+        1 => { Ok(found.remove(0)) }
+        _ => { Err(UserInputError::new(AmbiguousDeployment { name: reference.to_string(), paths: found })) }
+    };
+}
+
+/// Resolves user-provided references to deployment descriptors - paths, glob patterns or names of deployments. All the deployment
+/// descriptors of a deployment name found in the state directory are used, e.g. in different namespaces.
+fn resolve_descriptors<'a>(args: &ArgMatches, references: impl Iterator<Item=&'a str>) -> Result<Vec<PathBuf>, UserInputError> {
+    let mut paths: Vec<PathBuf> = vec!();
+    for reference in references {
+        let expanded: Vec<PathBuf> = expand_path(reference);
+        if !expanded.is_empty() {
+            paths.extend(expanded);
+            continue;
+        }
+        let found: Vec<PathBuf> = find_descriptors(args, reference);
+        if found.is_empty() {
+            return Err(UserInputError::new(UnknownDeployment(reference.to_string())));
+        }
+        paths.extend(found);
+    }
+    return Ok(paths);
+}
+
+/// Finds deployment descriptors of the deployments with the given name in the state directory, only in the namespace given, if any.
+fn find_descriptors(args: &ArgMatches, name: &str) -> Vec<PathBuf> {
+    if !state::is_deployment_name(name) {
+        return vec!();
     }
+    return match extract_state_dir(args) {
+        Some(state_dir) => { state_dir.find(name, args.value_of("namespace")) }
+        None => { vec!() }
+    };
+}
+
+/// Expands a user-provided path, which might be a glob pattern, into paths of existing files.
+/// A path pointing to an existing file is returned as-is, even if it contains glob special characters.
+fn expand_path(user_provided_path: &str) -> Vec<PathBuf> {
+    let path: &Path = Path::new(user_provided_path);
+    if path.is_file() {
+        return vec!(path.to_path_buf());
+    }
+    return match glob::glob(user_provided_path) {
+        Ok(paths) => {
+            paths.filter_map(Result::ok)
+                .filter(|path| path.is_file())
+                .collect()
+        }
+        Err(_) => { vec!() }
+    };
 }
 
 /// Commands issuable by the user.
 pub enum Command {
     Deployment(UserDeploymentSpecification),
-    Undeploy(PathBuf),
-    Ingress(PathBuf),
+    Preflight(UserDeploymentSpecification),
+    Undeploy(UndeploySpecification),
+    Ingress(IngressSpecification),
+    Wait(WaitSpecification),
+    Status(StatusSpecification),
+    List(ListSpecification),
+    Adopt(AdoptSpecification),
+    Protect(ProtectSpecification),
+    Export(ExportSpecification),
+    Apply(ApplySpecification),
+    Config(ConfigSpecification),
+    Descriptor(DescriptorSpecification),
 }
 
 pub struct UserDeploymentSpecification {
@@ -100,14 +360,369 @@ pub struct UserDeploymentSpecification {
     pub num_h2o_nodes: u32,
     /// Kubeconfig - provided optionally. There are well-known standardized locations to look for Kubeconfig, therefore optional.
     pub kubeconfig_path: Option<PathBuf>,
+    pub options: DeployOptions,
+    /// Format of the deployment document printed.
+    pub output: OutputFormat,
+    /// Kubeconfig context, cluster, user and impersonation to use. Defaults to the kubeconfig's current context.
+    pub connection: ClusterConnection,
+    /// If true, pre-flight checks are run before deploying and the deployment is refused if any of them fails.
+    pub preflight: bool,
+    /// Directory deployment descriptors are stored in. None if there is none, descriptors are then written to the current working directory.
+    pub state_dir: Option<StateDir>,
+    /// Path to write the deployment descriptor to instead of the state directory.
+    pub descriptor_out: Option<PathBuf>,
+    /// Where the deployment descriptor is stored.
+    pub store: DescriptorStore,
 }
 
 impl UserDeploymentSpecification {
     pub fn new(name: String, namespace: Option<String>, memory_percentage: u8, memory: String, num_cpu: u32, num_h2o_nodes: u32, kubeconfig_path: Option<PathBuf>) -> Self {
-        UserDeploymentSpecification { name, namespace, memory_percentage, memory, num_cpu, num_h2o_nodes, kubeconfig_path }
+        UserDeploymentSpecification {
+            name,
+            namespace,
+            memory_percentage,
+            memory,
+            num_cpu,
+            num_h2o_nodes,
+            kubeconfig_path,
+            options: DeployOptions::default(),
+            output: OutputFormat::Text,
+            connection: ClusterConnection::default(),
+            preflight: true,
+            state_dir: None,
+            descriptor_out: None,
+            store: DescriptorStore::Local,
+        }
+    }
+}
+
+/// Where the deployment descriptors of newly made deployments are stored.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DescriptorStore {
+    /// A file in the state directory, or in the current working directory if there is no state directory.
+    Local,
+    /// A ConfigMap in the namespace of the deployment, owned by the H2O stateful set, so anyone with access to the namespace
+    /// can work with the deployment.
+    Cluster,
+}
+
+impl DescriptorStore {
+    /// Parses a descriptor store from its user-facing name. Returns None for unknown names.
+    pub fn from_name(name: &str) -> Option<Self> {
+        return match name {
+            "local" => Some(DescriptorStore::Local),
+            "cluster" => Some(DescriptorStore::Cluster),
+            _ => None
+        };
+    }
+}
+
+/// Where the deployment descriptor of an existing deployment is read from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DescriptorSource {
+    /// A deployment descriptor file. The kubeconfig and connection recorded in it are used to connect to the Kubernetes cluster.
+    File(PathBuf),
+    /// The ConfigMap of the deployment with the given name, stored by `--store cluster`. The Kubernetes cluster is connected to
+    /// using the kubeconfig and connection given by the user, the namespace defaults to the kubeconfig default.
+    Cluster { name: String, namespace: Option<String>, kubeconfig_path: Option<PathBuf>, connection: ClusterConnection },
+}
+
+/// Creation of an ingress for an existing H2O deployment.
+pub struct IngressSpecification {
+    /// Deployment descriptor of the deployment to expose.
+    pub descriptor: DescriptorSource,
+    pub retry_policy: RetryPolicy,
+    /// Format of the deployment document printed.
+    pub output: OutputFormat,
+    /// If true, the ingress is created even if the Kubernetes cluster does not match the one recorded in the deployment descriptor.
+    pub ignore_cluster_mismatch: bool,
+}
+
+impl IngressSpecification {
+    pub fn new(descriptor: DescriptorSource, retry_policy: RetryPolicy, output: OutputFormat) -> Self {
+        IngressSpecification { descriptor, retry_policy, output, ignore_cluster_mismatch: false }
+    }
+}
+
+/// H2O deployments to undeploy.
+pub enum UndeployTarget {
+    /// H2O deployments described by the deployment descriptor files.
+    Descriptors(Vec<PathBuf>),
+    /// A single H2O deployment with the given name, found in the Kubernetes cluster.
+    Name(String),
+    /// All H2O deployments whose stateful sets match the given label selector.
+    Selector(String),
+    /// All H2O deployments in the namespace.
+    All,
+}
+
+/// Undeployment of existing H2O deployments.
+pub struct UndeploySpecification {
+    pub target: UndeployTarget,
+    /// Namespace to search for H2O deployments in when not undeploying by a deployment descriptor.
+    /// If not provided, kubeconfig default is used.
+    pub namespace: Option<String>,
+    /// Kubeconfig used when not undeploying by a deployment descriptor.
+    pub kubeconfig_path: Option<PathBuf>,
+    /// If true, H2O deployments found in the Kubernetes cluster are undeployed without confirmation.
+    pub assume_yes: bool,
+    pub options: UndeployOptions,
+    /// If defined, undeployment waits up to the given time until all H2O pods are gone.
+    pub wait_timeout: Option<Duration>,
+    /// Format of the undeployment report printed.
+    pub output: OutputFormat,
+    /// Kubeconfig context, cluster, user and impersonation used when not undeploying by a deployment descriptor.
+    pub connection: ClusterConnection,
+    /// Directory the deployment descriptors of the deployments undeployed are removed from.
+    pub state_dir: Option<StateDir>,
+    /// If true, deployments are undeployed even if the Kubernetes cluster does not match the one recorded in their deployment descriptors.
+    pub ignore_cluster_mismatch: bool,
+}
+
+impl UndeploySpecification {
+    pub fn new(target: UndeployTarget, namespace: Option<String>, kubeconfig_path: Option<PathBuf>, assume_yes: bool,
+               options: UndeployOptions, wait_timeout: Option<Duration>, output: OutputFormat) -> Self {
+        UndeploySpecification { target, namespace, kubeconfig_path, assume_yes, options, wait_timeout, output, connection: ClusterConnection::default(),
+            state_dir: None, ignore_cluster_mismatch: false }
+    }
+}
+
+/// Waiting for an existing deployment to reach a certain condition.
+pub struct WaitSpecification {
+    /// Deployment descriptor of the deployment to wait for.
+    pub descriptor: DescriptorSource,
+    /// Condition to wait for.
+    pub condition: WaitCondition,
+    /// Maximum amount of time to wait for the condition to be met.
+    pub timeout: Duration,
+    /// Format of the outcome printed once the condition is met.
+    pub output: OutputFormat,
+    pub retry_policy: RetryPolicy,
+}
+
+impl WaitSpecification {
+    pub fn new(descriptor: DescriptorSource, condition: WaitCondition, timeout: Duration, output: OutputFormat) -> Self {
+        WaitSpecification { descriptor, condition, timeout, output, retry_policy: RetryPolicy::default() }
+    }
+}
+
+/// Format of the command output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    /// Human-readable text.
+    Text,
+    /// JSON document.
+    Json,
+    /// YAML document.
+    Yaml,
+}
+
+impl OutputFormat {
+    /// Parses an output format from its user-facing name. Returns None for unknown names.
+    pub fn from_name(name: &str) -> Option<Self> {
+        return match name {
+            "text" => Some(OutputFormat::Text),
+            "json" => Some(OutputFormat::Json),
+            "yaml" => Some(OutputFormat::Yaml),
+            _ => None
+        };
     }
 }
 
+/// Displaying status of an existing deployment.
+pub struct StatusSpecification {
+    /// Deployment descriptor of the deployment to display status of.
+    pub descriptor: DescriptorSource,
+    /// If true, the status is displayed repeatedly as it changes.
+    pub watch: bool,
+    /// Format of the status printed.
+    pub output: OutputFormat,
+    pub retry_policy: RetryPolicy,
+}
+
+impl StatusSpecification {
+    pub fn new(descriptor: DescriptorSource, watch: bool, output: OutputFormat) -> Self {
+        StatusSpecification { descriptor, watch, output, retry_policy: RetryPolicy::default() }
+    }
+}
+
+/// Listing H2O deployments found in the Kubernetes cluster.
+pub struct ListSpecification {
+    /// Namespace to search in - if not provided, kubeconfig default is used.
+    pub namespace: Option<String>,
+    /// If true, all namespaces are searched.
+    pub all_namespaces: bool,
+    /// Kubeconfig - provided optionally. There are well-known standardized locations to look for Kubeconfig, therefore optional.
+    pub kubeconfig_path: Option<PathBuf>,
+    /// Format of the list printed.
+    pub output: OutputFormat,
+    /// Kubeconfig context, cluster, user and impersonation to use. Defaults to the kubeconfig's current context.
+    pub connection: ClusterConnection,
+    /// Directory the deployment descriptors of the deployments listed are looked for in.
+    pub state_dir: Option<StateDir>,
+}
+
+impl ListSpecification {
+    pub fn new(namespace: Option<String>, all_namespaces: bool, kubeconfig_path: Option<PathBuf>, output: OutputFormat) -> Self {
+        ListSpecification { namespace, all_namespaces, kubeconfig_path, output, connection: ClusterConnection::default(), state_dir: None }
+    }
+}
+
+/// Reconstructing a deployment descriptor of an existing H2O deployment from the Kubernetes cluster.
+pub struct AdoptSpecification {
+    /// Name of the H2O deployment to adopt.
+    pub name: String,
+    /// Namespace the H2O deployment lives in - if not provided, kubeconfig default is used.
+    pub namespace: Option<String>,
+    /// Kubeconfig - provided optionally. There are well-known standardized locations to look for Kubeconfig, therefore optional.
+    pub kubeconfig_path: Option<PathBuf>,
+    /// Format of the deployment document printed.
+    pub output: OutputFormat,
+    /// Kubeconfig context, cluster, user and impersonation to use. Defaults to the kubeconfig's current context.
+    pub connection: ClusterConnection,
+    pub retry_policy: RetryPolicy,
+    /// Directory deployment descriptors are stored in. None if there is none, descriptors are then written to the current working directory.
+    pub state_dir: Option<StateDir>,
+    /// Path to write the deployment descriptor to instead of the state directory.
+    pub descriptor_out: Option<PathBuf>,
+    /// Where the deployment descriptor is stored.
+    pub store: DescriptorStore,
+}
+
+impl AdoptSpecification {
+    pub fn new(name: String, namespace: Option<String>, kubeconfig_path: Option<PathBuf>, output: OutputFormat) -> Self {
+        AdoptSpecification {
+            name,
+            namespace,
+            kubeconfig_path,
+            output,
+            connection: ClusterConnection::default(),
+            retry_policy: RetryPolicy::default(),
+            state_dir: None,
+            descriptor_out: None,
+            store: DescriptorStore::Local,
+        }
+    }
+}
+
+/// Enabling or disabling deletion protection of an existing H2O deployment.
+pub struct ProtectSpecification {
+    /// Deployment descriptor of the deployment to (un)protect.
+    pub descriptor: DescriptorSource,
+    /// True to protect the deployment against deletion, false to remove the protection.
+    pub protected: bool,
+    /// Format of the outcome printed.
+    pub output: OutputFormat,
+    /// If true, the protection is changed even if the Kubernetes cluster does not match the one recorded in the deployment descriptor.
+    pub ignore_cluster_mismatch: bool,
+    pub retry_policy: RetryPolicy,
+}
+
+impl ProtectSpecification {
+    pub fn new(descriptor: DescriptorSource, protected: bool, output: OutputFormat) -> Self {
+        ProtectSpecification { descriptor, protected, output, ignore_cluster_mismatch: false, retry_policy: RetryPolicy::default() }
+    }
+}
+
+/// Exporting an existing H2O deployment for GitOps tools, e.g. Argo CD or Flux.
+pub struct ExportSpecification {
+    /// Deployment descriptor of the deployment to export.
+    pub descriptor: DescriptorSource,
+    pub format: ExportFormat,
+    /// Directory to write the exported files to. If not provided, the exported YAML is printed to stdout.
+    pub out: Option<PathBuf>,
+    /// Format of the outcome printed.
+    pub output: OutputFormat,
+    /// Used only if the deployment descriptor is stored in the Kubernetes cluster.
+    pub retry_policy: RetryPolicy,
+}
+
+impl ExportSpecification {
+    pub fn new(descriptor: DescriptorSource, format: ExportFormat, out: Option<PathBuf>, output: OutputFormat) -> Self {
+        ExportSpecification { descriptor, format, out, output, retry_policy: RetryPolicy::default() }
+    }
+}
+
+/// Bringing an H2O deployment to the state specified by a cluster spec file.
+pub struct ApplySpecification {
+    /// Path to the cluster spec file.
+    pub spec_path: PathBuf,
+    /// Kubeconfig - provided optionally. There are well-known standardized locations to look for Kubeconfig, therefore optional.
+    pub kubeconfig_path: Option<PathBuf>,
+    /// Kubeconfig context, cluster, user and impersonation to use. Defaults to the kubeconfig's current context.
+    pub connection: ClusterConnection,
+    pub retry_policy: RetryPolicy,
+    /// If true, the changes required are only reported, not made.
+    pub plan: bool,
+    /// If true, the H2O cluster is recreated if the changes required can not be made in place.
+    pub recreate: bool,
+    /// If true, the changes are made even if the Kubernetes cluster does not match the one recorded in the deployment descriptor.
+    pub ignore_cluster_mismatch: bool,
+    /// Format of the outcome printed.
+    pub output: OutputFormat,
+    /// Directory deployment descriptors are stored in. None if there is none, descriptors are then written to the current working directory.
+    pub state_dir: Option<StateDir>,
+    /// Path to write the deployment descriptor to instead of the state directory.
+    pub descriptor_out: Option<PathBuf>,
+    /// Where the deployment descriptor is stored.
+    pub store: DescriptorStore,
+}
+
+impl ApplySpecification {
+    pub fn new(spec_path: PathBuf, kubeconfig_path: Option<PathBuf>, output: OutputFormat) -> Self {
+        ApplySpecification {
+            spec_path,
+            kubeconfig_path,
+            connection: ClusterConnection::default(),
+            retry_policy: RetryPolicy::default(),
+            plan: false,
+            recreate: false,
+            ignore_cluster_mismatch: false,
+            output,
+            state_dir: None,
+            descriptor_out: None,
+            store: DescriptorStore::Local,
+        }
+    }
+}
+
+/// Displaying the effective configuration of the `deploy` and `preflight` commands.
+pub struct ConfigSpecification {
+    /// Configuration files loaded.
+    pub config: Config,
+    /// Effective values of the configurable arguments, each with its source.
+    pub values: Vec<EffectiveValue>,
+    /// Format of the configuration printed.
+    pub output: OutputFormat,
+}
+
+impl ConfigSpecification {
+    pub fn new(config: Config, values: Vec<EffectiveValue>, output: OutputFormat) -> Self {
+        ConfigSpecification { config, values, output }
+    }
+}
+
+/// Working with deployment descriptor files themselves, regardless of the deployments they describe.
+pub struct DescriptorSpecification {
+    pub action: DescriptorAction,
+    /// Format of the outcome printed.
+    pub output: OutputFormat,
+}
+
+impl DescriptorSpecification {
+    pub fn new(action: DescriptorAction, output: OutputFormat) -> Self {
+        DescriptorSpecification { action, output }
+    }
+}
+
+pub enum DescriptorAction {
+    /// Rewrite the deployment descriptors given in the current version of the descriptor format.
+    Migrate(Vec<PathBuf>),
+    /// Check the deployment descriptors given can be read by this version of h2ok.
+    Validate(Vec<PathBuf>),
+    /// Print the JSON Schema of the descriptor format.
+    Schema,
+}
 
 /// Error while processing user input.
 #[derive(Debug)]
@@ -119,31 +734,90 @@ impl UserInputError {
     pub fn new(kind: CommandErrorKind) -> Self {
         UserInputError { kind }
     }
+
+    /// Suggestion on how to correct the user input.
+    pub fn hint(&self) -> &str {
+        return match &self.kind {
+            MissingDeploymentDescriptor => { "Use the '--file' option or pass deployment descriptor paths on stdin, e.g. 'ls *.h2ok | h2ok undeploy'." }
+            UnreachableDeploymentDescriptor => { "Check the deployment descriptor path. If the deployment descriptor has been lost, use 'h2ok adopt' to reconstruct it." }
+            InvalidArgument { .. } => { "Use the '--help' flag to display the arguments accepted." }
+            UnknownCommand => { "Use 'h2ok --help' to display the commands available." }
+            ConfirmationRequired => { "Use the '--yes' flag to proceed without confirmation." }
+            MissingArgument(_) => { "Use the '--help' flag to display the arguments accepted. The value may also be set in a configuration file or a preset." }
+            InvalidConfig { .. } => {
+                "Fix the configuration file. Values are set in the '[defaults]' table or a '[presets.<name>]' table, \
+                the keys are the names of the 'deploy' arguments, e.g. 'memory' or 'cluster_size'."
+            }
+            UnknownPreset(_) => { "Use 'h2ok config show' to display the presets defined." }
+            UnknownDeployment(_) => {
+                "Check the deployment descriptor path or the name of the deployment. Use '--state-dir' if the deployment descriptor is stored elsewhere, \
+                or 'h2ok adopt' to reconstruct it."
+            }
+            AmbiguousDeployment { .. } => { "Use the path to one of the deployment descriptors instead." }
+        };
+    }
+}
+
+impl Display for UserInputError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        return match &self.kind {
+            MissingDeploymentDescriptor => { write!(f, "No deployment descriptor given.") }
+            UnreachableDeploymentDescriptor => { write!(f, "Deployment descriptor not found.") }
+            InvalidArgument { name, value } => { write!(f, "Invalid value '{}' of argument '{}'.", value, name) }
+            UnknownCommand => { write!(f, "No command given.") }
+            ConfirmationRequired => { write!(f, "Unable to ask for confirmation, as the input is not a terminal.") }
+            MissingArgument(name) => { write!(f, "No value of argument '{}' given.", name) }
+            InvalidConfig { path, reason } => { write!(f, "Invalid configuration file '{}'. {}", path.display(), reason) }
+            UnknownPreset(name) => { write!(f, "Preset '{}' is not defined in any configuration file.", name) }
+            UnknownDeployment(name) => { write!(f, "No deployment descriptor file or deployment named '{}' found.", name) }
+            AmbiguousDeployment { name, paths } => {
+                let paths: Vec<String> = paths.iter().map(|path| path.to_string_lossy().to_string()).collect();
+                write!(f, "There are multiple deployments named '{}': {}", name, paths.join(", "))
+            }
+        };
+    }
 }
 
 #[derive(Debug)]
 pub enum CommandErrorKind {
     MissingDeploymentDescriptor,
     UnreachableDeploymentDescriptor,
+    /// An argument value could not be parsed.
+    InvalidArgument { name: String, value: String },
+    UnknownCommand,
+    /// A confirmation of the user is required, yet there is no terminal to ask on.
+    ConfirmationRequired,
+    /// A required argument has been given neither on the command line nor by a configuration file.
+    MissingArgument(String),
+    /// A configuration file could not be read or parsed.
+    InvalidConfig { path: PathBuf, reason: String },
+    /// The preset selected is not defined by any configuration file.
+    UnknownPreset(String),
+    /// The value given is neither a path to a deployment descriptor nor a name of a deployment found in the state directory.
+    UnknownDeployment(String),
+    /// Deployments of the name given are found in multiple namespaces or Kubernetes clusters.
+    AmbiguousDeployment { name: String, paths: Vec<PathBuf> },
 }
 
 /// Attempts to extract/parse a number from user-given argument. If the user did not provide
-/// any value or the value has not default, returns Option::None. Panics if the argument can not be parsed.
-fn extract_num<T: Num + FromStr>(args: &ArgMatches, arg_name: &str) -> Option<T> {
+/// any value or the value has not default, returns Option::None. Returns an error if the argument can not be parsed.
+fn extract_num<T: Num + FromStr>(args: &ArgMatches, arg_name: &str) -> Result<Option<T>, UserInputError> {
     return match args.value_of(arg_name) {
         None => {
-            Option::None
+            Ok(Option::None)
         }
         Some(value) => {
-            if let Ok(result) = value.parse::<T>() {
-                Option::Some(result)
-            } else {
-                panic!("Unable to parse argument '{}'. Given value: '{}'", arg_name, value)
-            }
+            parse_num(arg_name, value).map(Option::Some)
         }
     };
 }
 
+/// Parses a number from the value of the given argument. Returns an error if the value can not be parsed.
+fn parse_num<T: Num + FromStr>(arg_name: &str, value: &str) -> Result<T, UserInputError> {
+    return value.parse::<T>()
+        .map_err(|_| UserInputError::new(InvalidArgument { name: arg_name.to_string(), value: value.to_string() }));
+}
+
 /// Attempts to extract/parse a string from user-given argument. If the user did not provide
 /// any value or the value has not default, returns Option::None. Panics if the argument can not be parsed.
 fn extract_string(args: &ArgMatches, arg_name: &str) -> Option<String> {
@@ -157,78 +831,531 @@ fn extract_string(args: &ArgMatches, arg_name: &str) -> Option<String> {
     };
 }
 
+/// Extracts the policy of retrying failed Kubernetes API requests from the global arguments.
+fn extract_retry_policy(args: &ArgMatches) -> Result<RetryPolicy, UserInputError> {
+    return Ok(RetryPolicy {
+        retries: extract_num(args, "retries")?.unwrap_or(RetryPolicy::default().retries),
+        request_timeout: args.value_of("request_timeout").and_then(parse_duration),
+        verbose: args.is_present("verbose"),
+    });
+}
+
+/// Extracts the kubeconfig context, cluster, user and impersonation to connect to the Kubernetes cluster with.
+fn extract_connection(args: &ArgMatches) -> ClusterConnection {
+    return ClusterConnection {
+        context: extract_string(args, "context"),
+        cluster: extract_string(args, "cluster"),
+        user: extract_string(args, "user"),
+        impersonate_user: extract_string(args, "as"),
+        impersonate_groups: args.values_of("as_group")
+            .map(|groups| groups.map(String::from).collect())
+            .unwrap_or_default(),
+        in_cluster: args.is_present("in_cluster"),
+    };
+}
+
+/// Arguments describing an H2O deployment, accepted by the `deploy` and `preflight` commands.
+fn deployment_args<'a>() -> Vec<Arg<'a, 'a>> {
+    let mut args: Vec<Arg<'a, 'a>> = vec!(
+        Arg::with_name("cluster_size")
+            .long("cluster_size")
+            .short("s")
+            .help("Number of H2O Nodes in the cluster. Up to 2^32. Required, unless set by a configuration file or the preset.")
+            .number_of_values(1)
+            .validator(self::validate_int_greater_than_zero),
+        Arg::with_name("kubeconfig")
+            .long("kubeconfig")
+            .short("k")
+            .number_of_values(1)
+            .validator(self::validate_path)
+            .help("Path to 'kubeconfig' yaml file. If not specified, well-known locations are scanned for kubeconfig."),
+        Arg::with_name("namespace")
+            .long("namespace")
+            .short("n")
+            .help("Kubernetes cluster namespace to deploy to. If not specified, kubeconfig default is used. The namespace must exist, \
+            unless '--create-namespace' is used.")
+            .number_of_values(1),
+        Arg::with_name("create_namespace")
+            .long("create-namespace")
+            .takes_value(false)
+            .help("Create the namespace if it does not exist. Such a namespace is deleted by 'undeploy --purge'."),
+        Arg::with_name("name")
+            .long("cluster_name")
+            .short("c")
+            .help("Name of the H2O cluster deployment. Used as prefix for K8S entities. Generated if not specified.")
+            .number_of_values(1),
+        Arg::with_name("memory_percentage")
+            .long("memory_percentage")
+            .short("p")
+            .default_value("50")
+            .help("Memory percentage allocated by H2O inside the container. <0,100>. Defaults to 50% to make space for XGBoost.")
+            .validator(self::validate_percentage),
+        Arg::with_name("memory")
+            .long("memory")
+            .short("m")
+            .number_of_values(1)
+            .default_value("1Gi")
+            .help("Amount of memory allocated by each H2O node - in a format accepted by K8S, e.g. 4Gi.")
+            .validator(self::validate_memory),
+        Arg::with_name("cpus")
+            .long("cpus")
+            .number_of_values(1)
+            .default_value("1")
+            .help("Number of CPUs allocated for each H2O node.")
+            .validator(self::validate_int_greater_than_zero),
+        Arg::with_name("preset")
+            .long("preset")
+            .number_of_values(1)
+            .help("Name of a preset defined in a configuration file, e.g. 'xgboost'. Values of the preset take precedence over the defaults \
+            of the configuration files, values given on the command line take precedence over the preset.")
+    );
+    args.extend(connection_args());
+    return args;
+}
+
+/// Arguments selecting the Kubernetes cluster, credentials and identity to connect with. Accepted by all the commands
+/// not working with a deployment descriptor file, as the deployment descriptor file records the ones used to deploy.
+fn connection_args<'a>() -> Vec<Arg<'a, 'a>> {
+    return vec!(
+        Arg::with_name("context")
+            .long("context")
+            .number_of_values(1)
+            .help("Name of the kubeconfig context to use. If not specified, the current context is used."),
+        Arg::with_name("cluster")
+            .long("cluster")
+            .number_of_values(1)
+            .help("Name of the kubeconfig cluster to use instead of the one of the context."),
+        Arg::with_name("user")
+            .long("user")
+            .number_of_values(1)
+            .help("Name of the kubeconfig user to use instead of the one of the context."),
+        Arg::with_name("as")
+            .long("as")
+            .number_of_values(1)
+            .help("User to impersonate, e.g. 'system:serviceaccount:h2o:deployer'."),
+        Arg::with_name("as_group")
+            .long("as-group")
+            .multiple(true)
+            .number_of_values(1)
+            .requires("as")
+            .help("Group to impersonate, requires '--as'. May be specified multiple times."),
+        Arg::with_name("in_cluster")
+            .long("in-cluster")
+            .takes_value(false)
+            .conflicts_with_all(&["kubeconfig", "context", "cluster", "user"])
+            .help("Use the service account of the pod h2ok runs in instead of a kubeconfig."),
+    );
+}
+
+/// Argument overriding the path the deployment descriptor is written to by `deploy`, `adopt` and `apply`.
+fn descriptor_out_arg<'a>() -> Arg<'a, 'a> {
+    return Arg::with_name("descriptor_out")
+        .long("descriptor-out")
+        .number_of_values(1)
+        .help("Path to write the deployment descriptor to, overwriting an existing file. If not specified, the deployment descriptor \
+        is written to the state directory.");
+}
+
+/// Flag overriding the verification of the Kubernetes cluster connected to against the one recorded in the deployment descriptor.
+/// The `help` describes what the command does despite a mismatch.
+fn ignore_cluster_mismatch_arg<'a>(help: &'a str) -> Arg<'a, 'a> {
+    return Arg::with_name("ignore_cluster_mismatch")
+        .long("ignore-cluster-mismatch")
+        .takes_value(false)
+        .help(help);
+}
+
+/// Argument choosing where the deployment descriptor is stored by `deploy`, `adopt` and `apply`.
+fn store_arg<'a>() -> Arg<'a, 'a> {
+    return Arg::with_name("store")
+        .long("store")
+        .number_of_values(1)
+        .possible_values(&["local", "cluster"])
+        .default_value("local")
+        .help("Where to store the deployment descriptor. 'local' - a file in the state directory. 'cluster' - a ConfigMap in the namespace \
+        of the deployment, garbage-collected along with the H2O stateful set. Anyone with access to the namespace can then work with \
+        the deployment using '--name' instead of '--file'. With '--descriptor-out', the deployment descriptor is written to the file as well.");
+}
+
+/// Arguments selecting an existing H2O deployment, accepted by the commands working with a single deployment. The deployment
+/// descriptor is either a file given by `--file`, or stored in the Kubernetes cluster and found by `--name`.
+fn existing_deployment_args<'a>() -> Vec<Arg<'a, 'a>> {
+    let mut args: Vec<Arg<'a, 'a>> = vec!(
+        Arg::with_name("file")
+            .long("file")
+            .short("f")
+            .number_of_values(1)
+            .required_unless("name")
+            .conflicts_with("name")
+            .help("H2O deployment descriptor file path, or name of the H2O deployment to look the deployment descriptor up in the state directory.")
+            .validator(self::validate_path_or_name),
+        Arg::with_name("name")
+            .long("name")
+            .number_of_values(1)
+            .help("Name of an H2O deployment whose deployment descriptor is stored in the Kubernetes cluster, see '--store cluster' of 'deploy'. \
+            The Kubernetes cluster is connected to using the kubeconfig and connection options given."),
+        Arg::with_name("namespace")
+            .long("namespace")
+            .short("n")
+            .number_of_values(1)
+            .help("Kubernetes cluster namespace the H2O deployment lives in. If not specified, kubeconfig default is used with '--name' \
+            and all namespaces are searched when looking the deployment descriptor up in the state directory."),
+        Arg::with_name("kubeconfig")
+            .long("kubeconfig")
+            .short("k")
+            .number_of_values(1)
+            .requires("name")
+            .validator(self::validate_path)
+            .help("Path to 'kubeconfig' yaml file used with '--name'. If not specified, well-known locations are scanned for kubeconfig."),
+    );
+    args.extend(connection_args());
+    return args;
+}
+
+/// Deployment descriptor files argument of the `descriptor` subcommands.
+fn descriptor_files_arg<'a>() -> Arg<'a, 'a> {
+    return Arg::with_name("file")
+        .long("file")
+        .short("f")
+        .required(true)
+        .multiple(true)
+        .number_of_values(1)
+        .help("H2O deployment descriptor file path, a glob pattern, e.g. '*.h2ok', or name of an H2O deployment \
+        to look the deployment descriptors up in the state directory. May be specified multiple times.")
+        .validator(self::validate_path_glob_or_name);
+}
+
+/// Extracts format of the command output from the global arguments. Defaults to text.
+fn extract_output_format(args: &ArgMatches) -> OutputFormat {
+    return args.value_of("output")
+        .and_then(OutputFormat::from_name)
+        .unwrap_or(OutputFormat::Text);
+}
+
 /// Contains definition of all commands, arguments, flags and the respective default values and descriptions
 /// This is the only source of truth for user-facing CLI.
 fn build_app<'a>() -> App<'a, 'a> {
     return App::new(APP_NAME)
         .version(APP_VERSION)
         .setting(AppSettings::ArgRequiredElseHelp)
+        .arg(Arg::with_name("verbose")
+            .long("verbose")
+            .short("v")
+            .global(true)
+            .takes_value(false)
+            .help("Report details of the progress, e.g. retries of failed Kubernetes API requests.")
+        )
+        .arg(Arg::with_name("retries")
+            .long("retries")
+            .global(true)
+            .number_of_values(1)
+            .help("Maximum number of retries of a Kubernetes API request failed due to a transient error, \
+            e.g. the API server being throttled, unavailable or a connection reset. Zero disables retries. Defaults to 3.")
+            .validator(self::validate_non_negative_int)
+        )
+        .arg(Arg::with_name("request_timeout")
+            .long("request-timeout")
+            .global(true)
+            .number_of_values(1)
+            .help("Maximum duration of a single Kubernetes API request attempt, e.g. 30s or 1m. Timed out requests are retried. Not limited by default.")
+            .validator(self::validate_duration)
+        )
+        .arg(Arg::with_name("state_dir")
+            .long("state-dir")
+            .global(true)
+            .number_of_values(1)
+            .help("Directory deployment descriptors are stored in, as '<context>/<namespace>/<name>.json'. \
+            Defaults to '$XDG_STATE_HOME/h2ok', or '~/.local/state/h2ok' if 'XDG_STATE_HOME' is not set.")
+        )
+        .arg(Arg::with_name("output")
+            .long("output")
+            .short("o")
+            .global(true)
+            .number_of_values(1)
+            .possible_values(&["text", "json", "yaml"])
+            .help("Output format. 'json' and 'yaml' print a single document describing the outcome of the command to stdout, \
+            e.g. the deployment descriptor path, names of the Kubernetes entities and endpoints. Diagnostics are printed to stderr. Defaults to text.")
+        )
         .subcommand(SubCommand::with_name("deploy")
             .about("Deploys an H2O cluster into Kubernetes. Once successfully deployed a deployment descriptor file with cluster name is saved.\
              Such a file can be used to undeploy the cluster or built on top of by adding additional services.")
-            .arg(Arg::with_name("cluster_size")
-                .required(true)
-                .long("cluster_size")
-                .short("s")
-                .help("Number of H2O Nodes in the cluster. Up to 2^32.")
+            .args(&deployment_args())
+            .arg(Arg::with_name("protect")
+                .long("protect")
+                .takes_value(false)
+                .help("Protect the H2O deployment against deletion. Undeployment is then refused unless '--force' is used.")
+            )
+            .arg(Arg::with_name("dry_run")
+                .long("dry-run")
+                .number_of_values(1)
+                .possible_values(&["client", "server"])
+                .help("Do not persist anything and write no deployment descriptor. 'client' prints the Kubernetes entities to be created \
+                as a multi-document YAML without contacting the Kubernetes cluster. 'server' submits them with 'dryRun=All', so they are \
+                validated by admission webhooks and quotas, and prints the entities returned by the API server.")
+            )
+            .arg(Arg::with_name("skip_preflight")
+                .long("skip-preflight")
+                .takes_value(false)
+                .help("Deploy without running the pre-flight checks first.")
+            )
+            .arg(descriptor_out_arg())
+            .arg(store_arg())
+        )
+        .subcommand(SubCommand::with_name("preflight")
+            .about("Checks whether an H2O cluster can be deployed with the given options without deploying it: the namespace, permissions, \
+            ResourceQuotas, LimitRanges and allocatable capacity of the nodes. The same checks are run by 'deploy' unless '--skip-preflight' is used.")
+            .args(&deployment_args())
+        )
+        .subcommand(SubCommand::with_name("undeploy")
+            .about("Undeploys an existing H2O cluster from Kubernetes")
+            .arg(Arg::with_name("file")
+                .long("file")
+                .short("f")
+                .multiple(true)
+                .number_of_values(1)
+                .conflicts_with_all(&["name", "selector", "all"])
+                .help("H2O deployment descriptor file path, a glob pattern, e.g. 'h2o-*.h2ok', or name of an H2O deployment \
+                to look the deployment descriptors up in the state directory. May be specified multiple times, \
+                the deployments are then undeployed concurrently. If neither a file, a name, a selector nor '--all' is specified, \
+                attempt is made to parse newline-separated deployment descriptor paths from stdin.")
+                .validator(self::validate_path_glob_or_name)
+            )
+            .arg(Arg::with_name("name")
+                .long("name")
+                .number_of_values(1)
+                .conflicts_with_all(&["selector", "all"])
+                .help("Name of the H2O deployment to undeploy. The deployment is found in the Kubernetes cluster, no deployment descriptor is required.")
+            )
+            .arg(Arg::with_name("selector")
+                .long("selector")
+                .short("l")
                 .number_of_values(1)
-                .validator(self::validate_int_greater_than_zero))
+                .conflicts_with("all")
+                .help("Label selector, e.g. 'app.kubernetes.io/instance=h2o-cluster'. All H2O deployments whose stateful sets match the selector are undeployed.")
+            )
+            .arg(Arg::with_name("all")
+                .long("all")
+                .takes_value(false)
+                .requires("namespace")
+                .help("Undeploy all H2O deployments in the namespace given.")
+            )
+            .arg(Arg::with_name("namespace")
+                .long("namespace")
+                .short("n")
+                .number_of_values(1)
+                .help("Kubernetes cluster namespace to search for H2O deployments in. If not specified, kubeconfig default is used.")
+            )
             .arg(Arg::with_name("kubeconfig")
                 .long("kubeconfig")
                 .short("k")
                 .number_of_values(1)
                 .validator(self::validate_path)
-                .help("Path to 'kubeconfig' yaml file. If not specified, well-known locations are scanned for kubeconfig.")
+                .help("Path to 'kubeconfig' yaml file used when undeploying by name, selector or '--all'. \
+                If not specified, well-known locations are scanned for kubeconfig.")
+            )
+            .args(&connection_args())
+            .arg(Arg::with_name("yes")
+                .long("yes")
+                .short("y")
+                .takes_value(false)
+                .help("Undeploy H2O deployments found by name, selector or '--all' without asking for confirmation.")
+            )
+            .arg(Arg::with_name("propagation")
+                .long("propagation")
+                .number_of_values(1)
+                .possible_values(&["foreground", "background", "orphan"])
+                .default_value("background")
+                .help("Deletion propagation policy. 'foreground' - H2O pods are deleted before the stateful set, \
+                'background' - H2O pods are deleted after the stateful set, 'orphan' - H2O pods are left running.")
+            )
+            .arg(Arg::with_name("wait")
+                .long("wait")
+                .takes_value(false)
+                .help("Wait until all H2O pods are gone, so an H2O deployment with the same name can be deployed right away.")
+            )
+            .arg(Arg::with_name("timeout")
+                .long("timeout")
+                .short("t")
+                .number_of_values(1)
+                .default_value("5m")
+                .help("Maximum time to wait for H2O pods to be gone when '--wait' is used, e.g. 90s, 10m or 1h.")
+                .validator(self::validate_duration)
+            )
+            .arg(Arg::with_name("purge")
+                .long("purge")
+                .takes_value(false)
+                .help("Also delete persistent volume claims and secrets labelled as belonging to the H2O deployment.")
+            )
+            .arg(Arg::with_name("force")
+                .long("force")
+                .takes_value(false)
+                .help("Undeploy H2O deployments even if protected against deletion.")
+            )
+            .arg(ignore_cluster_mismatch_arg("Undeploy H2O deployments even if deployed to a different Kubernetes cluster than the one currently selected."))
+            )
+        .subcommand(SubCommand::with_name("ingress")
+            .about("Creates an ingress pointing to the given H2O K8S deployment")
+            .args(&existing_deployment_args())
+            .arg(ignore_cluster_mismatch_arg("Create the ingress even if the Kubernetes cluster does not match the one recorded in the deployment descriptor.")))
+        .subcommand(SubCommand::with_name("wait")
+            .about("Waits until an existing H2O deployment reaches the given condition. Exits with code 0 once the condition is met, \
+            2 if the condition can never be met (e.g. a pod failed to start) and 3 on timeout.")
+            .args(&existing_deployment_args())
+            .arg(Arg::with_name("for")
+                .long("for")
+                .number_of_values(1)
+                .possible_values(&["ready", "leader", "deleted"])
+                .default_value("ready")
+                .help("Condition to wait for. 'ready' - all H2O nodes running and clustered, 'leader' - H2O leader node elected, \
+                'deleted' - all the deployment's components removed.")
+            )
+            .arg(Arg::with_name("timeout")
+                .long("timeout")
+                .short("t")
+                .number_of_values(1)
+                .default_value("10m")
+                .help("Maximum time to wait, e.g. 90s, 10m or 1h. A number without unit is interpreted as seconds.")
+                .validator(self::validate_duration)
+            ))
+        .subcommand(SubCommand::with_name("status")
+            .about("Displays status of an existing H2O deployment - H2O pods, the leader node and H2O cloud health.")
+            .args(&existing_deployment_args())
+            .arg(Arg::with_name("watch")
+                .long("watch")
+                .short("w")
+                .takes_value(false)
+                .help("Keep displaying the status as it changes. With '--output json', each status document is printed on a separate line.")
+            ))
+        .subcommand(SubCommand::with_name("list")
+            .about("Lists H2O deployments created by h2ok found in the Kubernetes cluster, even those without a deployment descriptor file.")
+            .arg(Arg::with_name("all_namespaces")
+                .long("all-namespaces")
+                .short("A")
+                .takes_value(false)
+                .conflicts_with("namespace")
+                .help("Search for H2O deployments in all namespaces.")
             )
             .arg(Arg::with_name("namespace")
                 .long("namespace")
                 .short("n")
-                .help("Kubernetes cluster namespace to connect to. If not specified, kubeconfig default is used.")
                 .number_of_values(1)
+                .help("Kubernetes cluster namespace to search in. If not specified, kubeconfig default is used.")
             )
-            .arg(Arg::with_name("name")
-                .long("cluster_name")
-                .short("c")
-                .help("Name of the H2O cluster deployment. Used as prefix for K8S entities. Generated if not specified.")
-                .number_of_values(1))
-            .arg(Arg::with_name("memory_percentage")
-                .long("memory_percentage")
-                .short("p")
-                .default_value("50")
-                .help("Memory percentage allocated by H2O inside the container. <0,100>. Defaults to 50% to make space for XGBoost.")
-                .validator(self::validate_percentage))
-            .arg(Arg::with_name("memory")
-                .long("memory")
-                .short("m")
+            .arg(Arg::with_name("kubeconfig")
+                .long("kubeconfig")
+                .short("k")
                 .number_of_values(1)
-                .default_value("1Gi")
-                .help("Amount of memory allocated by each H2O node - in a format accepted by K8S, e.g. 4Gi.")
-                .validator(self::validate_memory))
-            .arg(Arg::with_name("cpus")
-                .long("cpus")
+                .validator(self::validate_path)
+                .help("Path to 'kubeconfig' yaml file. If not specified, well-known locations are scanned for kubeconfig.")
+            )
+            .args(&connection_args()))
+        .subcommand(SubCommand::with_name("adopt")
+            .about("Reconstructs a deployment descriptor file of an existing H2O deployment from the Kubernetes cluster. \
+            Useful when the original deployment descriptor file has been lost.")
+            .arg(Arg::with_name("name")
+                .required(true)
+                .index(1)
+                .help("Name of the H2O deployment to adopt, as displayed by the 'list' command.")
+            )
+            .arg(Arg::with_name("namespace")
+                .long("namespace")
+                .short("n")
                 .number_of_values(1)
-                .default_value("1")
-                .help("Number of CPUs allocated for each H2O node.")
+                .help("Kubernetes cluster namespace the H2O deployment lives in. If not specified, kubeconfig default is used.")
             )
-        )
-        .subcommand(SubCommand::with_name("undeploy")
-            .about("Undeploys an existing H2O cluster from Kubernetes")
-            .arg(Arg::with_name("file")
-                .long("file")
-                .short("f")
+            .arg(Arg::with_name("kubeconfig")
+                .long("kubeconfig")
+                .short("k")
                 .number_of_values(1)
-                .help("H2O deployment descriptor file path. If not specified, attempt is made to parse deployment descriptor path from stdin.")
                 .validator(self::validate_path)
+                .help("Path to 'kubeconfig' yaml file. If not specified, well-known locations are scanned for kubeconfig.")
+            )
+            .args(&connection_args())
+            .arg(descriptor_out_arg())
+            .arg(store_arg()))
+        .subcommand(SubCommand::with_name("export")
+            .about("Exports an existing H2O deployment as a Helm chart, a Kustomize base or plain YAML, so the same H2O cluster \
+            can be managed by GitOps tools, e.g. Argo CD or Flux.")
+            .args(&existing_deployment_args())
+            .arg(Arg::with_name("format")
+                .long("format")
+                .number_of_values(1)
+                .possible_values(&["helm", "kustomize", "yaml"])
+                .default_value("yaml")
+                .help("'helm' exports a chart with values for the count of H2O nodes, memory, CPUs and the docker image. \
+                'kustomize' exports a base with a 'kustomization.yaml'. 'yaml' exports a single multi-document YAML.")
+            )
+            .arg(Arg::with_name("out")
+                .long("out")
+                .number_of_values(1)
+                .required_ifs(&[("format", "helm"), ("format", "kustomize")])
+                .help("Directory to write the exported files to, created if it does not exist. Existing files are overwritten. \
+                Required for 'helm' and 'kustomize'. If not specified for 'yaml', the YAML is printed to stdout.")
             ))
-        .subcommand(SubCommand::with_name("ingress")
-            .about("Creates an ingress pointing to the given H2O K8S deployment")
+        .subcommand(SubCommand::with_name("apply")
+            .about("Brings an H2O deployment to the state specified by a cluster spec file. Deploys the H2O cluster if missing, \
+            does nothing if it matches the cluster spec and makes the changes required otherwise. The deployment descriptor \
+            is written to the state directory and records the cluster spec file applied.")
             .arg(Arg::with_name("file")
                 .long("file")
                 .short("f")
+                .required(true)
                 .number_of_values(1)
-                .help("H2O deployment descriptor file path. If not specified, attempt is made to parse deployment descriptor path from stdin.")
+                .help("Cluster spec file path, a YAML document with 'apiVersion: h2ok/v1' and 'kind: H2OCluster'.")
                 .validator(self::validate_path)
-            ));
+            )
+            .arg(Arg::with_name("kubeconfig")
+                .long("kubeconfig")
+                .short("k")
+                .number_of_values(1)
+                .validator(self::validate_path)
+                .help("Path to 'kubeconfig' yaml file. If not specified, well-known locations are scanned for kubeconfig.")
+            )
+            .args(&connection_args())
+            .arg(Arg::with_name("plan")
+                .long("plan")
+                .takes_value(false)
+                .help("Only report the changes required, do not make them.")
+            )
+            .arg(Arg::with_name("recreate")
+                .long("recreate")
+                .takes_value(false)
+                .help("Recreate the H2O cluster if the count of nodes, memory, CPUs or memory percentage changed. H2O nodes can not be \
+                reconfigured once clustered, all the data held in memory is lost. Without this flag, such changes are only reported.")
+            )
+            .arg(ignore_cluster_mismatch_arg("Make the changes even if the Kubernetes cluster does not match the one recorded in the deployment descriptor."))
+            .arg(descriptor_out_arg())
+            .arg(store_arg()))
+        .subcommand(SubCommand::with_name("config")
+            .about("Works with the configuration files providing defaults and presets of the 'deploy' and 'preflight' arguments: \
+            '~/.config/h2ok/config.toml' and '.h2ok.toml' in the current directory.")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("show")
+                .about("Displays the effective values of the 'deploy' arguments and where each of them comes from - the command line, \
+                a preset, a configuration file or the built-in default. Accepts the same arguments as 'deploy'.")
+                .args(&deployment_args())))
+        .subcommand(SubCommand::with_name("descriptor")
+            .about("Works with the deployment descriptor files. Descriptors written by older versions of h2ok are upgraded when read, \
+            descriptors written by newer versions are refused.")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("migrate")
+                .about("Rewrites deployment descriptors of older versions in the current version of the descriptor format. \
+                Descriptors already in the current version are left intact.")
+                .arg(descriptor_files_arg()))
+            .subcommand(SubCommand::with_name("validate")
+                .about("Checks deployment descriptors can be read by this version of h2ok and describe a valid deployment. \
+                Reports descriptors of older versions, which are still readable, as outdated.")
+                .arg(descriptor_files_arg()))
+            .subcommand(SubCommand::with_name("schema")
+                .about("Prints the JSON Schema of the current version of the deployment descriptor format.")))
+        .subcommand(SubCommand::with_name("protect")
+            .about("Protects an existing H2O deployment against deletion. Undeployment is then refused unless '--force' is used.")
+            .args(&existing_deployment_args())
+            .arg(ignore_cluster_mismatch_arg("Change the protection even if the Kubernetes cluster does not match the one recorded in the deployment descriptor.")))
+        .subcommand(SubCommand::with_name("unprotect")
+            .about("Removes deletion protection from an existing H2O deployment.")
+            .args(&existing_deployment_args())
+            .arg(ignore_cluster_mismatch_arg("Change the protection even if the Kubernetes cluster does not match the one recorded in the deployment descriptor.")));
 }
 
 /// Validates whether a file under a user-provided path exists.
@@ -240,10 +1367,45 @@ fn validate_path(user_provided_path: String) -> Result<(), String> {
     };
 }
 
+/// Validates whether a file under a user-provided path exists or the value is a name of a deployment.
+fn validate_path_or_name(user_provided_path: String) -> Result<(), String> {
+    return if Path::new(&user_provided_path).is_file() || state::is_deployment_name(&user_provided_path) {
+        Result::Ok(())
+    } else {
+        Result::Err(format!("Invalid file path or deployment name: '{}'", user_provided_path))
+    };
+}
+
+/// Validates whether a user-provided path is a glob pattern matching at least one file, or the value is a name of a deployment.
+fn validate_path_glob_or_name(user_provided_path: String) -> Result<(), String> {
+    return if state::is_deployment_name(&user_provided_path) {
+        Result::Ok(())
+    } else {
+        validate_path_or_glob(user_provided_path)
+    };
+}
+
+/// Validates whether a file under a user-provided path exists or the path is a glob pattern matching at least one file.
+fn validate_path_or_glob(user_provided_path: String) -> Result<(), String> {
+    return if expand_path(&user_provided_path).is_empty() {
+        Result::Err(format!("Invalid file path or no file matching the pattern: '{}'", user_provided_path))
+    } else {
+        Result::Ok(())
+    };
+}
+
+/// Validates user input to be an integer greater than or equal to zero.
+fn validate_non_negative_int(input: String) -> Result<(), String> {
+    return match input.parse::<u32>() {
+        Ok(_) => { Result::Ok(()) }
+        Err(_) => { Result::Err("Error: The number provided must be zero or greater.".to_string()) }
+    };
+}
+
 /// Validates user input to be an integer greater than zero.
 /// Returns Result::Ok if given String  contains an integer greater than zero, otherwise Err with error message.
 fn validate_int_greater_than_zero(input: String) -> Result<(), String> {
-    let number: i64 = input.parse::<i64>().unwrap();
+    let number: i64 = input.parse::<i64>().map_err(|_| format!("Error: '{}' is not a number.", input))?;
     return if number < 1 {
         Result::Err("Error: The number provided must be greater than zero.".to_string())
     } else {
@@ -257,7 +1419,7 @@ fn validate_int_greater_than_zero(input: String) -> Result<(), String> {
 ///  * `input` User's input in String
 ///
 fn validate_percentage(input: String) -> Result<(), String> {
-    let number: i64 = input.parse::<i64>().unwrap();
+    let number: i64 = input.parse::<i64>().map_err(|_| format!("Error: '{}' is not a number.", input))?;
     return if number < 0 || number > 100 {
         Result::Err(format!("Error: The number must be withing range <{},{}>.", 0, 100))
     } else {
@@ -278,11 +1440,41 @@ fn validate_memory(input: String) -> Result<(), String> {
     };
 }
 
+const DURATION_PATTERN: &str = "^([0-9]+)(s|m|h)?$";
+
+/// Parses a duration in a `<number><unit>` format, where the unit is one of `s`, `m` or `h`.
+/// If the unit is omitted, seconds are assumed. Returns Option::None if the input does not match the format.
+fn parse_duration(input: &str) -> Option<Duration> {
+    let duration_regexp = Regex::new(DURATION_PATTERN).unwrap();
+    let captures = duration_regexp.captures(input)?;
+    let amount: u64 = captures.get(1)?.as_str().parse::<u64>().ok()?;
+    let multiplier: u64 = match captures.get(2).map(|unit| unit.as_str()) {
+        Some("h") => 3600,
+        Some("m") => 60,
+        _ => 1
+    };
+    return Some(Duration::from_secs(amount.checked_mul(multiplier)?));
+}
+
+/// Validates duration input from user, e.g. `10m`.
+fn validate_duration(input: String) -> Result<(), String> {
+    return match parse_duration(&input) {
+        Some(_) => { Result::Ok(()) }
+        None => { Result::Err(format!("Duration must match the following pattern: {}. For example 90s, 10m or 1h.", DURATION_PATTERN)) }
+    };
+}
 
 #[cfg(test)]
 mod tests {
+    use std::path::PathBuf;
+    use std::time::Duration;
+
     use clap::{App, ArgMatches};
 
+    use crate::cli::{DescriptorSource, DescriptorStore, OutputFormat};
+    use crate::cli::config::Config;
+    use crate::k8s::{ClusterConnection, DeployOptions, DryRun};
+    use crate::k8s::retry::RetryPolicy;
     use crate::tests::kubeconfig_location_panic;
 
     #[test]
@@ -321,9 +1513,168 @@ mod tests {
         assert_eq!("non-default", deploy.value_of("namespace").unwrap())
     }
 
+    #[test]
+    fn test_dry_run() {
+        let matches: ArgMatches = super::build_app().get_matches_from(vec!["h2ok", "deploy", "--cluster_size", "1", "--dry-run=client"]);
+        let options: DeployOptions = super::extract_deployment_specification(matches.subcommand_matches("deploy").unwrap(), &Config::default()).unwrap().options;
+        assert_eq!(Some(DryRun::Client), options.dry_run);
+
+        let matches: ArgMatches = super::build_app().get_matches_from(vec!["h2ok", "deploy", "--cluster_size", "1", "--dry-run", "server"]);
+        let options: DeployOptions = super::extract_deployment_specification(matches.subcommand_matches("deploy").unwrap(), &Config::default()).unwrap().options;
+        assert_eq!(Some(DryRun::Server), options.dry_run);
+
+        let result = super::build_app().get_matches_from_safe(vec!["h2ok", "deploy", "--cluster_size", "1", "--dry-run=none"]);
+        assert!(result.is_err());
+    }
+
     #[test]
     fn validate_number_range() {
         assert!(super::validate_percentage("10".to_string()).is_ok());
         assert!(super::validate_percentage("101".to_string()).is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_undeploy_targets() {
+        let app: App = super::build_app();
+        let args_all_without_namespace: Vec<&str> = vec!["h2ok", "undeploy", "--all"];
+        assert!(app.get_matches_from_safe(args_all_without_namespace).is_err());
+
+        let app: App = super::build_app();
+        let args_name_and_selector: Vec<&str> = vec!["h2ok", "undeploy", "--name", "h2o-cluster", "--selector", "team=ml"];
+        assert!(app.get_matches_from_safe(args_name_and_selector).is_err());
+
+        let app: App = super::build_app();
+        let args_all: Vec<&str> = vec!["h2ok", "undeploy", "--all", "--namespace", "h2o", "--yes"];
+        let matches: ArgMatches = app.get_matches_from(args_all);
+        let undeploy: &ArgMatches = matches.subcommand_matches("undeploy").unwrap();
+        assert!(undeploy.is_present("all"));
+        assert!(undeploy.is_present("yes"));
+        assert_eq!("h2o", undeploy.value_of("namespace").unwrap());
+    }
+
+    #[test]
+    fn test_expand_path() {
+        let directory: PathBuf = std::env::temp_dir().join(format!("h2ok-expand-path-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        for file_name in ["h2o-first.h2ok", "h2o-second.h2ok", "other.txt"].iter() {
+            std::fs::write(directory.join(file_name), "{}").unwrap();
+        }
+
+        let pattern: String = format!("{}/h2o-*.h2ok", directory.to_str().unwrap());
+        let mut expanded: Vec<PathBuf> = super::expand_path(&pattern);
+        expanded.sort();
+        assert_eq!(vec!(directory.join("h2o-first.h2ok"), directory.join("h2o-second.h2ok")), expanded);
+
+        let single_file: String = format!("{}/other.txt", directory.to_str().unwrap());
+        assert_eq!(vec!(directory.join("other.txt")), super::expand_path(&single_file));
+        assert!(super::validate_path_or_glob(format!("{}/*.json", directory.to_str().unwrap())).is_err());
+
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(Some(Duration::from_secs(90)), super::parse_duration("90"));
+        assert_eq!(Some(Duration::from_secs(90)), super::parse_duration("90s"));
+        assert_eq!(Some(Duration::from_secs(600)), super::parse_duration("10m"));
+        assert_eq!(Some(Duration::from_secs(7200)), super::parse_duration("2h"));
+        assert!(super::parse_duration("10d").is_none());
+        assert!(super::parse_duration("m").is_none());
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let app: App = super::build_app();
+        let args_default: Vec<&str> = vec!["h2ok", "undeploy", "--name", "h2o-cluster"];
+        let matches: ArgMatches = app.get_matches_from(args_default);
+        let retry_policy: RetryPolicy = super::extract_retry_policy(matches.subcommand_matches("undeploy").unwrap()).unwrap();
+        assert_eq!(3, retry_policy.retries);
+        assert!(retry_policy.request_timeout.is_none());
+        assert!(!retry_policy.verbose);
+
+        // Global arguments are accepted both before and after the subcommand
+        let app: App = super::build_app();
+        let args_custom: Vec<&str> = vec!["h2ok", "--retries", "0", "undeploy", "--name", "h2o-cluster", "--request-timeout", "30s", "-v"];
+        let matches: ArgMatches = app.get_matches_from(args_custom);
+        let retry_policy: RetryPolicy = super::extract_retry_policy(matches.subcommand_matches("undeploy").unwrap()).unwrap();
+        assert_eq!(0, retry_policy.retries);
+        assert_eq!(Some(Duration::from_secs(30)), retry_policy.request_timeout);
+        assert!(retry_policy.verbose);
+
+        let app: App = super::build_app();
+        let args_negative: Vec<&str> = vec!["h2ok", "undeploy", "--name", "h2o-cluster", "--retries", "-1"];
+        assert!(app.get_matches_from_safe(args_negative).is_err());
+    }
+
+    #[test]
+    fn test_connection() {
+        let app: App = super::build_app();
+        let args_connection: Vec<&str> = vec!["h2ok", "deploy", "--cluster_size", "1", "--context", "staging", "--as", "system:serviceaccount:h2o:deployer",
+                                              "--as-group", "system:serviceaccounts", "--as-group", "h2o-team"];
+        let matches: ArgMatches = app.get_matches_from(args_connection);
+        let connection: ClusterConnection = super::extract_connection(matches.subcommand_matches("deploy").unwrap());
+        assert_eq!(Some("staging".to_string()), connection.context);
+        assert_eq!(Some("system:serviceaccount:h2o:deployer".to_string()), connection.impersonate_user);
+        assert_eq!(vec!("system:serviceaccounts".to_string(), "h2o-team".to_string()), connection.impersonate_groups);
+        assert!(!connection.in_cluster);
+
+        let app: App = super::build_app();
+        let args_group_only: Vec<&str> = vec!["h2ok", "list", "--as-group", "h2o-team"];
+        assert!(app.get_matches_from_safe(args_group_only).is_err());
+
+        let app: App = super::build_app();
+        let args_in_cluster_context: Vec<&str> = vec!["h2ok", "list", "--in-cluster", "--context", "staging"];
+        assert!(app.get_matches_from_safe(args_in_cluster_context).is_err());
+    }
+
+    #[test]
+    fn test_descriptor_source() {
+        let app: App = super::build_app();
+        let args_name: Vec<&str> = vec!["h2ok", "status", "--name", "h2o-test", "-n", "h2o", "--context", "staging"];
+        let matches: ArgMatches = app.get_matches_from(args_name);
+        match super::extract_descriptor_source(matches.subcommand_matches("status").unwrap()).unwrap() {
+            DescriptorSource::Cluster { name, namespace, kubeconfig_path, connection } => {
+                assert_eq!("h2o-test", name);
+                assert_eq!(Some("h2o".to_string()), namespace);
+                assert!(kubeconfig_path.is_none());
+                assert_eq!(Some("staging".to_string()), connection.context);
+            }
+            DescriptorSource::File(path) => { panic!("Expected the deployment descriptor stored in the cluster, got '{}'", path.display()); }
+        }
+
+        let app: App = super::build_app();
+        let args_none: Vec<&str> = vec!["h2ok", "wait"];
+        assert!(app.get_matches_from_safe(args_none).is_err());
+
+        let app: App = super::build_app();
+        let args_both: Vec<&str> = vec!["h2ok", "protect", "--name", "h2o-test", "-f", "h2o-test"];
+        assert!(app.get_matches_from_safe(args_both).is_err());
+
+        let app: App = super::build_app();
+        let args_store: Vec<&str> = vec!["h2ok", "deploy", "--cluster_size", "1", "--store", "cluster"];
+        let matches: ArgMatches = app.get_matches_from(args_store);
+        assert_eq!(DescriptorStore::Cluster, super::extract_descriptor_store(matches.subcommand_matches("deploy").unwrap()));
+    }
+
+    #[test]
+    fn test_output_format() {
+        let app: App = super::build_app();
+        let args_default: Vec<&str> = vec!["h2ok", "deploy", "--cluster_size", "1"];
+        let matches: ArgMatches = app.get_matches_from(args_default);
+        assert_eq!(OutputFormat::Text, super::extract_output_format(matches.subcommand_matches("deploy").unwrap()));
+
+        let app: App = super::build_app();
+        let args_yaml: Vec<&str> = vec!["h2ok", "-o", "yaml", "undeploy", "--name", "h2o-cluster"];
+        let matches: ArgMatches = app.get_matches_from(args_yaml);
+        assert_eq!(OutputFormat::Yaml, super::extract_output_format(matches.subcommand_matches("undeploy").unwrap()));
+
+        let app: App = super::build_app();
+        let args_json: Vec<&str> = vec!["h2ok", "ingress", "--name", "h2o-cluster", "--output", "json"];
+        let matches: ArgMatches = app.get_matches_from(args_json);
+        assert_eq!(OutputFormat::Json, super::extract_output_format(matches.subcommand_matches("ingress").unwrap()));
+
+        let app: App = super::build_app();
+        let args_unknown: Vec<&str> = vec!["h2ok", "list", "--output", "xml"];
+        assert!(app.get_matches_from_safe(args_unknown).is_err());
+    }
+}
@@ -0,0 +1,237 @@
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use serde_json::{Map, Value};
+
+use crate::k8s::{ClusterConnection, Deployment};
+use crate::k8s::preflight::parse_memory;
+
+/// Version of the deployment descriptor format written by this version of h2ok. Descriptors without a version
+/// have been written before the format has been versioned and are treated as version 0.
+pub const DESCRIPTOR_VERSION: u32 = 1;
+
+/// JSON Schema of the current version of the deployment descriptor format.
+pub const SCHEMA: &str = include_str!("../schema/descriptor.schema.json");
+
+/// A migration upgrades a descriptor from the version equal to its index in `MIGRATIONS` to the next version.
+type Migration = fn(&mut Map<String, Value>) -> Result<(), String>;
+
+const MIGRATIONS: [Migration; DESCRIPTOR_VERSION as usize] = [migrate_v0];
+
+/// Reasons a deployment descriptor can not be read or written.
+#[derive(Debug)]
+pub enum DescriptorError {
+    Io(std::io::Error),
+    /// The descriptor is not a valid JSON document or does not describe a deployment.
+    Invalid(String),
+    /// The descriptor has been written by a newer version of h2ok.
+    Unsupported(u32),
+}
+
+impl Display for DescriptorError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            DescriptorError::Io(e) => { write!(f, "{}", e) }
+            DescriptorError::Invalid(reason) => { write!(f, "{}", reason) }
+            DescriptorError::Unsupported(version) => {
+                write!(f, "Descriptor version {} is not supported, the newest version known to this version of h2ok is {}. Upgrade h2ok.",
+                       version, DESCRIPTOR_VERSION)
+            }
+        };
+    }
+}
+
+impl From<std::io::Error> for DescriptorError {
+    fn from(e: std::io::Error) -> Self {
+        return DescriptorError::Io(e);
+    }
+}
+
+impl From<serde_json::Error> for DescriptorError {
+    fn from(e: serde_json::Error) -> Self {
+        return DescriptorError::Invalid(e.to_string());
+    }
+}
+
+/// Reads a Deployment from a deployment descriptor file. Descriptors of older versions are upgraded in memory, the file is left intact.
+pub fn read(path: &Path) -> Result<Deployment, DescriptorError> {
+    let (deployment, _) = read_versioned(path)?;
+    return Ok(deployment);
+}
+
+/// Reads a Deployment from a deployment descriptor file, together with the version of the descriptor before any migration.
+pub fn read_versioned(path: &Path) -> Result<(Deployment, u32), DescriptorError> {
+    let file: File = File::open(path)?;
+    let document: Value = serde_json::from_reader(file)?;
+    return parse(document);
+}
+
+/// Reads a Deployment from a serialized deployment descriptor, e.g. the one stored in the Kubernetes cluster, upgrading descriptors of older versions.
+pub fn from_json(descriptor: &str) -> Result<Deployment, DescriptorError> {
+    let (deployment, _) = parse(serde_json::from_str(descriptor)?)?;
+    return Ok(deployment);
+}
+
+/// Upgrades the descriptor `document` to the current version and deserializes the Deployment from it.
+/// Returns the Deployment together with the version of the descriptor before the migration.
+pub fn parse(document: Value) -> Result<(Deployment, u32), DescriptorError> {
+    let mut document: Map<String, Value> = match document {
+        Value::Object(document) => { document }
+        _ => { return Err(DescriptorError::Invalid("The descriptor is not a JSON object.".to_string())); }
+    };
+    let version: u32 = match document.get("version") {
+        None => { 0 }
+        Some(version) => {
+            match version.as_u64().and_then(|number| u32::try_from(number).ok()) {
+                Some(number) => { number }
+                None => { return Err(DescriptorError::Invalid(format!("Invalid descriptor version '{}'.", version))); }
+            }
+        }
+    };
+    if version > DESCRIPTOR_VERSION {
+        return Err(DescriptorError::Unsupported(version));
+    }
+
+    for (from_version, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        migration(&mut document).map_err(|reason| DescriptorError::Invalid(format!("Unable to migrate from version {}: {}", from_version, reason)))?;
+        document.insert("version".to_string(), Value::from(from_version as u32 + 1));
+    }
+    let deployment: Deployment = serde_json::from_value(Value::Object(document))?;
+    return Ok((deployment, version));
+}
+
+/// Serializes the Deployment into a descriptor of the current version.
+pub fn to_value(deployment: &Deployment) -> Value {
+    // Deployment consists of plain data and Kubernetes entities, its serialization can not fail.
+    let mut document: Value = serde_json::to_value(deployment).unwrap();
+    document["version"] = Value::from(DESCRIPTOR_VERSION);
+    return document;
+}
+
+/// Serializes the Deployment into a descriptor of the current version, as stored in the Kubernetes cluster.
+pub fn to_json(deployment: &Deployment) -> String {
+    return to_value(deployment).to_string();
+}
+
+/// Writes a Deployment into a deployment descriptor file of the current version under the given path, overwriting any existing file.
+/// The descriptor is written to a temporary file in the same directory first, which then replaces the existing file at once,
+/// so readers never see a partially written descriptor.
+pub fn write(deployment: &Deployment, path: &Path) -> Result<(), DescriptorError> {
+    let file_name: String = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    let temporary_path: PathBuf = path.with_file_name(format!(".{}.{}.tmp", file_name, std::process::id()));
+    let written: Result<(), DescriptorError> = File::create(&temporary_path)
+        .map_err(DescriptorError::from)
+        .and_then(|mut file| {
+            serde_json::to_writer(&mut file, &to_value(deployment))?;
+            file.flush()?;
+            file.sync_all()?;
+            return Ok(());
+        })
+        .and_then(|_| std::fs::rename(&temporary_path, path).map_err(DescriptorError::from));
+    if written.is_err() {
+        let _ = std::fs::remove_file(&temporary_path);
+    }
+    return written;
+}
+
+/// Checks the values of the Deployment beyond their types. Returns a description of each problem found,
+/// an empty list means the deployment is valid.
+pub fn problems(deployment: &Deployment) -> Vec<String> {
+    let mut problems: Vec<String> = vec!();
+    let specification = &deployment.specification;
+    if specification.name.is_empty() {
+        problems.push("The name is empty.".to_string());
+    }
+    if specification.namespace.is_empty() {
+        problems.push("The namespace is empty.".to_string());
+    }
+    if specification.num_h2o_nodes < 1 || specification.num_cpu < 1 {
+        problems.push("The count of nodes and CPUs must be greater than zero.".to_string());
+    }
+    if specification.memory_percentage > 100 {
+        problems.push(format!("The memory percentage {} is not within range <0,100>.", specification.memory_percentage));
+    }
+    if parse_memory(&specification.memory).is_none() {
+        problems.push(format!("Invalid memory '{}'.", specification.memory));
+    }
+    if deployment.stateful_sets.is_empty() && deployment.services.is_empty() && deployment.ingresses.is_empty() {
+        problems.push("No Kubernetes entities are recorded.".to_string());
+    }
+    return problems;
+}
+
+/// Version 0 descriptors have been written before the cluster connection, identity, spec file and namespace ownership
+/// have been recorded. Records them explicitly with the values the deployment has been created with.
+fn migrate_v0(document: &mut Map<String, Value>) -> Result<(), String> {
+    document.entry("namespace_created").or_insert(Value::Bool(false));
+    let specification: &mut Map<String, Value> = match document.get_mut("specification") {
+        Some(Value::Object(specification)) => { specification }
+        _ => { return Err("The specification is missing.".to_string()); }
+    };
+    specification.entry("kubeconfig_path").or_insert(Value::Null);
+    specification.entry("connection").or_insert_with(|| serde_json::to_value(ClusterConnection::default()).unwrap());
+    specification.entry("cluster_identity").or_insert(Value::Null);
+    specification.entry("spec_file").or_insert(Value::Null);
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::core::v1::Service;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+    use serde_json::Value;
+
+    use crate::k8s::{Deployment, DeploymentSpecification};
+
+    use super::{DESCRIPTOR_VERSION, DescriptorError, SCHEMA};
+
+    const V0_DESCRIPTOR: &str = r#"{"specification":{"name":"h2o-test","namespace":"default","memory_percentage":50,"memory":"1Gi","num_cpu":1,
+    "num_h2o_nodes":2,"kubeconfig_path":null},"ingresses":[],"stateful_sets":[],"services":[{"metadata":{"name":"h2o-test"}}]}"#;
+
+    #[test]
+    fn test_migrate() {
+        let (deployment, version) = super::parse(serde_json::from_str(V0_DESCRIPTOR).unwrap()).unwrap();
+        assert_eq!(0, version);
+        assert_eq!("h2o-test", deployment.specification.name);
+        assert!(!deployment.namespace_created);
+        assert!(super::problems(&deployment).is_empty());
+
+        let document: Value = super::to_value(&deployment);
+        assert_eq!(DESCRIPTOR_VERSION as u64, document["version"]);
+        let (_, version) = super::parse(document.clone()).unwrap();
+        assert_eq!(DESCRIPTOR_VERSION, version);
+        assert_eq!("h2o-test", super::from_json(&super::to_json(&deployment)).unwrap().specification.name);
+
+        let mut future: Value = document;
+        future["version"] = Value::from(DESCRIPTOR_VERSION + 1);
+        assert!(matches!(super::parse(future), Err(DescriptorError::Unsupported(_))));
+        assert!(matches!(super::parse(Value::from("h2o")), Err(DescriptorError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_schema() {
+        let schema: Value = serde_json::from_str(SCHEMA).unwrap();
+        assert_eq!(DESCRIPTOR_VERSION as u64, schema["properties"]["version"]["const"]);
+
+        let mut deployment: Deployment = Deployment::new(DeploymentSpecification::new("h2o-test".to_string(), "h2o".to_string(), 50,
+                                                                                      "1Gi".to_string(), 1, 2, None));
+        deployment.services.push(Service { metadata: ObjectMeta { name: Some("h2o-test".to_string()), ..ObjectMeta::default() }, ..Service::default() });
+        let document: Value = super::to_value(&deployment);
+        // Every property required by the schema is written, so the schema does not drift from the descriptors actually written.
+        let required = |schema: &Value| -> Vec<String> {
+            return schema["required"].as_array().unwrap().iter().map(|name| name.as_str().unwrap().to_string()).collect();
+        };
+        for name in required(&schema) {
+            assert!(document.get(&name).is_some(), "Missing property '{}'", name);
+        }
+        for name in required(&schema["definitions"]["specification"]) {
+            assert!(document["specification"].get(&name).is_some(), "Missing property 'specification.{}'", name);
+        }
+        for name in required(&schema["definitions"]["connection"]) {
+            assert!(document["specification"]["connection"].get(&name).is_some(), "Missing property 'connection.{}'", name);
+        }
+    }
+}
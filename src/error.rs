@@ -0,0 +1,279 @@
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+
+use kube::Error;
+
+use crate::cli::UserInputError;
+use crate::k8s::{DeploymentError, DeploymentFailure};
+use crate::k8s::discovery::AdoptionError;
+
+/// Exit code of invalid user input, e.g. a missing deployment descriptor.
+pub const EXIT_CODE_USER_INPUT: i32 = 1;
+/// Exit code of an operation carried out only partially or whose goal can never be reached, e.g. some Kubernetes entities
+/// not undeployed or an H2O pod failed to start while waiting for the deployment to be ready.
+pub const EXIT_CODE_INCOMPLETE: i32 = 2;
+/// Exit code of an operation not finished in time.
+pub const EXIT_CODE_TIMEOUT: i32 = 3;
+/// Exit code of a missing or invalid kubeconfig.
+pub const EXIT_CODE_KUBECONFIG: i32 = 4;
+/// Exit code of a request rejected by the Kubernetes API server or the API server being unreachable.
+pub const EXIT_CODE_API: i32 = 5;
+/// Exit code of a deployment descriptor or an exported file that can not be read or written.
+pub const EXIT_CODE_DESCRIPTOR: i32 = 6;
+/// Exit code of a failed deployment whose rollback failed as well - some Kubernetes entities may be left behind.
+pub const EXIT_CODE_ROLLBACK: i32 = 7;
+/// Exit code of an H2O deployment not found in the Kubernetes cluster.
+pub const EXIT_CODE_NOT_FOUND: i32 = 8;
+/// Exit code of a deployment descriptor recorded in a different Kubernetes cluster than the one currently connected to.
+pub const EXIT_CODE_CLUSTER_MISMATCH: i32 = 9;
+/// Exit code of a deployment refused, as some of the pre-flight checks failed.
+pub const EXIT_CODE_PREFLIGHT: i32 = 10;
+/// Exit code of an operation refused, as the deployment or its deployment descriptor is locked by another operation.
+pub const EXIT_CODE_LOCKED: i32 = 11;
+/// Exit code of an operation interrupted by Ctrl-C or SIGTERM, following the shell convention.
+pub const EXIT_CODE_INTERRUPTED: i32 = 130;
+
+/// Error of any h2ok command. Each kind of error maps to a documented exit code.
+#[derive(Debug)]
+pub enum H2okError {
+    UserInput(UserInputError),
+    /// Kubeconfig could not be found or loaded.
+    Kubeconfig(Box<Error>),
+    /// A Kubernetes API request failed while performing the given action.
+    Api { action: String, error: Box<Error> },
+    /// The given action has not been finished in time.
+    Timeout(String),
+    /// The given action has been carried out only partially or can never be finished.
+    Incomplete(String),
+    /// A deployment descriptor could not be read or written.
+    Descriptor { path: PathBuf, reason: String },
+    /// A cluster spec file could not be read or is not a valid cluster spec.
+    Spec { path: PathBuf, reason: String },
+    /// A file of an exported deployment could not be written.
+    Export { path: PathBuf, reason: String },
+    /// A deployment failed and has been rolled back.
+    Rollback { action: String, error: Box<DeploymentError> },
+    /// The given H2O deployment has not been found in the Kubernetes cluster.
+    NotFound(String),
+    /// The Kubernetes cluster connected to is not the one the deployment descriptor has been recorded in.
+    ClusterMismatch(String),
+    /// Some of the pre-flight checks failed, the deployment is not going to succeed.
+    Preflight(String),
+    /// The deployment or its deployment descriptor is locked by another operation. Describes the holder of the lock.
+    Locked(String),
+}
+
+impl H2okError {
+    pub fn api(action: &str, error: Error) -> Self {
+        H2okError::Api { action: action.to_string(), error: Box::new(error) }
+    }
+
+    pub fn descriptor<E: Display>(path: &Path, reason: E) -> Self {
+        H2okError::Descriptor { path: path.to_path_buf(), reason: reason.to_string() }
+    }
+
+    pub fn spec<E: Display>(path: &Path, reason: E) -> Self {
+        H2okError::Spec { path: path.to_path_buf(), reason: reason.to_string() }
+    }
+
+    pub fn export<E: Display>(path: &Path, reason: E) -> Self {
+        H2okError::Export { path: path.to_path_buf(), reason: reason.to_string() }
+    }
+
+    pub fn kubeconfig(error: Error) -> Self {
+        H2okError::Kubeconfig(Box::new(error))
+    }
+
+    pub fn rollback(action: &str, error: DeploymentError) -> Self {
+        H2okError::Rollback { action: action.to_string(), error: Box::new(error) }
+    }
+
+    pub fn adoption(action: &str, error: AdoptionError) -> Self {
+        return match error {
+            AdoptionError::Api(error) => { H2okError::api(action, error) }
+            error => { H2okError::NotFound(format!("{}: {}", action, error)) }
+        };
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        return match self {
+            H2okError::UserInput(_) | H2okError::Spec { .. } => { EXIT_CODE_USER_INPUT }
+            H2okError::Kubeconfig(_) => { EXIT_CODE_KUBECONFIG }
+            H2okError::Api { .. } => { EXIT_CODE_API }
+            H2okError::Timeout(_) => { EXIT_CODE_TIMEOUT }
+            H2okError::Incomplete(_) => { EXIT_CODE_INCOMPLETE }
+            H2okError::Descriptor { .. } | H2okError::Export { .. } => { EXIT_CODE_DESCRIPTOR }
+            H2okError::Rollback { error, .. } => {
+                if !error.rollback_errors.is_empty() {
+                    EXIT_CODE_ROLLBACK
+                } else {
+                    match error.failure {
+                        DeploymentFailure::Interrupted(_) => { EXIT_CODE_INTERRUPTED }
+                        DeploymentFailure::Signals(_) => { EXIT_CODE_INCOMPLETE }
+                        DeploymentFailure::MissingNamespace(_) => { EXIT_CODE_NOT_FOUND }
+                        DeploymentFailure::Api(_) => { EXIT_CODE_API }
+                    }
+                }
+            }
+            H2okError::NotFound(_) => { EXIT_CODE_NOT_FOUND }
+            H2okError::ClusterMismatch(_) => { EXIT_CODE_CLUSTER_MISMATCH }
+            H2okError::Preflight(_) => { EXIT_CODE_PREFLIGHT }
+            H2okError::Locked(_) => { EXIT_CODE_LOCKED }
+        };
+    }
+
+    /// Suggestion on how to resolve the error, if there is one.
+    pub fn hint(&self) -> Option<String> {
+        return match self {
+            H2okError::UserInput(error) => { Some(error.hint().to_string()) }
+            H2okError::Kubeconfig(_) => {
+                Some("Use the '--kubeconfig' option to point to a valid kubeconfig file or set the KUBECONFIG environment variable.".to_string())
+            }
+            H2okError::Api { error, .. } => { api_hint(error) }
+            H2okError::Rollback { error, .. } => {
+                if !error.rollback_errors.is_empty() {
+                    Some("Remove the Kubernetes entities listed manually, e.g. using 'kubectl delete'.".to_string())
+                } else {
+                    match &error.failure {
+                        DeploymentFailure::Api(error) => { api_hint(error) }
+                        DeploymentFailure::MissingNamespace(_) => {
+                            Some("Use the '--create-namespace' flag to create the namespace or choose an existing one using '--namespace'.".to_string())
+                        }
+                        DeploymentFailure::Interrupted(_) | DeploymentFailure::Signals(_) => { None }
+                    }
+                }
+            }
+            H2okError::Timeout(_) => { Some("Increase the timeout using the '--timeout' or '--request-timeout' option.".to_string()) }
+            H2okError::Incomplete(_) => { None }
+            H2okError::Descriptor { .. } => {
+                Some("Check the file is a deployment descriptor created by h2ok. If it has been lost or damaged, use 'h2ok adopt' to reconstruct it.".to_string())
+            }
+            H2okError::Spec { .. } => {
+                Some("Check the cluster spec starts with 'apiVersion: h2ok/v1' and 'kind: H2OCluster' and contains only the fields documented.".to_string())
+            }
+            H2okError::Export { .. } => { Some("Check the directory given by '--out' is writable.".to_string()) }
+            H2okError::NotFound(_) => { Some("Use 'h2ok list' to display H2O deployments present in the Kubernetes cluster.".to_string()) }
+            H2okError::ClusterMismatch(_) => {
+                Some("Check the kubeconfig and its current context point to the cluster the deployment lives in. Use '--ignore-cluster-mismatch' to proceed anyway.".to_string())
+            }
+            H2okError::Preflight(_) => {
+                Some("Resolve the failed checks listed, e.g. by choosing fewer or smaller H2O nodes. Use '--skip-preflight' to deploy regardless.".to_string())
+            }
+            H2okError::Locked(_) => {
+                Some("Wait until the operation holding the lock finishes, then try again. Deployment descriptor locks are released once \
+                the h2ok process holding them ends, a deployment lock left behind expires after 15 minutes.".to_string())
+            }
+        };
+    }
+}
+
+/// Suggestion on how to resolve a failed Kubernetes API request, based on the response status code.
+fn api_hint(error: &Error) -> Option<String> {
+    return match error {
+        Error::Api(response) => {
+            match response.code {
+                401 => { Some("Check the credentials in the kubeconfig used are valid and not expired.".to_string()) }
+                403 => { Some("Check the kubeconfig user is permitted to manage the entities, e.g. using 'kubectl auth can-i create statefulsets'.".to_string()) }
+                404 => { Some("The entity or namespace does not exist. Check the namespace and whether it has been removed outside of h2ok.".to_string()) }
+                409 => { Some("An entity with the same name already exists. Choose a different name using '--cluster_name' or undeploy the existing deployment first.".to_string()) }
+                422 => { Some("The entity has been rejected as invalid. Check the values of the deployment options, e.g. '--memory'.".to_string()) }
+                429 | 500..=599 => { Some("The Kubernetes API server is overloaded or unavailable. Try again later or increase the number of '--retries'.".to_string()) }
+                _ => { None }
+            }
+        }
+        Error::ReqwestError(_) => { Some("Check the Kubernetes API server is reachable from this machine.".to_string()) }
+        _ => { None }
+    };
+}
+
+impl Display for H2okError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            H2okError::UserInput(error) => { write!(f, "Unable to process user input. {}", error) }
+            H2okError::Kubeconfig(error) => { write!(f, "Unable to load kubeconfig. Reason: {}", error) }
+            H2okError::Api { action, error } => { write!(f, "{}. Reason: {}", action, error) }
+            H2okError::Timeout(action) => { write!(f, "{}", action) }
+            H2okError::Incomplete(action) => { write!(f, "{}", action) }
+            H2okError::Descriptor { path, reason } => { write!(f, "Unable to access deployment descriptor '{}'. Reason: {}", path.display(), reason) }
+            H2okError::Spec { path, reason } => { write!(f, "Invalid cluster spec '{}'. Reason: {}", path.display(), reason) }
+            H2okError::Export { path, reason } => { write!(f, "Unable to write exported file '{}'. Reason: {}", path.display(), reason) }
+            H2okError::Rollback { action, error } => {
+                write!(f, "{}. Reason: {}", action, error)?;
+                if error.rollback_errors.is_empty() {
+                    write!(f, "\nAll the Kubernetes entities created have been removed.")
+                } else {
+                    write!(f, "\nRollback failed, the following Kubernetes entities may have to be removed manually:")?;
+                    for rollback_error in error.rollback_errors.iter() {
+                        write!(f, "\n- {}", rollback_error)?;
+                    }
+                    Ok(())
+                }
+            }
+            H2okError::NotFound(reason) => { write!(f, "{}", reason) }
+            H2okError::ClusterMismatch(reason) => { write!(f, "{}", reason) }
+            H2okError::Preflight(reason) => { write!(f, "{}", reason) }
+            H2okError::Locked(reason) => { write!(f, "{}", reason) }
+        };
+    }
+}
+
+impl From<UserInputError> for H2okError {
+    fn from(error: UserInputError) -> Self {
+        H2okError::UserInput(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use kube::Error;
+    use kube::error::ErrorResponse;
+
+    use crate::cli::CommandErrorKind::MissingDeploymentDescriptor;
+    use crate::cli::UserInputError;
+    use crate::k8s::{DeploymentError, DeploymentFailure};
+
+    use super::H2okError;
+
+    fn api_error(code: u16) -> Error {
+        return Error::Api(ErrorResponse { status: "Failure".to_string(), message: String::new(), reason: String::new(), code });
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        assert_eq!(1, H2okError::from(UserInputError::new(MissingDeploymentDescriptor)).exit_code());
+        assert_eq!(5, H2okError::api("Unable to deploy", api_error(403)).exit_code());
+        assert_eq!(3, H2okError::Timeout("Timed out".to_string()).exit_code());
+
+        let rolled_back: H2okError = H2okError::rollback("Unable to deploy",
+                                                         DeploymentError { failure: DeploymentFailure::Api(api_error(409)), rollback_errors: vec!() });
+        assert_eq!(5, rolled_back.exit_code());
+        assert!(rolled_back.hint().unwrap().contains("already exists"));
+
+        let rollback_failed: H2okError = H2okError::rollback("Unable to deploy",
+                                                             DeploymentError {
+                                                                 failure: DeploymentFailure::Api(api_error(500)),
+                                                                 rollback_errors: vec!("Service 'h2o-service'".to_string()),
+                                                             });
+        assert_eq!(7, rollback_failed.exit_code());
+        assert!(rollback_failed.to_string().contains("Service 'h2o-service'"));
+
+        let interrupted: H2okError = H2okError::rollback("Unable to deploy",
+                                                         DeploymentError { failure: DeploymentFailure::Interrupted("SIGINT"), rollback_errors: vec!() });
+        assert_eq!(130, interrupted.exit_code());
+
+        let missing_namespace: H2okError = H2okError::rollback("Unable to deploy",
+                                                               DeploymentError { failure: DeploymentFailure::MissingNamespace("h2o".to_string()), rollback_errors: vec!() });
+        assert_eq!(8, missing_namespace.exit_code());
+        assert!(missing_namespace.hint().unwrap().contains("--create-namespace"));
+
+        let cluster_mismatch: H2okError = H2okError::ClusterMismatch("Deployment 'h2o' has been deployed to a different cluster".to_string());
+        assert_eq!(9, cluster_mismatch.exit_code());
+        assert!(cluster_mismatch.hint().unwrap().contains("--ignore-cluster-mismatch"));
+
+        assert_eq!(1, H2okError::spec(Path::new("cluster.yaml"), "Unsupported kind 'Pod'.").exit_code());
+        assert_eq!(10, H2okError::Preflight("1 of 12 pre-flight checks failed.".to_string()).exit_code());
+        assert_eq!(11, H2okError::Locked("Deployment 'h2o' is locked by 'alice@laptop' running 'undeploy'.".to_string()).exit_code());
+    }
+}
@@ -0,0 +1,304 @@
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use k8s_openapi::api::apps::v1::StatefulSet;
+use k8s_openapi::api::core::v1::{Container, Namespace, Service};
+use k8s_openapi::api::networking::v1beta1::Ingress;
+use kube::{Api, Client, Error};
+use kube::api::{ListParams, Meta};
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use tokio::runtime::Runtime;
+
+use crate::k8s::{ANNOTATION_CREATED_AT, ANNOTATION_CREATOR, Deployment, DeploymentSpecification, ingress, LABEL_INSTANCE, LABEL_MANAGED_BY, MANAGED_BY};
+
+const MEMORY_PERCENTAGE_PATTERN: &str = "MaxRAMPercentage=([0-9]+)";
+
+/// Summary of an H2O deployment discovered in the Kubernetes cluster by the labels h2ok puts on every entity created.
+#[derive(Debug)]
+pub struct DeploymentSummary {
+    /// Name of the deployment.
+    pub name: String,
+    /// Namespace the deployment lives in.
+    pub namespace: String,
+    /// Count of H2O nodes requested.
+    pub num_h2o_nodes: i32,
+    /// CPUs requested by each H2O node.
+    pub cpu: Option<String>,
+    /// Memory requested by each H2O node.
+    pub memory: Option<String>,
+    /// Creation time of the H2O stateful set.
+    pub created: Option<DateTime<Utc>>,
+    /// Name of the user who created the deployment, if known.
+    pub creator: Option<String>,
+    /// URL the H2O cluster is exposed on by an ingress, if exposed.
+    pub url: Option<String>,
+}
+
+/// Returns label selector matching all Kubernetes entities created by h2ok.
+pub fn managed_by_selector() -> String {
+    return format!("{}={}", LABEL_MANAGED_BY, MANAGED_BY);
+}
+
+/// Returns label selector matching all Kubernetes entities created by h2ok for the deployment with given name.
+pub fn instance_selector(deployment_name: &str) -> String {
+    return format!("{},{}={}", managed_by_selector(), LABEL_INSTANCE, deployment_name);
+}
+
+/// Reason an existing H2O deployment could not be reconstructed from the Kubernetes cluster.
+#[derive(Debug)]
+pub enum AdoptionError {
+    /// There is no H2O stateful set for the deployment with the given name.
+    NotFound(String),
+    /// The H2O stateful set found does not have the structure h2ok creates.
+    Unrecognized(String),
+    /// Communication with the Kubernetes API failed.
+    Api(Error),
+}
+
+impl Display for AdoptionError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            AdoptionError::NotFound(reason) => { write!(f, "{}", reason) }
+            AdoptionError::Unrecognized(reason) => { write!(f, "{}", reason) }
+            AdoptionError::Api(error) => { write!(f, "Kubernetes API error: {}", error) }
+        };
+    }
+}
+
+impl From<Error> for AdoptionError {
+    fn from(error: Error) -> Self {
+        AdoptionError::Api(error)
+    }
+}
+
+/// Reconstructs a `Deployment` of an existing H2O cluster with the given `name` from the entities found in `namespace`.
+/// Entities are primarily searched for by labels h2ok puts on them. Deployments created before h2ok started labelling
+/// entities are found by the naming convention h2ok uses.
+pub fn adopt_deployment(client: &Client, name: &str, namespace: &str, kubeconfig_path: Option<PathBuf>) -> Result<Deployment, AdoptionError> {
+    let mut tokio_runtime: Runtime = tokio::runtime::Runtime::new().unwrap();
+    return tokio_runtime.block_on(adopt(client, name, namespace, kubeconfig_path));
+}
+
+/// Reconstructs a `Deployment` of every H2O deployment in `namespace` whose stateful set matches the given label `selector`.
+/// Only H2O deployments created by h2ok are considered.
+pub fn find_deployments(client: &Client, namespace: &str, selector: Option<&str>, kubeconfig_path: Option<PathBuf>) -> Result<Vec<Deployment>, AdoptionError> {
+    let mut tokio_runtime: Runtime = tokio::runtime::Runtime::new().unwrap();
+    let api: Api<StatefulSet> = Api::namespaced(client.clone(), namespace);
+    let label_selector: String = match selector {
+        None => { managed_by_selector() }
+        Some(selector) => { format!("{},{}", managed_by_selector(), selector) }
+    };
+    let list_params: ListParams = ListParams::default()
+        .labels(&label_selector);
+
+    let mut names: Vec<String> = tokio_runtime.block_on(api.list(&list_params))?.items.iter()
+        .filter_map(instance_name)
+        .collect();
+    names.sort();
+    names.dedup();
+
+    let mut deployments: Vec<Deployment> = vec!();
+    for name in names.iter() {
+        deployments.push(tokio_runtime.block_on(adopt(client, name, namespace, kubeconfig_path.clone()))?);
+    }
+    return Ok(deployments);
+}
+
+async fn adopt(client: &Client, name: &str, namespace: &str, kubeconfig_path: Option<PathBuf>) -> Result<Deployment, AdoptionError> {
+    let stateful_sets: Vec<StatefulSet> = find_entities(client, namespace, name, &format!("{}-stateful-set", name)).await?;
+    let services: Vec<Service> = find_entities(client, namespace, name, &format!("{}-service", name)).await?;
+    let ingresses: Vec<Ingress> = find_entities(client, namespace, name, &format!("{}-ingress", name)).await?;
+
+    let stateful_set: &StatefulSet = stateful_sets.first()
+        .ok_or_else(|| AdoptionError::NotFound(format!("No H2O deployment named '{}' found in namespace '{}'.", name, namespace)))?;
+    let specification: DeploymentSpecification = specification_from(stateful_set, name, namespace, kubeconfig_path)?;
+
+    let mut deployment: Deployment = Deployment::new(specification);
+    deployment.stateful_sets = stateful_sets;
+    deployment.services = services;
+    deployment.ingresses = ingresses;
+    deployment.namespace_created = namespace_created_for(client, namespace, name).await;
+    return Ok(deployment);
+}
+
+/// Returns true if the namespace has been created by h2ok for the H2O deployment with the given name, as recognized by its labels.
+/// If the namespace can not be read, e.g. due to missing permissions, it is not considered created by h2ok.
+async fn namespace_created_for(client: &Client, namespace: &str, deployment_name: &str) -> bool {
+    let api: Api<Namespace> = Api::all(client.clone());
+    return match api.get(namespace).await {
+        Ok(namespace) => {
+            let labels = namespace.meta().labels.as_ref();
+            labels.and_then(|labels| labels.get(LABEL_MANAGED_BY)).map(String::as_str) == Some(MANAGED_BY)
+                && instance_name(&namespace).as_deref() == Some(deployment_name)
+        }
+        Err(_) => { false }
+    };
+}
+
+/// Finds entities of H2O deployment with the given name by labels, falling back to the entity with `conventional_name`.
+async fn find_entities<K>(client: &Client, namespace: &str, deployment_name: &str, conventional_name: &str) -> Result<Vec<K>, Error>
+    where K: Clone + DeserializeOwned + Meta {
+    let api: Api<K> = Api::namespaced(client.clone(), namespace);
+    let list_params: ListParams = ListParams::default()
+        .labels(&instance_selector(deployment_name));
+    let labelled: Vec<K> = api.list(&list_params).await?.items;
+    if !labelled.is_empty() {
+        return Ok(labelled);
+    }
+
+    return match api.get(conventional_name).await {
+        Ok(entity) => { Ok(vec!(entity)) }
+        Err(Error::Api(response)) if response.code == 404 => { Ok(vec!()) }
+        Err(e) => { Err(e) }
+    };
+}
+
+/// Reconstructs deployment specification from the H2O stateful set - its replicas, H2O container resources,
+/// JVM memory percentage and the expected count of H2O nodes.
+fn specification_from(stateful_set: &StatefulSet, name: &str, namespace: &str, kubeconfig_path: Option<PathBuf>) -> Result<DeploymentSpecification, AdoptionError> {
+    let unrecognized = |what: &str| AdoptionError::Unrecognized(format!("Unable to determine {} of H2O deployment '{}' from stateful set '{}'.",
+                                                                        what, name, stateful_set.name()));
+    let spec = stateful_set.spec.as_ref().ok_or_else(|| unrecognized("specification"))?;
+    let container: &Container = spec.template.spec.as_ref()
+        .and_then(|pod_spec| pod_spec.containers.first())
+        .ok_or_else(|| unrecognized("H2O container"))?;
+
+    let limits = container.resources.as_ref()
+        .and_then(|resources| resources.limits.as_ref().or(resources.requests.as_ref()));
+    let memory: String = limits.and_then(|limits| limits.get("memory"))
+        .map(|quantity| quantity.0.clone())
+        .ok_or_else(|| unrecognized("memory"))?;
+    let num_cpu: u32 = limits.and_then(|limits| limits.get("cpu"))
+        .and_then(|quantity| parse_cpu(&quantity.0))
+        .ok_or_else(|| unrecognized("CPU count"))?;
+
+    let memory_percentage_regexp = Regex::new(MEMORY_PERCENTAGE_PATTERN).unwrap();
+    let memory_percentage: u8 = container.command.iter().flatten()
+        .chain(container.args.iter().flatten())
+        .find_map(|argument| memory_percentage_regexp.captures(argument))
+        .and_then(|captures| captures.get(1)?.as_str().parse::<u8>().ok())
+        .ok_or_else(|| unrecognized("JVM memory percentage"))?;
+
+    let num_h2o_nodes: u32 = container.env.iter().flatten()
+        .find(|env_var| env_var.name == "H2O_NODE_EXPECTED_COUNT")
+        .and_then(|env_var| env_var.value.as_ref()?.parse::<u32>().ok())
+        .or_else(|| spec.replicas.map(|replicas| replicas as u32))
+        .ok_or_else(|| unrecognized("count of H2O nodes"))?;
+
+    return Ok(DeploymentSpecification::new(name.to_string(), namespace.to_string(), memory_percentage, memory, num_cpu, num_h2o_nodes, kubeconfig_path));
+}
+
+/// Parses a Kubernetes CPU quantity, e.g. `2` or `1500m`, into a whole number of CPUs, rounding up.
+fn parse_cpu(quantity: &str) -> Option<u32> {
+    return match quantity.strip_suffix('m') {
+        Some(millicpus) => {
+            let millicpus: u32 = millicpus.parse::<u32>().ok()?;
+            Some(millicpus.div_ceil(1000))
+        }
+        None => { quantity.parse::<u32>().ok() }
+    };
+}
+
+/// Lists H2O deployments created by h2ok in the given `namespace`, or in all namespaces if no namespace is given.
+/// H2O deployments are discovered by their stateful sets.
+pub fn list_deployments(client: &Client, namespace: Option<&str>) -> Result<Vec<DeploymentSummary>, Error> {
+    let mut tokio_runtime: Runtime = tokio::runtime::Runtime::new().unwrap();
+    let (stateful_set_api, ingress_api): (Api<StatefulSet>, Api<Ingress>) = match namespace {
+        None => { (Api::all(client.clone()), Api::all(client.clone())) }
+        Some(namespace) => { (Api::namespaced(client.clone(), namespace), Api::namespaced(client.clone(), namespace)) }
+    };
+    let list_params: ListParams = ListParams::default()
+        .labels(&managed_by_selector());
+
+    let stateful_sets: Vec<StatefulSet> = tokio_runtime.block_on(stateful_set_api.list(&list_params))?.items;
+    let ingresses: Vec<Ingress> = tokio_runtime.block_on(ingress_api.list(&list_params))?.items;
+
+    let mut deployments: Vec<DeploymentSummary> = stateful_sets.iter()
+        .map(|stateful_set| summarize(stateful_set, &ingresses))
+        .collect();
+    deployments.sort_by(|a, b| (&a.namespace, &a.name).cmp(&(&b.namespace, &b.name)));
+    return Ok(deployments);
+}
+
+fn summarize(stateful_set: &StatefulSet, ingresses: &[Ingress]) -> DeploymentSummary {
+    let name: String = instance_name(stateful_set).unwrap_or_else(|| stateful_set.name());
+    let namespace: String = stateful_set.namespace().unwrap_or_default();
+    let spec = stateful_set.spec.as_ref();
+    let requests = spec
+        .and_then(|spec| spec.template.spec.as_ref())
+        .and_then(|pod_spec| pod_spec.containers.first())
+        .and_then(|container| container.resources.as_ref())
+        .and_then(|resources| resources.requests.as_ref());
+
+    let url: Option<String> = ingresses.iter()
+        .filter(|ingress| ingress.namespace().as_ref() == Some(&namespace) && instance_name(*ingress).as_ref() == Some(&name))
+        .find_map(ingress::url);
+
+    return DeploymentSummary {
+        num_h2o_nodes: spec.and_then(|spec| spec.replicas).unwrap_or(0),
+        cpu: requests.and_then(|requests| requests.get("cpu")).map(|quantity| quantity.0.clone()),
+        memory: requests.and_then(|requests| requests.get("memory")).map(|quantity| quantity.0.clone()),
+        created: created(stateful_set),
+        creator: annotation(stateful_set, ANNOTATION_CREATOR),
+        url,
+        name,
+        namespace,
+    };
+}
+
+/// Returns creation time of the entity as recorded by h2ok, falling back to the Kubernetes creation timestamp.
+fn created<K: Meta>(entity: &K) -> Option<DateTime<Utc>> {
+    return annotation(entity, ANNOTATION_CREATED_AT)
+        .and_then(|created_at| DateTime::parse_from_rfc3339(&created_at).ok())
+        .map(|created_at| created_at.with_timezone(&Utc))
+        .or_else(|| entity.meta().creation_timestamp.as_ref().map(|time| time.0));
+}
+
+/// Returns value of the given annotation of a Kubernetes entity, if present.
+fn annotation<K: Meta>(entity: &K, annotation_name: &str) -> Option<String> {
+    return entity.meta().annotations.as_ref()?
+        .get(annotation_name)
+        .cloned();
+}
+
+/// Returns name of the H2O deployment the given Kubernetes entity belongs to, if labelled by h2ok.
+pub fn instance_name<K: Meta>(entity: &K) -> Option<String> {
+    return entity.meta().labels.as_ref()?
+        .get(LABEL_INSTANCE)
+        .cloned();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use k8s_openapi::api::apps::v1::StatefulSet;
+
+    use crate::k8s::DeploymentSpecification;
+    use crate::k8s::templates;
+
+    #[test]
+    fn test_specification_from() {
+        let stateful_set: StatefulSet = templates::h2o_stateful_set("h2o-test", "h2o-namespace", "h2oai/h2o-open-source-k8s", "latest",
+                                                                    3, 70, "32Gi", 8);
+        let specification: DeploymentSpecification = super::specification_from(&stateful_set, "h2o-test", "h2o-namespace",
+                                                                                Some(PathBuf::from("kubeconfig.yaml"))).unwrap();
+        assert_eq!("h2o-test", specification.name);
+        assert_eq!("h2o-namespace", specification.namespace);
+        assert_eq!(70, specification.memory_percentage);
+        assert_eq!("32Gi", specification.memory);
+        assert_eq!(8, specification.num_cpu);
+        assert_eq!(3, specification.num_h2o_nodes);
+        assert_eq!(Some(PathBuf::from("kubeconfig.yaml")), specification.kubeconfig_path);
+    }
+
+    #[test]
+    fn test_parse_cpu() {
+        assert_eq!(Some(2), super::parse_cpu("2"));
+        assert_eq!(Some(2), super::parse_cpu("1500m"));
+        assert_eq!(Some(1), super::parse_cpu("1000m"));
+        assert!(super::parse_cpu("two").is_none());
+    }
+}
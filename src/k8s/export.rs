@@ -0,0 +1,226 @@
+use std::path::PathBuf;
+
+use kube::api::Meta;
+
+use crate::k8s::{ANNOTATION_CREATED_AT, ANNOTATION_CREATOR, ANNOTATION_PROTECTED, Deployment, H2O_IMAGE_NAME, H2O_IMAGE_TAG};
+use crate::k8s::{protection, templates};
+
+/// Format an H2O deployment is exported in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExportFormat {
+    /// A Helm chart parameterised by the count of H2O nodes, memory, CPUs and the docker image.
+    Helm,
+    /// A Kustomize base with the Kubernetes entities and a `kustomization.yaml`.
+    Kustomize,
+    /// A single multi-document YAML with the Kubernetes entities.
+    Yaml,
+}
+
+impl ExportFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        return match name {
+            "helm" => Some(ExportFormat::Helm),
+            "kustomize" => Some(ExportFormat::Kustomize),
+            "yaml" => Some(ExportFormat::Yaml),
+            _ => None
+        };
+    }
+
+    pub fn name(&self) -> &'static str {
+        return match self {
+            ExportFormat::Helm => { "helm" }
+            ExportFormat::Kustomize => { "kustomize" }
+            ExportFormat::Yaml => { "yaml" }
+        };
+    }
+}
+
+/// A single file of an exported H2O deployment.
+#[derive(Debug)]
+pub struct ExportedFile {
+    /// Path relative to the export directory.
+    pub path: PathBuf,
+    pub content: String,
+}
+
+impl ExportedFile {
+    fn new(path: &str, content: String) -> Self {
+        ExportedFile { path: PathBuf::from(path), content }
+    }
+}
+
+/// Exports the given H2O deployment in the given format. The Kubernetes entities are rendered from the very same templates
+/// `deploy` uses, so the exported entities replace the deployed ones in place. An ingress is only exported if the deployment has one.
+/// The namespace is left to be managed by the tool the deployment is exported for.
+pub fn export(deployment: &Deployment, format: ExportFormat) -> Vec<ExportedFile> {
+    return match format {
+        ExportFormat::Helm => { helm_chart(deployment) }
+        ExportFormat::Kustomize => { kustomize_base(deployment) }
+        ExportFormat::Yaml => {
+            let documents: Vec<String> = entity_templates(deployment).iter()
+                .map(|(_, template)| fill_values(template, deployment))
+                .collect();
+            vec!(ExportedFile::new(&format!("{}.yaml", deployment.specification.name), documents.join("---\n")))
+        }
+    };
+}
+
+/// Templates of the Kubernetes entities of the deployment, each with the name of the file it is exported to.
+/// The stateful set of a deployment protected against deletion keeps the protection annotation.
+fn entity_templates(deployment: &Deployment) -> Vec<(&'static str, String)> {
+    let mut stateful_set_template: String = fill_creation_metadata(templates::STATEFUL_SET_TEMPLATE, deployment);
+    if deployment.stateful_sets.iter().any(protection::is_protected) {
+        stateful_set_template = stateful_set_template.replacen("\n  annotations:\n",
+                                                               &format!("\n  annotations:\n    {}: 'true'\n", ANNOTATION_PROTECTED), 1);
+    }
+    let mut entity_templates: Vec<(&'static str, String)> = vec!(
+        ("service.yaml", fill_creation_metadata(templates::SERVICE_TEMPLATE, deployment)),
+        ("statefulset.yaml", stateful_set_template),
+    );
+    if !deployment.ingresses.is_empty() {
+        entity_templates.push(("ingress.yaml", fill_creation_metadata(templates::INGRESS_TEMPLATE, deployment)));
+    }
+    return entity_templates;
+}
+
+/// Fills the creator and creation time of the deployment as recorded in the deployment descriptor, so the exported entities
+/// do not differ from the deployed ones. If not recorded, the current user and time are used instead.
+fn fill_creation_metadata(template: &str, deployment: &Deployment) -> String {
+    let annotations = deployment.stateful_sets.first().and_then(|stateful_set| stateful_set.meta().annotations.clone()).unwrap_or_default();
+    let mut template: String = template.trim_start().to_string();
+    if let Some(creator) = annotations.get(ANNOTATION_CREATOR) {
+        template = template.replace("<creator>", &creator.replace("'", "''"));
+    }
+    if let Some(created_at) = annotations.get(ANNOTATION_CREATED_AT) {
+        template = template.replace("<created-at>", created_at);
+    }
+    return templates::fill_creation_metadata(&template);
+}
+
+/// Fills the placeholders of the template with the values from the deployment specification.
+fn fill_values(template: &str, deployment: &Deployment) -> String {
+    let specification = &deployment.specification;
+    return template.replace("<name>", &specification.name)
+        .replace("<namespace>", &specification.namespace)
+        .replace("<docker-img-name>", H2O_IMAGE_NAME)
+        .replace("<docker-img-tag>", H2O_IMAGE_TAG)
+        .replace("<nodes>", &specification.num_h2o_nodes.to_string())
+        .replace("<memory-percentage>", &specification.memory_percentage.to_string())
+        .replace("<memory>", &specification.memory)
+        .replace("<num-cpu>", &specification.num_cpu.to_string());
+}
+
+const CHART_TEMPLATE: &str = r#"apiVersion: v2
+name: <name>
+description: H2O cluster '<name>' exported by h2ok.
+type: application
+version: 0.1.0
+appVersion: '<docker-img-tag>'
+"#;
+
+const VALUES_TEMPLATE: &str = r#"# Number of H2O nodes, each running in a separate pod.
+nodes: <nodes>
+# Memory of each H2O node - a pod memory request and limit, e.g. 4Gi.
+memory: <memory>
+# Number of CPUs of each H2O node - a pod CPU request and limit.
+cpus: <num-cpu>
+# Percentage of the memory allocated by the JVM running H2O inside the container.
+memoryPercentage: <memory-percentage>
+image:
+  repository: <docker-img-name>
+  tag: '<docker-img-tag>'
+"#;
+
+/// Exports the deployment as a Helm chart. Name of the H2O deployment is kept, so the chart manages the very same entities.
+fn helm_chart(deployment: &Deployment) -> Vec<ExportedFile> {
+    let mut files: Vec<ExportedFile> = vec!(
+        ExportedFile::new("Chart.yaml", fill_values(CHART_TEMPLATE, deployment)),
+        ExportedFile::new("values.yaml", fill_values(VALUES_TEMPLATE, deployment)),
+    );
+    for (file_name, template) in entity_templates(deployment).iter() {
+        let helm_template: String = template.replace("<name>", &deployment.specification.name)
+            .replace("<namespace>", "{{ .Release.Namespace }}")
+            .replace("<docker-img-name>", "{{ .Values.image.repository }}")
+            .replace("<docker-img-tag>", "{{ .Values.image.tag }}")
+            .replace("<nodes>", "{{ .Values.nodes }}")
+            .replace("<memory-percentage>", "{{ .Values.memoryPercentage }}")
+            .replace("<memory>", "{{ .Values.memory }}")
+            .replace("<num-cpu>", "{{ .Values.cpus }}");
+        files.push(ExportedFile::new(&format!("templates/{}", file_name), helm_template));
+    }
+    return files;
+}
+
+/// Exports the deployment as a Kustomize base. The docker image is listed in `kustomization.yaml`, so overlays may change it.
+fn kustomize_base(deployment: &Deployment) -> Vec<ExportedFile> {
+    let entity_templates: Vec<(&'static str, String)> = entity_templates(deployment);
+    let mut kustomization: String = format!("apiVersion: kustomize.config.k8s.io/v1beta1\nkind: Kustomization\nnamespace: {}\nresources:\n",
+                                            deployment.specification.namespace);
+    for (file_name, _) in entity_templates.iter() {
+        kustomization.push_str(&format!("- {}\n", file_name));
+    }
+    kustomization.push_str(&format!("images:\n- name: {}\n  newTag: '{}'\n", H2O_IMAGE_NAME, H2O_IMAGE_TAG));
+
+    let mut files: Vec<ExportedFile> = vec!(ExportedFile::new("kustomization.yaml", kustomization));
+    files.extend(entity_templates.iter()
+        .map(|(file_name, template)| ExportedFile::new(file_name, fill_values(template, deployment))));
+    return files;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use k8s_openapi::api::apps::v1::StatefulSet;
+    use k8s_openapi::api::core::v1::Service;
+
+    use crate::k8s::{Deployment, DeploymentSpecification, protection, templates};
+
+    use super::{ExportedFile, ExportFormat};
+
+    fn content<'a>(files: &'a [ExportedFile], path: &str) -> &'a str {
+        return &files.iter().find(|file| file.path == Path::new(path)).unwrap().content;
+    }
+
+    #[test]
+    fn test_export() {
+        let deployment: Deployment = Deployment::new(DeploymentSpecification::new("h2o-test".to_string(), "h2o".to_string(), 60,
+                                                                                  "4Gi".to_string(), 2, 3, None));
+
+        let chart: Vec<ExportedFile> = super::export(&deployment, ExportFormat::Helm);
+        assert_eq!(4, chart.len());
+        assert!(content(&chart, "values.yaml").contains("nodes: 3"));
+        assert!(content(&chart, "values.yaml").contains("memory: 4Gi"));
+        let stateful_set_template: &str = content(&chart, "templates/statefulset.yaml");
+        assert!(stateful_set_template.contains("replicas: {{ .Values.nodes }}"));
+        assert!(stateful_set_template.contains("namespace: {{ .Release.Namespace }}"));
+        assert!(stateful_set_template.contains("name: h2o-test-stateful-set"));
+        assert!(!stateful_set_template.contains("<"));
+
+        let base: Vec<ExportedFile> = super::export(&deployment, ExportFormat::Kustomize);
+        assert!(content(&base, "kustomization.yaml").contains("namespace: h2o\n"));
+        let stateful_set: StatefulSet = serde_yaml::from_str(content(&base, "statefulset.yaml")).unwrap();
+        assert!(!protection::is_protected(&stateful_set));
+        assert_eq!(Some(3), stateful_set.spec.unwrap().replicas);
+
+        let yaml: Vec<ExportedFile> = super::export(&deployment, ExportFormat::Yaml);
+        assert_eq!(Path::new("h2o-test.yaml"), yaml[0].path);
+        let service: Service = serde_yaml::from_str(yaml[0].content.split("---\n").next().unwrap()).unwrap();
+        assert_eq!(Some("h2o".to_string()), service.metadata.namespace);
+    }
+
+    #[test]
+    fn test_export_protected() {
+        let mut deployment: Deployment = Deployment::new(DeploymentSpecification::new("h2o-test".to_string(), "h2o".to_string(), 60,
+                                                                                      "4Gi".to_string(), 2, 3, None));
+        let mut recorded: StatefulSet = templates::h2o_stateful_set("h2o-test", "h2o", "h2oai/h2o-open-source-k8s", "latest", 3, 60, "4Gi", 2);
+        protection::protect_template(&mut recorded);
+        deployment.stateful_sets.push(recorded);
+
+        let chart: Vec<ExportedFile> = super::export(&deployment, ExportFormat::Helm);
+        assert!(content(&chart, "templates/statefulset.yaml").contains("h2ok/protected: 'true'"));
+        let base: Vec<ExportedFile> = super::export(&deployment, ExportFormat::Kustomize);
+        let stateful_set: StatefulSet = serde_yaml::from_str(content(&base, "statefulset.yaml")).unwrap();
+        assert!(protection::is_protected(&stateful_set));
+    }
+}
@@ -0,0 +1,85 @@
+use k8s_openapi::api::core::v1::Namespace;
+use kube::Error;
+use serde::{Deserialize, Serialize};
+use tokio::runtime::Runtime;
+
+use crate::k8s::ConnectedCluster;
+use crate::k8s::retry;
+use crate::k8s::retry::{RetryPolicy, ThrottledApi};
+
+/// Namespace present in every Kubernetes cluster. Its UID is used as the identifier of the cluster.
+const CLUSTER_ID_NAMESPACE: &str = "kube-system";
+
+/// Details identifying the Kubernetes cluster an H2O deployment has been deployed to. Recorded in the deployment descriptor,
+/// so the deployment is never modified in a different cluster by accident.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ClusterIdentity {
+    /// URL of the Kubernetes API server.
+    pub api_server: String,
+    /// Name of the kubeconfig context used. None if connected using in-cluster configuration.
+    pub context: Option<String>,
+    /// UID of the `kube-system` namespace. None if the user is not permitted to read it.
+    pub cluster_uid: Option<String>,
+}
+
+/// Identifies the Kubernetes cluster connected to. Transient errors are retried according to the `retry_policy`.
+pub fn identify(cluster: &ConnectedCluster, retry_policy: &RetryPolicy) -> Result<ClusterIdentity, Error> {
+    let mut tokio_runtime: Runtime = tokio::runtime::Runtime::new().unwrap();
+    let api: ThrottledApi<Namespace> = ThrottledApi::all(cluster.client.clone());
+    let description: String = format!("get Namespace '{}'", CLUSTER_ID_NAMESPACE);
+    let cluster_uid: Option<String> = match tokio_runtime.block_on(retry::with_retry(retry_policy, &description, || api.get(CLUSTER_ID_NAMESPACE))) {
+        Ok(namespace) => { namespace.metadata.uid }
+        Err(Error::Api(response)) if response.code == 403 || response.code == 404 => { None }
+        Err(e) => { return Err(e); }
+    };
+    return Ok(ClusterIdentity { api_server: cluster.api_server.clone(), context: cluster.context.clone(), cluster_uid });
+}
+
+/// Compares the `recorded` cluster identity with the `current` one. Returns a description of each difference found.
+/// Details not known for both of the clusters are not compared.
+pub fn differences(recorded: &ClusterIdentity, current: &ClusterIdentity) -> Vec<String> {
+    let mut differences: Vec<String> = vec!();
+    if recorded.api_server != current.api_server {
+        differences.push(format!("API server '{}' recorded, '{}' used now", recorded.api_server, current.api_server));
+    }
+    if let (Some(recorded_context), Some(current_context)) = (&recorded.context, &current.context) {
+        if recorded_context != current_context {
+            differences.push(format!("context '{}' recorded, '{}' used now", recorded_context, current_context));
+        }
+    }
+    if let (Some(recorded_uid), Some(current_uid)) = (&recorded.cluster_uid, &current.cluster_uid) {
+        if recorded_uid != current_uid {
+            differences.push(format!("cluster UID '{}' recorded, '{}' found now", recorded_uid, current_uid));
+        }
+    }
+    return differences;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClusterIdentity;
+
+    #[test]
+    fn test_differences() {
+        let recorded: ClusterIdentity = ClusterIdentity {
+            api_server: "https://10.0.0.1:6443/".to_string(),
+            context: Some("production".to_string()),
+            cluster_uid: Some("5f6b7c8d-0000-4000-8000-000000000001".to_string()),
+        };
+        assert!(super::differences(&recorded, &recorded.clone()).is_empty());
+
+        let other_context: ClusterIdentity = ClusterIdentity { context: Some("staging".to_string()), ..recorded.clone() };
+        assert_eq!(1, super::differences(&recorded, &other_context).len());
+
+        let other_cluster: ClusterIdentity = ClusterIdentity {
+            api_server: "https://10.0.0.2:6443/".to_string(),
+            context: Some("production".to_string()),
+            cluster_uid: Some("5f6b7c8d-0000-4000-8000-000000000002".to_string()),
+        };
+        assert_eq!(2, super::differences(&recorded, &other_cluster).len());
+
+        // The UID is not known if the user is not permitted to read the kube-system namespace
+        let unknown_uid: ClusterIdentity = ClusterIdentity { cluster_uid: None, ..recorded.clone() };
+        assert!(super::differences(&recorded, &unknown_uid).is_empty());
+    }
+}
@@ -17,4 +17,8 @@ pub fn any_path(ingress: &Ingress) -> Option<String> {
         .http.as_ref()?
         .paths.last()?
         .path.clone();
-}
\ No newline at end of file
+}
+/// Returns URL the H2O cluster is exposed on by the given ingress, if the ingress already has an IP assigned.
+pub fn url(ingress: &Ingress) -> Option<String> {
+    return Some(format!("http://{}:80{}", any_ip(ingress)?, any_path(ingress)?));
+}
@@ -0,0 +1,262 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::thread::JoinHandle;
+
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use k8s_openapi::api::coordination::v1::{Lease, LeaseSpec};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::MicroTime;
+use kube::{Api, Client, Error};
+use kube::api::{DeleteParams, Meta, PostParams};
+use tokio::runtime::Runtime;
+
+use crate::k8s::retry;
+use crate::k8s::retry::{RetryPolicy, ThrottledApi, with_retry};
+use crate::k8s::templates;
+
+/// Annotation of the lease recording the h2ok command holding it.
+pub const ANNOTATION_OPERATION: &str = "h2ok/operation";
+/// Time after which a lease not released, e.g. by an h2ok process killed, is considered abandoned and may be taken over.
+pub const LEASE_DURATION_SECONDS: i32 = 900;
+/// Interval the lease is renewed in while held, well within its duration, so operations of any length keep it.
+const RENEW_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Who holds a lock of a deployment, as reported to the users refused to take it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LockHolder {
+    /// Identity of the holder, `<user>@<host>` of the h2ok process.
+    pub identity: String,
+    /// h2ok command holding the lock, e.g. `undeploy`. None if not known.
+    pub operation: Option<String>,
+    /// Time the lock has been taken at. None if not known.
+    pub since: Option<DateTime<Utc>>,
+}
+
+impl Display for LockHolder {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "'{}'", self.identity)?;
+        if let Some(operation) = &self.operation {
+            write!(f, " running '{}'", operation)?;
+        }
+        if let Some(since) = &self.since {
+            write!(f, " since {}", since.to_rfc3339_opts(SecondsFormat::Secs, true))?;
+        }
+        return Ok(());
+    }
+}
+
+/// Reasons a lease could not be acquired.
+#[derive(Debug)]
+pub enum LeaseError {
+    /// The lease is held by someone else.
+    Held(LockHolder),
+    Api(Error),
+}
+
+/// Lease of an H2O deployment in the Kubernetes cluster, preventing concurrent changes of the deployment by multiple h2ok processes.
+/// Renewed in the background while held, released once dropped.
+pub struct DeploymentLease {
+    client: Client,
+    name: String,
+    namespace: String,
+    /// Resource version of the lease as last acquired or renewed. A lease modified since then has been taken over by someone else.
+    resource_version: Arc<Mutex<Option<String>>>,
+    /// Stops the renewal once dropped.
+    stop_renewal: Option<Sender<()>>,
+    renewal: Option<JoinHandle<()>>,
+}
+
+impl DeploymentLease {
+    fn new(client: Client, name: String, namespace: String, resource_version: Option<String>) -> Self {
+        let resource_version: Arc<Mutex<Option<String>>> = Arc::new(Mutex::new(resource_version));
+        let (stop_renewal, stopped): (Sender<()>, Receiver<()>) = std::sync::mpsc::channel();
+        let renewal: JoinHandle<()> = {
+            let (client, name, namespace, resource_version) = (client.clone(), name.clone(), namespace.clone(), resource_version.clone());
+            std::thread::spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(RENEW_INTERVAL) {
+                    let mut current: Option<String> = resource_version.lock().unwrap().clone();
+                    match renew(&client, &name, &namespace, &mut current) {
+                        Ok(true) => { *resource_version.lock().unwrap() = current; }
+                        Ok(false) => {
+                            eprintln!("Lease '{}' has been taken over by someone else.", name);
+                            return;
+                        }
+                        Err(e) => { eprintln!("Unable to renew lease '{}'. Reason: {}", name, e); }
+                    }
+                }
+            })
+        };
+        return DeploymentLease { client, name, namespace, resource_version, stop_renewal: Some(stop_renewal), renewal: Some(renewal) };
+    }
+}
+
+impl Drop for DeploymentLease {
+    fn drop(&mut self) {
+        self.stop_renewal.take();
+        if let Some(renewal) = self.renewal.take() {
+            let _ = renewal.join();
+        }
+        let resource_version: Option<String> = self.resource_version.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone();
+        if let Err(e) = release(&self.client, &self.name, &self.namespace, &resource_version) {
+            eprintln!("Unable to release lease '{}'. It expires in {} seconds. Reason: {}", self.name, LEASE_DURATION_SECONDS, e);
+        }
+    }
+}
+
+/// Name of the lease locking the deployment with the given name.
+pub fn lease_name(deployment_name: &str) -> String {
+    return format!("{}-lock", deployment_name);
+}
+
+/// Identity of this h2ok process recorded as the lock holder - `<user>@<host>`.
+pub fn holder_identity() -> String {
+    let user: String = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string());
+    return format!("{}@{}", user, gethostname::gethostname().to_string_lossy());
+}
+
+/// Acquires the lease of the deployment with the given name for the `operation`, e.g. `undeploy`. An expired lease is taken over.
+/// If the lease is held by someone else, the holder is returned as `LeaseError::Held`.
+pub fn acquire(client: &Client, deployment_name: &str, namespace: &str, operation: &str, retry_policy: &RetryPolicy) -> Result<DeploymentLease, LeaseError> {
+    let mut tokio_runtime: Runtime = tokio::runtime::Runtime::new().unwrap();
+    let api: ThrottledApi<Lease> = ThrottledApi::namespaced(client.clone(), namespace);
+    let name: String = lease_name(deployment_name);
+    let identity: String = holder_identity();
+    let mut lease: Lease = lease(deployment_name, namespace, &identity, operation, Utc::now());
+    let description: String = format!("acquire Lease '{}'", name);
+
+    // Ok(Err(holder)) if the lease is held by someone else.
+    let acquired: Result<Result<Lease, LockHolder>, Error> = tokio_runtime.block_on(async {
+        return match with_retry(retry_policy, &description, || api.get(&name)).await {
+            Ok(existing) => {
+                if let Some(holder) = active_holder(&existing, Utc::now()) {
+                    return Ok(Err(holder));
+                }
+                lease.metadata.resource_version = existing.metadata.resource_version.clone();
+                lease.spec.as_mut().unwrap().lease_transitions = Some(existing.spec.and_then(|spec| spec.lease_transitions).unwrap_or(0) + 1);
+                api.replace(&name, &PostParams::default(), &lease).await.map(Ok).map_err(retry::into_error)
+            }
+            Err(Error::Api(response)) if response.code == 404 => {
+                api.create(&PostParams::default(), &lease).await.map(Ok).map_err(retry::into_error)
+            }
+            Err(e) => { Err(e) }
+        };
+    });
+    return match acquired {
+        Ok(Ok(acquired)) => {
+            Ok(DeploymentLease::new(client.clone(), name, namespace.to_string(), acquired.metadata.resource_version))
+        }
+        Ok(Err(holder)) => { Err(LeaseError::Held(holder)) }
+        // Someone else has acquired the lease in the meantime.
+        Err(Error::Api(response)) if response.code == 409 => {
+            let holder: Option<LockHolder> = tokio_runtime.block_on(api.get(&name)).ok()
+                .and_then(|existing| active_holder(&existing, Utc::now()));
+            Err(LeaseError::Held(holder.unwrap_or_else(|| LockHolder { identity: "unknown".to_string(), operation: None, since: None })))
+        }
+        Err(e) => { Err(LeaseError::Api(e)) }
+    };
+}
+
+/// Builds the lease of the given deployment held by `identity` from `now` on.
+fn lease(deployment_name: &str, namespace: &str, identity: &str, operation: &str, now: DateTime<Utc>) -> Lease {
+    let mut lease: Lease = templates::h2o_lease(deployment_name, namespace);
+    lease.metadata.annotations.get_or_insert_with(BTreeMap::new)
+        .insert(ANNOTATION_OPERATION.to_string(), operation.to_string());
+    lease.spec = Some(LeaseSpec {
+        holder_identity: Some(identity.to_string()),
+        acquire_time: Some(MicroTime(now)),
+        renew_time: Some(MicroTime(now)),
+        lease_duration_seconds: Some(LEASE_DURATION_SECONDS),
+        lease_transitions: Some(0),
+    });
+    return lease;
+}
+
+/// Returns the holder of the given lease, if it is held and has not expired at `now`.
+fn active_holder(lease: &Lease, now: DateTime<Utc>) -> Option<LockHolder> {
+    let spec: &LeaseSpec = lease.spec.as_ref()?;
+    let identity: String = spec.holder_identity.clone().filter(|identity| !identity.is_empty())?;
+    let renewed: DateTime<Utc> = spec.renew_time.as_ref().or(spec.acquire_time.as_ref())?.0;
+    let duration: Duration = Duration::seconds(spec.lease_duration_seconds.unwrap_or(LEASE_DURATION_SECONDS) as i64);
+    if renewed + duration < now {
+        return None;
+    }
+    return Some(LockHolder {
+        identity,
+        operation: lease.meta().annotations.as_ref().and_then(|annotations| annotations.get(ANNOTATION_OPERATION)).cloned(),
+        since: spec.acquire_time.as_ref().map(|acquire_time| acquire_time.0),
+    });
+}
+
+/// Renews the lease held, updating its `resource_version`. Returns false if the lease has been taken over by someone else
+/// or removed in the meantime.
+fn renew(client: &Client, name: &str, namespace: &str, resource_version: &mut Option<String>) -> Result<bool, Error> {
+    let mut tokio_runtime: Runtime = tokio::runtime::Runtime::new().unwrap();
+    let api: Api<Lease> = Api::namespaced(client.clone(), namespace);
+    let mut lease: Lease = match tokio_runtime.block_on(api.get(name)) {
+        Ok(lease) => { lease }
+        Err(Error::Api(response)) if response.code == 404 => { return Ok(false); }
+        Err(e) => { return Err(e); }
+    };
+    if &lease.metadata.resource_version != resource_version {
+        return Ok(false);
+    }
+    if let Some(spec) = lease.spec.as_mut() {
+        spec.renew_time = Some(MicroTime(Utc::now()));
+    }
+    return match tokio_runtime.block_on(api.replace(name, &PostParams::default(), &lease)) {
+        Ok(renewed) => {
+            *resource_version = renewed.metadata.resource_version;
+            Ok(true)
+        }
+        Err(Error::Api(response)) if response.code == 409 => { Ok(false) }
+        Err(e) => { Err(e) }
+    };
+}
+
+/// Deletes the lease, unless it has been taken over by someone else in the meantime. A lease already gone is ignored.
+fn release(client: &Client, name: &str, namespace: &str, resource_version: &Option<String>) -> Result<(), Error> {
+    let mut tokio_runtime: Runtime = tokio::runtime::Runtime::new().unwrap();
+    let api: Api<Lease> = Api::namespaced(client.clone(), namespace);
+    let existing: Lease = match tokio_runtime.block_on(api.get(name)) {
+        Ok(existing) => { existing }
+        Err(Error::Api(response)) if response.code == 404 => { return Ok(()); }
+        Err(e) => { return Err(e); }
+    };
+    if &existing.metadata.resource_version != resource_version {
+        return Ok(());
+    }
+    return match tokio_runtime.block_on(api.delete(name, &DeleteParams::default())) {
+        Ok(_) => { Ok(()) }
+        Err(Error::Api(response)) if response.code == 404 => { Ok(()) }
+        Err(e) => { Err(e) }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+    use k8s_openapi::api::coordination::v1::Lease;
+
+    use super::{LEASE_DURATION_SECONDS, LockHolder};
+
+    #[test]
+    fn test_active_holder() {
+        let acquired = Utc.with_ymd_and_hms(2020, 9, 1, 12, 0, 0).unwrap();
+        let lease: Lease = super::lease("h2o-test", "h2o", "alice@laptop", "undeploy", acquired);
+        assert_eq!(Some("h2o-test-lock".to_string()), lease.metadata.name);
+
+        let holder: LockHolder = super::active_holder(&lease, acquired + Duration::seconds(60)).unwrap();
+        assert_eq!("alice@laptop", holder.identity);
+        assert_eq!(Some("undeploy".to_string()), holder.operation);
+        assert_eq!("'alice@laptop' running 'undeploy' since 2020-09-01T12:00:00Z", holder.to_string());
+
+        // An abandoned lease may be taken over.
+        assert!(super::active_holder(&lease, acquired + Duration::seconds(LEASE_DURATION_SECONDS as i64 + 1)).is_none());
+        let mut released: Lease = lease;
+        released.spec.as_mut().unwrap().holder_identity = None;
+        assert!(super::active_holder(&released, acquired).is_none());
+    }
+}
@@ -0,0 +1,168 @@
+use std::fmt::Debug;
+
+use k8s_openapi::api::apps::v1::StatefulSet;
+use k8s_openapi::api::core::v1::{Namespace, Service};
+use k8s_openapi::api::networking::v1beta1::Ingress;
+use kube::{Client, Error};
+use kube::api::{DeleteParams, Meta, PropagationPolicy};
+use serde::de::DeserializeOwned;
+
+use crate::k8s::ANNOTATION_CREATED_AT;
+use crate::k8s::retry;
+use crate::k8s::retry::{RetryPolicy, ThrottledApi};
+
+/// Kind of a Kubernetes entity created during a deployment.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EntityKind {
+    Namespace,
+    Service,
+    StatefulSet,
+    Ingress,
+}
+
+/// A single Kubernetes entity whose creation has been requested.
+#[derive(Clone, Debug)]
+pub struct LedgerEntry {
+    pub kind: EntityKind,
+    pub name: String,
+    /// Value of the `h2ok/created-at` annotation of the entity submitted. Identifies entities created by this deployment
+    /// when the outcome of the creation request is unknown.
+    pub created_at: Option<String>,
+    /// UID assigned by the Kubernetes cluster. None if the creation request has not been confirmed (yet).
+    pub uid: Option<String>,
+}
+
+impl LedgerEntry {
+    /// Returns true if the given entity is the one submitted for creation, recognized by its `h2ok/created-at` annotation.
+    pub fn is_submitted<K: Meta>(&self, entity: &K) -> bool {
+        let created_at: Option<&String> = entity.meta().annotations.as_ref()
+            .and_then(|annotations| annotations.get(ANNOTATION_CREATED_AT));
+        return self.created_at.is_some() && created_at == self.created_at.as_ref();
+    }
+}
+
+/// Record of all the Kubernetes entities created during a single deployment, used to roll the deployment back.
+/// Each entity is recorded before its creation is requested, so an entity created by a request interrupted midway is not leaked.
+#[derive(Debug)]
+pub struct Ledger {
+    namespace: String,
+    entries: Vec<LedgerEntry>,
+}
+
+impl Ledger {
+    pub fn new(namespace: &str) -> Self {
+        Ledger { namespace: namespace.to_string(), entries: vec!() }
+    }
+
+    /// Records an intent to create the given entity. Returns index of the entry to confirm or discard it with later.
+    pub fn record<K: Meta>(&mut self, kind: EntityKind, entity: &K) -> usize {
+        let created_at: Option<String> = entity.meta().annotations.as_ref()
+            .and_then(|annotations| annotations.get(ANNOTATION_CREATED_AT))
+            .cloned();
+        self.entries.push(LedgerEntry { kind, name: entity.name(), created_at, uid: None });
+        return self.entries.len() - 1;
+    }
+
+    /// Confirms the entity recorded under the given index has been created.
+    pub fn confirm<K: Meta>(&mut self, index: usize, created_entity: &K) {
+        self.entries[index].uid = created_entity.meta().uid.clone();
+    }
+
+    /// Discards the entity recorded under the given index, as the Kubernetes cluster refused to create it.
+    pub fn discard(&mut self, index: usize) {
+        self.entries.remove(index);
+    }
+
+    pub fn entries(&self) -> &[LedgerEntry] {
+        return &self.entries;
+    }
+
+    /// Deletes all the recorded entities in reverse order of their creation. Entities already gone are treated as deleted.
+    /// Returns a reason for each entity that could not be deleted. Transient errors are retried according to the `retry_policy`.
+    pub async fn rollback(&mut self, client: &Client, retry_policy: &RetryPolicy) -> Vec<String> {
+        let mut rollback_errors: Vec<String> = vec!();
+        while let Some(entry) = self.entries.pop() {
+            let result: Result<(), Error> = match entry.kind {
+                EntityKind::Namespace => {
+                    rollback_entry::<Namespace>(&ThrottledApi::all(client.clone()), &entry, retry_policy).await
+                }
+                EntityKind::Service => {
+                    rollback_entry::<Service>(&ThrottledApi::namespaced(client.clone(), &self.namespace), &entry, retry_policy).await
+                }
+                EntityKind::StatefulSet => {
+                    rollback_entry::<StatefulSet>(&ThrottledApi::namespaced(client.clone(), &self.namespace), &entry, retry_policy).await
+                }
+                EntityKind::Ingress => {
+                    rollback_entry::<Ingress>(&ThrottledApi::namespaced(client.clone(), &self.namespace), &entry, retry_policy).await
+                }
+            };
+            if let Err(e) = result {
+                rollback_errors.push(format!("{:?} '{}'. Reason: {}", entry.kind, entry.name, e));
+            }
+        }
+        return rollback_errors;
+    }
+}
+
+/// Deletes the entity described by the given ledger entry. The entity is only deleted if it is the one created
+/// by the deployment, so an entity with the same name created by someone else meanwhile is left untouched.
+async fn rollback_entry<K>(api: &ThrottledApi<K>, entry: &LedgerEntry, retry_policy: &RetryPolicy) -> Result<(), Error>
+    where K: Clone + DeserializeOwned + Meta + Debug {
+    let entity: K = match retry::with_retry(retry_policy, &format!("get {:?} '{}'", entry.kind, entry.name), || api.get(&entry.name)).await {
+        Ok(entity) => { entity }
+        Err(Error::Api(response)) if response.code == 404 => { return Ok(()); }
+        Err(e) => { return Err(e); }
+    };
+    let created_by_deployment: bool = match &entry.uid {
+        Some(uid) => { entity.meta().uid.as_ref() == Some(uid) }
+        // Outcome of the creation request is unknown - compare with the entity submitted.
+        None => { entry.is_submitted(&entity) }
+    };
+    if !created_by_deployment {
+        return Ok(());
+    }
+
+    let delete_params: DeleteParams = DeleteParams {
+        propagation_policy: Some(PropagationPolicy::Background),
+        ..DeleteParams::default()
+    };
+    return match retry::with_retry(retry_policy, &format!("delete {:?} '{}'", entry.kind, entry.name), || api.delete(&entry.name, &delete_params)).await {
+        Ok(_) => { Ok(()) }
+        Err(Error::Api(response)) if response.code == 404 => { Ok(()) }
+        Err(e) => { Err(e) }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::core::v1::Service;
+
+    use crate::k8s::ANNOTATION_CREATED_AT;
+    use crate::k8s::templates;
+
+    use super::{EntityKind, Ledger};
+
+    #[test]
+    fn test_record_confirm_discard() {
+        let mut ledger: Ledger = Ledger::new("default");
+        let service: Service = templates::h2o_service("h2o-test", "default");
+        let index: usize = ledger.record(EntityKind::Service, &service);
+        assert_eq!(1, ledger.entries().len());
+        assert_eq!("h2o-test-service", ledger.entries()[0].name);
+        assert!(ledger.entries()[0].created_at.is_some());
+        assert!(ledger.entries()[0].uid.is_none());
+
+        let mut created_service: Service = service.clone();
+        created_service.metadata.uid = Some("0b9e8f3c-6d4a-4c1e-9f0e-1d2c3b4a5e6f".to_string());
+        ledger.confirm(index, &created_service);
+        assert_eq!(Some("0b9e8f3c-6d4a-4c1e-9f0e-1d2c3b4a5e6f".to_string()), ledger.entries()[0].uid);
+
+        let mut other_service: Service = service.clone();
+        other_service.metadata.annotations.as_mut().unwrap().insert(ANNOTATION_CREATED_AT.to_string(), "2020-09-01T12:00:00Z".to_string());
+        let index: usize = ledger.record(EntityKind::Service, &service);
+        assert!(ledger.entries()[index].is_submitted(&created_service));
+        assert!(!ledger.entries()[index].is_submitted(&other_service));
+        ledger.discard(index);
+        assert_eq!(1, ledger.entries().len());
+    }
+}
@@ -1,39 +1,152 @@
 extern crate futures;
 extern crate kube;
 
+use std::fmt::{Display, Formatter};
 use std::path::{Path, PathBuf};
 
 use k8s_openapi::api::apps::v1::StatefulSet;
-use k8s_openapi::api::core::v1::Service;
+use k8s_openapi::api::core::v1::{Namespace, PersistentVolumeClaim, Secret, Service};
 use k8s_openapi::api::networking::v1beta1::Ingress;
+use http::HeaderValue;
 use kube::Client;
 use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
 use tokio::runtime::Runtime;
 
+use crate::k8s::identity::ClusterIdentity;
 use crate::k8s::ingress::any_ip;
+use crate::k8s::ledger::{EntityKind, Ledger};
+use crate::k8s::retry::{RetryPolicy, ThrottledApi};
+use crate::k8s::signals::SignalWindow;
 
 use self::futures::{StreamExt, TryStreamExt};
 use self::futures::executor::block_on;
 use self::kube::{Api, Config, Error};
-use self::kube::api::{DeleteParams, ListParams, Meta, PostParams, WatchEvent};
+use self::kube::api::{DeleteParams, ListParams, Meta, PostParams, PropagationPolicy, WatchEvent};
 use self::kube::config::{Kubeconfig, KubeConfigOptions};
 
 mod templates;
+pub mod discovery;
+pub mod export;
+pub mod identity;
 pub mod ingress;
+pub mod lease;
+pub mod ledger;
+pub mod pod;
+pub mod preflight;
+pub mod protection;
+pub mod retry;
+mod signals;
+pub mod spec;
+pub mod status;
+pub mod store;
+pub mod wait;
+
+/// Label present on every Kubernetes entity created by h2ok, with `MANAGED_BY` as its value.
+pub const LABEL_MANAGED_BY: &str = "app.kubernetes.io/managed-by";
+pub const MANAGED_BY: &str = "h2ok";
+/// Label with name of the H2O deployment the Kubernetes entity belongs to.
+pub const LABEL_INSTANCE: &str = "app.kubernetes.io/instance";
+/// Annotation with name of the user who created the Kubernetes entity.
+pub const ANNOTATION_CREATOR: &str = "h2ok/creator";
+/// Annotation with creation time of the Kubernetes entity in RFC 3339 format.
+pub const ANNOTATION_CREATED_AT: &str = "h2ok/created-at";
+/// Annotation of H2O stateful sets protected against deletion, with `true` as its value.
+pub const ANNOTATION_PROTECTED: &str = "h2ok/protected";
+/// Docker image H2O pods run.
+pub const H2O_IMAGE_NAME: &str = "h2oai/h2o-open-source-k8s";
+pub const H2O_IMAGE_TAG: &str = "latest";
+
+/// Header of a request impersonating the given user.
+const HEADER_IMPERSONATE_USER: &str = "impersonate-user";
+/// Header of a request impersonating the given group, may be repeated.
+const HEADER_IMPERSONATE_GROUP: &str = "impersonate-group";
+
+/// Selection of the Kubernetes cluster, credentials and identity used to connect. Persisted in the deployment descriptor,
+/// so subsequent commands reconnect to the very same cluster.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ClusterConnection {
+    /// Kubeconfig context to use. If not set, the kubeconfig's current context is used.
+    pub context: Option<String>,
+    /// Kubeconfig cluster to use instead of the one of the context.
+    pub cluster: Option<String>,
+    /// Kubeconfig user to use instead of the one of the context.
+    pub user: Option<String>,
+    /// User to impersonate, e.g. `system:serviceaccount:h2o:deployer`.
+    pub impersonate_user: Option<String>,
+    /// Groups to impersonate. Only applicable together with `impersonate_user`.
+    #[serde(default)]
+    pub impersonate_groups: Vec<String>,
+    /// If true, the credentials of the pod's service account are used instead of a kubeconfig.
+    #[serde(default)]
+    pub in_cluster: bool,
+}
+
+/// Client connected to a Kubernetes cluster, together with details of the connection identifying the cluster.
+pub struct ConnectedCluster {
+    pub client: Client,
+    /// Default namespace of the kubeconfig context, or the namespace of the pod h2ok runs in.
+    pub default_namespace: String,
+    /// URL of the Kubernetes API server.
+    pub api_server: String,
+    /// Name of the kubeconfig context used. None if connected using in-cluster configuration.
+    pub context: Option<String>,
+}
+
+/// Connects to a Kubernetes cluster. If the `connection` requires in-cluster configuration, the service account
+/// of the pod h2ok runs in is used. Otherwise, the user-provided kubeconfig is used if there is one. If there is none, in-cluster
+/// configuration is attempted first and kubeconfig is searched for in the environment and well-known locations.
+pub fn connect(kubeconfig_path: Option<&Path>, connection: &ClusterConnection) -> Result<ConnectedCluster, Error> {
+    let explicit_selection: bool = connection.context.is_some() || connection.cluster.is_some() || connection.user.is_some();
+    let (mut config, context): (Config, Option<String>) = if connection.in_cluster {
+        (Config::from_cluster_env()?, None)
+    } else if let Some(kubeconfig_path) = kubeconfig_path {
+        from_kubeconfig(Kubeconfig::read_from(kubeconfig_path)?, connection)?
+    } else if explicit_selection {
+        from_kubeconfig(Kubeconfig::read()?, connection)?
+    } else {
+        match Config::from_cluster_env() {
+            Ok(config) => { (config, None) }
+            Err(_) => { from_kubeconfig(Kubeconfig::read()?, connection)? }
+        }
+    };
+    impersonate(&mut config, connection)?;
 
-pub fn from_kubeconfig(kubeconfig_path: &Path) -> (Client, String) {
-    let kubeconfig: Kubeconfig = Kubeconfig::read_from(kubeconfig_path).unwrap();
-    let config: Config = block_on(Config::from_custom_kubeconfig(kubeconfig, &KubeConfigOptions::default())).unwrap();
-    let kubeconfig_namespace: String = config.default_ns.clone();
+    let default_namespace: String = config.default_ns.clone();
+    let api_server: String = config.cluster_url.to_string();
     let client: Client = Client::new(config);
-    return (client, kubeconfig_namespace);
+    return Ok(ConnectedCluster { client, default_namespace, api_server, context });
+}
+
+/// Creates a configuration out of the given kubeconfig, using the context, cluster and user chosen by the `connection`.
+/// Returns the configuration and name of the context used.
+fn from_kubeconfig(kubeconfig: Kubeconfig, connection: &ClusterConnection) -> Result<(Config, Option<String>), Error> {
+    let options: KubeConfigOptions = KubeConfigOptions {
+        context: connection.context.clone(),
+        cluster: connection.cluster.clone(),
+        user: connection.user.clone(),
+    };
+    let context: String = connection.context.clone().unwrap_or_else(|| kubeconfig.current_context.clone());
+    let config: Config = block_on(Config::from_custom_kubeconfig(kubeconfig, &options))?;
+    return Ok((config, Some(context)));
+}
+
+/// Makes all the requests sent using the given `config` impersonate the user and groups of the `connection`, if any.
+fn impersonate(config: &mut Config, connection: &ClusterConnection) -> Result<(), Error> {
+    let user: &str = match &connection.impersonate_user {
+        None => { return Ok(()); }
+        Some(user) => { user }
+    };
+    config.headers.insert(HEADER_IMPERSONATE_USER, header_value(user)?);
+    for group in connection.impersonate_groups.iter() {
+        config.headers.append(HEADER_IMPERSONATE_GROUP, header_value(group)?);
+    }
+    return Ok(());
 }
 
-pub fn try_default() -> Result<(Client, String), Error> {
-    let config = block_on(Config::infer())?;
-    let kubeconfig_namespace: String = config.default_ns.clone();
-    let client = Client::new(config);
-    return Result::Ok((client, kubeconfig_namespace));
+fn header_value(value: &str) -> Result<HeaderValue, Error> {
+    return HeaderValue::from_str(value)
+        .map_err(|_| Error::RequestValidation(format!("Unable to impersonate '{}', the name contains invalid characters.", value)));
 }
 
 /// Deployment descriptor - contains deployment specification as defined by the user/called
@@ -44,12 +157,15 @@ pub struct Deployment {
     pub ingresses: Vec<Ingress>,
     pub stateful_sets: Vec<StatefulSet>,
     pub services: Vec<Service>,
+    /// True if the namespace has been created by h2ok for this deployment. Such a namespace is only deleted by `undeploy --purge`.
+    #[serde(default)]
+    pub namespace_created: bool,
 }
 
 impl Deployment {
     /// Deployment might contain a specification, yet it might not contain any deployed units yet.
     pub fn new(specification: DeploymentSpecification) -> Self {
-        Deployment { specification, services: vec!(), ingresses: vec!(), stateful_sets: vec!() }
+        Deployment { specification, services: vec!(), ingresses: vec!(), stateful_sets: vec!(), namespace_created: false }
     }
 }
 
@@ -71,162 +187,527 @@ pub struct DeploymentSpecification {
     pub num_h2o_nodes: u32,
     /// Kubeconfig - provided optionally. There are well-known standardized locations to look for Kubeconfig, therefore optional.
     pub kubeconfig_path: Option<PathBuf>,
+    /// Kubeconfig context, cluster, user and impersonation used, so the very same cluster is used to undeploy.
+    #[serde(default)]
+    pub connection: ClusterConnection,
+    /// Identity of the Kubernetes cluster deployed to, verified before the deployment is modified.
+    /// None for deployment descriptors created before the identity has been recorded.
+    #[serde(default)]
+    pub cluster_identity: Option<ClusterIdentity>,
+    /// Path to the cluster spec file the deployment is managed by using `h2ok apply`. None if deployed by `h2ok deploy`.
+    #[serde(default)]
+    pub spec_file: Option<PathBuf>,
 }
 
 impl DeploymentSpecification {
     pub fn new(name: String, namespace: String, memory_percentage: u8, memory: String, num_cpu: u32, num_h2o_nodes: u32, kubeconfig_path: Option<PathBuf>) -> Self {
-        DeploymentSpecification { name, namespace, memory_percentage, memory, num_cpu, num_h2o_nodes, kubeconfig_path }
+        DeploymentSpecification { name, namespace, memory_percentage, memory, num_cpu, num_h2o_nodes, kubeconfig_path, connection: ClusterConnection::default(), cluster_identity: None, spec_file: None }
+    }
+}
+
+/// Options of H2O cluster deployment.
+#[derive(Clone, Debug, Default)]
+pub struct DeployOptions {
+    /// If true, the H2O stateful set is protected against deletion from its creation.
+    pub protect: bool,
+    /// If true, a missing namespace is created. Otherwise, deployment into a missing namespace fails.
+    pub create_namespace: bool,
+    pub retry: RetryPolicy,
+    /// If set, nothing is persisted in the Kubernetes cluster.
+    pub dry_run: Option<DryRun>,
+}
+
+/// Mode of a deployment not persisting anything.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DryRun {
+    /// The Kubernetes entities are only rendered from the templates, the Kubernetes cluster is not contacted.
+    Client,
+    /// The Kubernetes entities are submitted to the API server with `dryRun=All`, so they are validated by admission
+    /// webhooks and quotas, yet not persisted.
+    Server,
+}
+
+impl DryRun {
+    pub fn from_name(name: &str) -> Option<Self> {
+        return match name {
+            "client" => Some(DryRun::Client),
+            "server" => Some(DryRun::Server),
+            _ => None
+        };
+    }
+}
+
+/// Reason an H2O cluster deployment failed.
+#[derive(Debug)]
+pub enum DeploymentFailure {
+    /// A Kubernetes entity could not be created.
+    Api(Error),
+    /// The deployment has been interrupted by the given signal, e.g. Ctrl-C.
+    Interrupted(&'static str),
+    /// Termination signals could not be intercepted, so the deployment could not be rolled back if interrupted.
+    Signals(std::io::Error),
+    /// The namespace to deploy to does not exist and creating it has not been requested.
+    MissingNamespace(String),
+}
+
+/// Failed H2O cluster deployment. The deployment has been rolled back, `rollback_errors` contain a reason
+/// for each Kubernetes entity the rollback failed to delete.
+#[derive(Debug)]
+pub struct DeploymentError {
+    pub failure: DeploymentFailure,
+    pub rollback_errors: Vec<String>,
+}
+
+impl Display for DeploymentError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        return match &self.failure {
+            DeploymentFailure::Api(e) => { write!(f, "{}", e) }
+            DeploymentFailure::Interrupted(signal) => { write!(f, "Interrupted by {}.", signal) }
+            DeploymentFailure::Signals(e) => { write!(f, "Unable to intercept termination signals: {}", e) }
+            DeploymentFailure::MissingNamespace(namespace) => { write!(f, "Namespace '{}' does not exist.", namespace) }
+        };
     }
 }
 
-/// Deploys an H2O cluster using the given `client` and `deployment_specification`.
-/// If there is any error during the deployment of any component (stateful set, service, etc.),
-/// the deployment is rolled back - components already deployed are undeployed.
-pub fn deploy_h2o_cluster(client: &Client, deployment_specification: DeploymentSpecification) -> Result<Deployment, Error> {
+/// Deploys an H2O cluster using the given `client` and `deployment_specification`. A missing namespace is created
+/// only if requested by the `options`. Each Kubernetes entity is recorded in a ledger before it is created. If the creation of any entity fails,
+/// or the deployment is interrupted by Ctrl-C/SIGTERM, all the entities recorded are deleted in reverse order.
+/// With a server-side dry run, the entities returned by the API server are recorded in the deployment and there is nothing to roll back.
+/// If the namespace does not exist yet, the entities in it can not be validated and are recorded as rendered from the templates,
+/// with `namespace_created` set.
+pub fn deploy_h2o_cluster(client: &Client, deployment_specification: DeploymentSpecification, options: &DeployOptions) -> Result<Deployment, DeploymentError> {
     let mut tokio_runtime: Runtime = tokio::runtime::Runtime::new().unwrap();
     let mut deployment: Deployment = Deployment::new(deployment_specification);
+    let mut ledger: Ledger = Ledger::new(&deployment.specification.namespace);
+
+    // Signals are intercepted until the rollback finishes, so it is not interrupted midway.
+    let _signal_window: SignalWindow = SignalWindow::open();
+    let result: Result<(), DeploymentFailure> = tokio_runtime.block_on(async {
+        tokio::select! {
+            result = create_entities(client, &mut deployment, &mut ledger, options) => { result }
+            signal = signals::shutdown_signal() => { Err(signal.map_or_else(DeploymentFailure::Signals, DeploymentFailure::Interrupted)) }
+        }
+    });
+
+    return match result {
+        Ok(_) => { Ok(deployment) }
+        Err(failure) if options.dry_run.is_some() => { Err(DeploymentError { failure, rollback_errors: vec!() }) }
+        Err(failure) => {
+            eprintln!("Deployment of '{}' failed. Rolling back {} created entities.", deployment.specification.name, ledger.entries().len());
+            let rollback_errors: Vec<String> = tokio_runtime.block_on(ledger.rollback(client, &options.retry));
+            Err(DeploymentError { failure, rollback_errors })
+        }
+    };
+}
+
+/// Creates all the Kubernetes entities of the H2O cluster, recording each of them in the `ledger` first.
+async fn create_entities(client: &Client, deployment: &mut Deployment, ledger: &mut Ledger, options: &DeployOptions) -> Result<(), DeploymentFailure> {
+    ensure_namespace(client, deployment, ledger, options).await?;
+    let namespace: &str = &deployment.specification.namespace;
+    let dry_run: bool = options.dry_run.is_some();
+    if dry_run && deployment.namespace_created {
+        // The API server refuses even a dry run of an entity in a namespace that does not exist. The entities are rendered from the templates instead.
+        deployment.services.push(service_template(&deployment.specification));
+        deployment.stateful_sets.push(stateful_set_template(&deployment.specification, options));
+        return Ok(());
+    }
+
+    let service_api: ThrottledApi<Service> = ThrottledApi::namespaced(client.clone(), namespace);
+    let service: Service = create_entity(&service_api, ledger, EntityKind::Service, &service_template(&deployment.specification), &options.retry, dry_run).await
+        .map_err(DeploymentFailure::Api)?;
+    deployment.services.push(service);
+
+    let statefulset_api: ThrottledApi<StatefulSet> = ThrottledApi::namespaced(client.clone(), namespace);
+    let stateful_set: StatefulSet = stateful_set_template(&deployment.specification, options);
+    let stateful_set: StatefulSet = create_entity(&statefulset_api, ledger, EntityKind::StatefulSet, &stateful_set, &options.retry, dry_run).await
+        .map_err(DeploymentFailure::Api)?;
+    deployment.stateful_sets.push(stateful_set);
+    return Ok(());
+}
 
-    deployment.services.push(deploy_service(&mut tokio_runtime, client, &deployment)?);
-    deployment.stateful_sets.push(deploy_statefulset(&mut tokio_runtime, client, &deployment)?);
+/// Renders the Kubernetes entities of an H2O cluster from the templates without contacting the Kubernetes cluster.
+/// The namespace is only rendered if requested to be created by the `options`, as its existence can not be verified.
+pub fn render_h2o_cluster(deployment_specification: DeploymentSpecification, options: &DeployOptions) -> Deployment {
+    let mut deployment: Deployment = Deployment::new(deployment_specification);
+    deployment.services.push(service_template(&deployment.specification));
+    deployment.stateful_sets.push(stateful_set_template(&deployment.specification, options));
+    deployment.namespace_created = options.create_namespace;
+    return deployment;
+}
 
-    return Ok(deployment);
+/// Returns all the Kubernetes entities recorded in the deployment as manifests, in the order of their creation.
+/// A namespace created by h2ok is represented by its template.
+pub fn manifests(deployment: &Deployment) -> Vec<serde_json::Value> {
+    let mut manifests: Vec<serde_json::Value> = vec!();
+    // Kubernetes entities consist of strings, numbers, maps and sequences only, their serialization can not fail.
+    if deployment.namespace_created {
+        let namespace: Namespace = templates::h2o_namespace(&deployment.specification.name, &deployment.specification.namespace);
+        manifests.push(serde_json::to_value(&namespace).unwrap());
+    }
+    manifests.extend(deployment.services.iter().map(|service| serde_json::to_value(service).unwrap()));
+    manifests.extend(deployment.stateful_sets.iter().map(|stateful_set| serde_json::to_value(stateful_set).unwrap()));
+    manifests.extend(deployment.ingresses.iter().map(|ingress| serde_json::to_value(ingress).unwrap()));
+    return manifests;
 }
 
-#[inline]
-fn deploy_service(tokio_runtime: &mut Runtime, client: &Client, deployment: &Deployment) -> Result<Service, Error> {
-    let service_api: Api<Service> = Api::namespaced(client.clone(), &deployment.specification.namespace);
+fn service_template(specification: &DeploymentSpecification) -> Service {
+    return templates::h2o_service(&specification.name, &specification.namespace);
+}
 
-    let service: Service = templates::h2o_service(&deployment.specification.name, &deployment.specification.namespace);
-    return match tokio_runtime.block_on(service_api.create(&PostParams::default(), &service)) {
-        Ok(service) => {
-            Ok(service)
+fn stateful_set_template(specification: &DeploymentSpecification, options: &DeployOptions) -> StatefulSet {
+    let mut stateful_set: StatefulSet = templates::h2o_stateful_set(&specification.name, &specification.namespace, H2O_IMAGE_NAME, H2O_IMAGE_TAG,
+                                                                    specification.num_h2o_nodes, specification.memory_percentage,
+                                                                    &specification.memory, specification.num_cpu);
+    if options.protect {
+        protection::protect_template(&mut stateful_set);
+    }
+    return stateful_set;
+}
+
+/// Makes sure the namespace of the deployment exists. A missing namespace is created with h2ok labels if `options.create_namespace`
+/// is set, otherwise the deployment fails. If the user is not permitted to read namespaces, the namespace is assumed to exist.
+async fn ensure_namespace(client: &Client, deployment: &mut Deployment, ledger: &mut Ledger, options: &DeployOptions) -> Result<(), DeploymentFailure> {
+    let namespace: &str = &deployment.specification.namespace;
+    let api: ThrottledApi<Namespace> = ThrottledApi::all(client.clone());
+    match retry::with_retry(&options.retry, &format!("get Namespace '{}'", namespace), || api.get(namespace)).await {
+        Ok(_) => { return Ok(()); }
+        Err(Error::Api(response)) if response.code == 403 => { return Ok(()); }
+        Err(Error::Api(response)) if response.code == 404 => {}
+        Err(e) => { return Err(DeploymentFailure::Api(e)); }
+    }
+    if !options.create_namespace {
+        return Err(DeploymentFailure::MissingNamespace(namespace.to_string()));
+    }
+
+    let namespace_template: Namespace = templates::h2o_namespace(&deployment.specification.name, namespace);
+    create_entity(&api, ledger, EntityKind::Namespace, &namespace_template, &options.retry, options.dry_run.is_some()).await
+        .map_err(DeploymentFailure::Api)?;
+    deployment.namespace_created = true;
+    return Ok(());
+}
+
+/// Creates the given entity, recording it in the `ledger` before the creation is requested. The entity is only
+/// removed from the ledger if the Kubernetes cluster refuses to create it. Transient errors are retried according to the `retry_policy`.
+/// If `dry_run` is true, the entity is only validated by the API server, not persisted.
+async fn create_entity<K>(api: &ThrottledApi<K>, ledger: &mut Ledger, kind: EntityKind, entity: &K, retry_policy: &RetryPolicy, dry_run: bool) -> Result<K, Error>
+    where K: Clone + DeserializeOwned + Serialize + Meta {
+    let index: usize = ledger.record(kind, entity);
+    let description: String = format!("create {:?} '{}'", kind, entity.name());
+    let post_params: PostParams = PostParams { dry_run, ..PostParams::default() };
+    let mut attempts: u32 = 0;
+    let result: Result<K, Error> = retry::with_retry(retry_policy, &description, || {
+        attempts += 1;
+        api.create(&post_params, entity)
+    }).await;
+    return match result {
+        Ok(created_entity) => {
+            ledger.confirm(index, &created_entity);
+            Ok(created_entity)
+        }
+        // A conflict after a retry may be caused by a previous attempt which timed out, yet created the entity.
+        Err(Error::Api(response)) if response.code == 409 && attempts > 1 && !dry_run => {
+            let name: String = entity.name();
+            let description: String = format!("get {:?} '{}'", kind, name);
+            match retry::with_retry(retry_policy, &description, || api.get(&name)).await {
+                Ok(existing) if ledger.entries()[index].is_submitted(&existing) => {
+                    ledger.confirm(index, &existing);
+                    Ok(existing)
+                }
+                // An entity with the same name created by someone else is left untouched by the rollback.
+                _ => { Err(Error::Api(response)) }
+            }
         }
         Err(e) => {
-            eprintln!("Unable to deploy service for '{}' deployment. Rewinding existing deployment. Reason:\n{:?}", &deployment.specification.name, e);
-            undeploy_h2o(&client, &deployment).unwrap();
+            ledger.discard(index);
             Err(e)
         }
     };
 }
 
-#[inline]
-fn deploy_statefulset(tokio_runtime: &mut Runtime, client: &Client, deployment: &Deployment) -> Result<StatefulSet, Error> {
-    let statefulset_api: Api<StatefulSet> = Api::namespaced(client.clone(), &deployment.specification.namespace);
-    let stateful_set: StatefulSet = templates::h2o_stateful_set(&deployment.specification.name, &deployment.specification.namespace, "h2oai/h2o-open-source-k8s", "latest",
-                                                                deployment.specification.num_h2o_nodes, deployment.specification.memory_percentage, &deployment.specification.memory, deployment.specification.num_cpu);
-    return match tokio_runtime.block_on(statefulset_api.create(&PostParams::default(), &stateful_set)) {
-        Ok(statefulset) => {
-            Result::Ok(statefulset)
+/// Options of H2O deployment undeployment.
+#[derive(Clone, Debug)]
+pub struct UndeployOptions {
+    /// Policy of deletion of dependent entities, e.g. pods of a stateful set.
+    pub propagation_policy: PropagationPolicy,
+    /// If true, persistent volume claims and secrets labelled as belonging to the H2O deployment are deleted as well,
+    /// together with the namespace if it has been created by h2ok for the deployment.
+    pub purge: bool,
+    /// If true, H2O deployments protected against deletion are undeployed as well.
+    pub force: bool,
+    pub retry: RetryPolicy,
+}
+
+impl Default for UndeployOptions {
+    fn default() -> Self {
+        UndeployOptions { propagation_policy: PropagationPolicy::Background, purge: false, force: false, retry: RetryPolicy::default() }
+    }
+}
+
+/// Deletes all the Kubernetes entities recorded in the given deployment. Entities deleted, as well as entities
+/// already gone, are removed from the deployment. Therefore, on error, the deployment only contains entities still present
+/// and the undeployment may be retried with it. The error contains a reason for each entity not deleted.
+/// Unless forced, deployments with a stateful set protected against deletion are left untouched.
+pub fn undeploy_h2o(client: &Client, deployment: &mut Deployment, options: &UndeployOptions) -> Result<(), Vec<String>> {
+    let mut tokio_runtime: Runtime = tokio::runtime::Runtime::new().unwrap();
+    if !options.force {
+        match protection::protected_stateful_sets(&mut tokio_runtime, client, deployment, &options.retry) {
+            Ok(protected) if protected.is_empty() => {}
+            Ok(protected) => {
+                return Err(protected.iter()
+                    .map(|name| format!("StatefulSet '{}'. Reason: Protected against deletion, use 'h2ok unprotect' or the '--force' flag.", name))
+                    .collect());
+            }
+            Err(e) => { return Err(vec!(format!("Unable to check deletion protection. Reason: {}", e))); }
         }
-        Err(e) => {
-            eprintln!("Unable to statefulset for '{}' deployment. Rewinding existing deployment. Reason:\n{:?}", &deployment.specification.name, e);
-            undeploy_h2o(&client, &deployment).unwrap();
-            Result::Err(e)
+    }
+
+    let namespace: String = deployment.specification.namespace.clone();
+    let delete_params: DeleteParams = DeleteParams {
+        propagation_policy: Some(options.propagation_policy.clone()),
+        ..DeleteParams::default()
+    };
+    let mut not_deleted: Vec<String> = vec!();
+
+    let api: ThrottledApi<Ingress> = ThrottledApi::namespaced(client.clone(), &namespace);
+    let ingresses: Vec<Ingress> = std::mem::take(&mut deployment.ingresses);
+    deployment.ingresses = delete_entities(&mut tokio_runtime, &api, ingresses, "Ingress", &delete_params, &options.retry, &mut not_deleted);
+
+    let api: ThrottledApi<Service> = ThrottledApi::namespaced(client.clone(), &namespace);
+    let services: Vec<Service> = std::mem::take(&mut deployment.services);
+    deployment.services = delete_entities(&mut tokio_runtime, &api, services, "Service", &delete_params, &options.retry, &mut not_deleted);
+
+    let api: ThrottledApi<StatefulSet> = ThrottledApi::namespaced(client.clone(), &namespace);
+    let stateful_sets: Vec<StatefulSet> = std::mem::take(&mut deployment.stateful_sets);
+    deployment.stateful_sets = delete_entities(&mut tokio_runtime, &api, stateful_sets, "StatefulSet", &delete_params, &options.retry, &mut not_deleted);
+
+    if options.purge {
+        let api: ThrottledApi<PersistentVolumeClaim> = ThrottledApi::namespaced(client.clone(), &namespace);
+        purge_entities(&mut tokio_runtime, &api, &deployment.specification.name, "PersistentVolumeClaim", &delete_params, &options.retry, &mut not_deleted);
+        let api: ThrottledApi<Secret> = ThrottledApi::namespaced(client.clone(), &namespace);
+        purge_entities(&mut tokio_runtime, &api, &deployment.specification.name, "Secret", &delete_params, &options.retry, &mut not_deleted);
+    }
+
+    // The namespace is only deleted once everything else is gone, so a failed undeployment can be retried using the deployment descriptor.
+    if options.purge && deployment.namespace_created && not_deleted.is_empty() {
+        match tokio_runtime.block_on(delete_namespace(client, &deployment.specification.name, &namespace, &delete_params, &options.retry)) {
+            Ok(_) => { deployment.namespace_created = false; }
+            Err(reason) => { not_deleted.push(format!("Namespace '{}'. Reason: {}", namespace, reason)); }
         }
+    }
+
+    return if not_deleted.len() > 0 {
+        Err(not_deleted)
+    } else {
+        Ok(())
     };
 }
 
-pub fn undeploy_h2o(client: &Client, deployment: &Deployment) -> Result<(), Vec<String>> {
+/// Removes the ingresses of the given deployment, leaving the H2O cluster itself running. Ingresses not removed are kept
+/// in the deployment, a reason is returned for each of them.
+pub fn remove_ingresses(client: &Client, deployment: &mut Deployment, retry_policy: &RetryPolicy) -> Result<(), Vec<String>> {
     let mut tokio_runtime: Runtime = tokio::runtime::Runtime::new().unwrap();
-    let namespace: &str = deployment.specification.namespace.as_str();
     let mut not_deleted: Vec<String> = vec!();
+    let api: ThrottledApi<Ingress> = ThrottledApi::namespaced(client.clone(), &deployment.specification.namespace);
+    let ingresses: Vec<Ingress> = std::mem::take(&mut deployment.ingresses);
+    deployment.ingresses = delete_entities(&mut tokio_runtime, &api, ingresses, "Ingress", &DeleteParams::default(), retry_policy, &mut not_deleted);
+    return if not_deleted.is_empty() {
+        Ok(())
+    } else {
+        Err(not_deleted)
+    };
+}
 
-    let api: Api<Ingress> = Api::namespaced(client.clone(), namespace);
-    for ingress in deployment.ingresses.iter() {
-        match tokio_runtime.block_on(api.delete(ingress.name().as_str(), &DeleteParams::default())) {
+/// Deletes the given entities, treating entities not found as already deleted. Returns entities that could not be deleted,
+/// the reasons are pushed into `not_deleted`. Transient errors are retried according to the `retry_policy`.
+fn delete_entities<K>(tokio_runtime: &mut Runtime, api: &ThrottledApi<K>, entities: Vec<K>, kind: &str, delete_params: &DeleteParams,
+                      retry_policy: &RetryPolicy, not_deleted: &mut Vec<String>) -> Vec<K>
+    where K: Clone + DeserializeOwned + Meta {
+    let mut remaining: Vec<K> = vec!();
+    for entity in entities.into_iter() {
+        let name: String = entity.name();
+        let description: String = format!("delete {} '{}'", kind, name);
+        match tokio_runtime.block_on(retry::with_retry(retry_policy, &description, || api.delete(&name, delete_params))) {
             Ok(_) => {}
-            Err(e) => { not_deleted.push(format!("Unable to undeploy '{}'. Reason:\n{:?}", ingress.name(), e)) }
+            Err(Error::Api(response)) if response.code == 404 => {}
+            Err(e) => {
+                not_deleted.push(format!("{} '{}'. Reason: {}", kind, entity.name(), e));
+                remaining.push(entity);
+            }
         }
     }
+    return remaining;
+}
 
-    let api: Api<Service> = Api::namespaced(client.clone(), namespace);
-    for service in deployment.services.iter() {
-        match tokio_runtime.block_on(api.delete(service.name().as_str(), &DeleteParams::default())) {
-            Ok(_) => {}
-            Err(_) => { not_deleted.push(service.name()) }
+/// Deletes all entities of the given kind labelled by h2ok as belonging to the deployment with the given name.
+/// Such entities are not recorded in the deployment descriptor.
+fn purge_entities<K>(tokio_runtime: &mut Runtime, api: &ThrottledApi<K>, deployment_name: &str, kind: &str, delete_params: &DeleteParams,
+                     retry_policy: &RetryPolicy, not_deleted: &mut Vec<String>)
+    where K: Clone + DeserializeOwned + Meta {
+    let list_params: ListParams = ListParams::default()
+        .labels(&discovery::instance_selector(deployment_name));
+    let description: String = format!("list {}s of '{}'", kind, deployment_name);
+    match tokio_runtime.block_on(retry::with_retry(retry_policy, &description, || api.list(&list_params))) {
+        Ok(entities) => {
+            delete_entities(tokio_runtime, api, entities.items, kind, delete_params, retry_policy, not_deleted);
         }
+        Err(e) => { not_deleted.push(format!("{}s of '{}'. Reason: {}", kind, deployment_name, e)); }
     }
+}
 
-    let api: Api<StatefulSet> = Api::namespaced(client.clone(), namespace);
-
-    for stateful_set in deployment.stateful_sets.iter() {
-        match tokio_runtime.block_on(api.delete(stateful_set.name().as_str(), &DeleteParams::default())) {
-            Ok(_) => {}
-            Err(_) => { not_deleted.push(stateful_set.name()) }
-        }
+/// Deletes the namespace created by h2ok for the deployment with the given name. The namespace is left untouched if there are
+/// other H2O deployments in it.
+async fn delete_namespace(client: &Client, deployment_name: &str, namespace: &str, delete_params: &DeleteParams,
+                          retry_policy: &RetryPolicy) -> Result<(), String> {
+    let stateful_set_api: ThrottledApi<StatefulSet> = ThrottledApi::namespaced(client.clone(), namespace);
+    let list_params: ListParams = ListParams::default()
+        .labels(&discovery::managed_by_selector());
+    let description: String = format!("list H2O deployments in Namespace '{}'", namespace);
+    let mut other_deployments: Vec<String> = retry::with_retry(retry_policy, &description, || stateful_set_api.list(&list_params)).await
+        .map_err(|e| e.to_string())?
+        .items.iter()
+        .filter_map(discovery::instance_name)
+        .filter(|name| name != deployment_name)
+        .collect();
+    if !other_deployments.is_empty() {
+        other_deployments.sort();
+        other_deployments.dedup();
+        return Err(format!("The namespace also contains H2O deployments {}, undeploy them first.", other_deployments.join(", ")));
     }
 
-    return if not_deleted.len() > 0 {
-        Err(not_deleted)
-    } else {
-        Ok(())
+    let api: ThrottledApi<Namespace> = ThrottledApi::all(client.clone());
+    return match retry::with_retry(retry_policy, &format!("delete Namespace '{}'", namespace), || api.delete(namespace, delete_params)).await {
+        Ok(_) => { Ok(()) }
+        Err(Error::Api(response)) if response.code == 404 => { Ok(()) }
+        Err(e) => { Err(e.to_string()) }
     };
 }
 
-pub fn deploy_ingress(client: &Client, deployment: &mut Deployment) -> Result<(), Error> {
+/// Returns a human-readable list of all Kubernetes entities recorded in the given deployment, e.g. `Service default/h2o-service`.
+pub fn describe_entities(deployment: &Deployment) -> Vec<String> {
+    let namespace: &str = &deployment.specification.namespace;
+    let mut entities: Vec<String> = vec!();
+    entities.extend(deployment.ingresses.iter().map(|ingress| format!("Ingress {}/{}", namespace, ingress.name())));
+    entities.extend(deployment.services.iter().map(|service| format!("Service {}/{}", namespace, service.name())));
+    entities.extend(deployment.stateful_sets.iter().map(|stateful_set| format!("StatefulSet {}/{}", namespace, stateful_set.name())));
+    return entities;
+}
+
+/// Creates an ingress pointing to the H2O service of the given deployment and waits until it has an IP address assigned.
+/// If the ingress creation is interrupted, the ingress is removed. Transient errors are retried according to the `retry_policy`.
+pub fn deploy_ingress(client: &Client, deployment: &mut Deployment, retry_policy: &RetryPolicy) -> Result<(), DeploymentError> {
     let mut tokio_runtime: Runtime = tokio::runtime::Runtime::new().unwrap();
+    let mut ledger: Ledger = Ledger::new(&deployment.specification.namespace);
 
-    let api: Api<Ingress> = Api::namespaced(client.clone(), &deployment.specification.namespace);
+    let api: ThrottledApi<Ingress> = ThrottledApi::namespaced(client.clone(), &deployment.specification.namespace);
     let ingress_template: Ingress = templates::h2o_ingress(&deployment.specification.name, &deployment.specification.namespace);
 
-    return match tokio_runtime.block_on(api.create(&PostParams::default(), &ingress_template)) {
-        Ok(ingress) => {
-            let ingress_name: String = ingress.meta().name.as_ref().unwrap().clone();
-            let mut created_ingress: Ingress = ingress;
-            let lp: ListParams = ListParams::default()
-                .fields(&format!("metadata.name={}", &ingress_name))
-                .timeout(3);
-            let mut event_stream = tokio_runtime.block_on(api.watch(&lp, "0")).unwrap().boxed();
-
-            while let Some(status) = tokio_runtime.block_on(event_stream.try_next()).unwrap() {
-                match status {
-                    WatchEvent::Modified(up_to_date_ingress) => {
+    let signal_window: SignalWindow = SignalWindow::open();
+    let result: Result<Ingress, DeploymentFailure> = tokio_runtime.block_on(async {
+        tokio::select! {
+            result = create_entity(&api, &mut ledger, EntityKind::Ingress, &ingress_template, retry_policy, false) => { result.map_err(DeploymentFailure::Api) }
+            signal = signals::shutdown_signal() => { Err(signal.map_or_else(DeploymentFailure::Signals, DeploymentFailure::Interrupted)) }
+        }
+    });
+    let ingress: Ingress = match result {
+        Ok(ingress) => { ingress }
+        Err(failure) => {
+            let rollback_errors: Vec<String> = tokio_runtime.block_on(ledger.rollback(client, retry_policy));
+            return Err(DeploymentError { failure, rollback_errors });
+        }
+    };
+    drop(signal_window);
+
+    let ingress_name: String = ingress.name();
+    let mut created_ingress: Ingress = ingress;
+    let lp: ListParams = ListParams::default()
+        .fields(&format!("metadata.name={}", &ingress_name))
+        .timeout(3);
+    let description: String = format!("watch Ingress '{}'", ingress_name);
+    let api: Api<Ingress> = Api::namespaced(client.clone(), &deployment.specification.namespace);
+    // The ingress is already created, failure to observe its IP address assignment is therefore not fatal.
+    match tokio_runtime.block_on(retry::with_retry(retry_policy, &description, || api.watch(&lp, "0"))) {
+        Ok(event_stream) => {
+            let mut event_stream = event_stream.boxed();
+            loop {
+                match tokio_runtime.block_on(event_stream.try_next()) {
+                    Ok(Some(WatchEvent::Modified(up_to_date_ingress))) => {
                         created_ingress = up_to_date_ingress;
                         if any_ip(&created_ingress).is_some() {
                             break;
                         }
                     }
-                    _ => {}
+                    Ok(Some(_)) => {}
+                    Ok(None) => { break; }
+                    Err(e) => {
+                        eprintln!("Unable to watch ingress '{}' for an IP address. Reason: {}", ingress_name, e);
+                        break;
+                    }
                 }
             }
-            deployment.ingresses.push(created_ingress);
-            return Ok(());
         }
-        Err(err) => {
-            Err(err)
-        }
-    };
+        Err(e) => { eprintln!("Unable to watch ingress '{}' for an IP address. Reason: {}", ingress_name, e); }
+    }
+    deployment.ingresses.push(created_ingress);
+    return Ok(());
 }
 
 #[cfg(test)]
 mod tests {
     use std::path::Path;
 
-    use crate::k8s::{Deployment, DeploymentSpecification};
+    use crate::k8s::{ClusterConnection, ConnectedCluster, DeployOptions, Deployment, DeploymentSpecification, UndeployOptions};
+    use crate::k8s::retry::RetryPolicy;
     use crate::tests::kubeconfig_location_panic;
 
-    use super::kube::Client;
+    use super::kube::{Client, Config};
 
     #[test]
     fn test_from_kubeconfig() {
         let kubeconfig_location: String = kubeconfig_location_panic();
         let kubeconfig_path: &Path = Path::new(&kubeconfig_location);
         assert!(kubeconfig_path.exists());
-        super::from_kubeconfig(kubeconfig_path);
+        super::connect(Some(kubeconfig_path), &ClusterConnection::default()).unwrap();
+    }
+
+    #[test]
+    fn test_descriptor_without_namespace_created() {
+        // Deployment descriptors written before namespaces could be created by h2ok
+        let descriptor: &str = r#"{"specification": {"name": "h2o-test", "namespace": "default", "memory_percentage": 50, "memory": "1Gi",
+        "num_cpu": 1, "num_h2o_nodes": 1, "kubeconfig_path": null}, "ingresses": [], "stateful_sets": [], "services": []}"#;
+        let deployment: Deployment = serde_json::from_str(descriptor).unwrap();
+        assert!(!deployment.namespace_created);
+        assert_eq!(ClusterConnection::default(), deployment.specification.connection);
+    }
+
+    #[test]
+    fn test_impersonate() {
+        let mut config: Config = Config::new("https://127.0.0.1:6443".parse().unwrap());
+        let connection: ClusterConnection = ClusterConnection {
+            impersonate_user: Some("system:serviceaccount:h2o:deployer".to_string()),
+            impersonate_groups: vec!("system:serviceaccounts".to_string(), "h2o-team".to_string()),
+            ..ClusterConnection::default()
+        };
+        super::impersonate(&mut config, &connection).unwrap();
+        assert_eq!("system:serviceaccount:h2o:deployer", config.headers.get(super::HEADER_IMPERSONATE_USER).unwrap());
+        assert_eq!(2, config.headers.get_all(super::HEADER_IMPERSONATE_GROUP).iter().count());
+
+        let invalid: ClusterConnection = ClusterConnection { impersonate_user: Some("line\nbreak".to_string()), ..ClusterConnection::default() };
+        assert!(super::impersonate(&mut config, &invalid).is_err());
     }
 
     #[test]
     fn test_deploy_h2o() {
-        let (client, namespace): (Client, String) = super::try_default().unwrap();
+        let connected: ConnectedCluster = super::connect(None, &ClusterConnection::default()).unwrap();
+        let (client, namespace): (Client, String) = (connected.client, connected.default_namespace);
         let deployment_specification: DeploymentSpecification = DeploymentSpecification::new("h2o-k8s-test-cluster".to_string(), namespace,
                                                                                              80, "256Mi".to_string(), 2, 2, None);
-        let mut deployment: Deployment = super::deploy_h2o_cluster(&client, deployment_specification).unwrap();
+        let mut deployment: Deployment = super::deploy_h2o_cluster(&client, deployment_specification, &DeployOptions::default()).unwrap();
         assert_eq!(1, deployment.services.len());
         assert_eq!(1, deployment.stateful_sets.len());
         assert_eq!(0, deployment.ingresses.len());
 
         // Deploy ingress on top of existing deployment
-        match super::deploy_ingress(&client, &mut deployment) {
+        match super::deploy_ingress(&client, &mut deployment, &RetryPolicy::default()) {
             Ok(_) => {
                 assert_eq!(1, deployment.ingresses.len());
             }
@@ -234,7 +715,7 @@ mod tests {
                 panic!("Test of ingress deployment failed. Reason: \n{}", e);
             }
         }
-        let undeployment_result = super::undeploy_h2o(&client, &deployment);
+        let undeployment_result = super::undeploy_h2o(&client, &mut deployment, &UndeployOptions::default());
         assert!(undeployment_result.is_ok());
     }
 }
@@ -0,0 +1,46 @@
+use k8s_openapi::api::core::v1::Pod;
+
+use crate::k8s::Deployment;
+
+/// Returns the label selector matching all H2O pods of the given deployment.
+pub fn selector(deployment: &Deployment) -> String {
+    return format!("app={}", deployment.specification.name);
+}
+
+/// Returns phase of the given pod, if known. Otherwise returns None.
+pub fn phase(pod: &Pod) -> Option<&str> {
+    return pod.status.as_ref()?
+        .phase.as_deref();
+}
+
+/// Returns true if the pod has the `Ready` condition set to `True`, otherwise false.
+/// The H2O readiness probe only succeeds on the leader node, therefore a ready H2O pod is the leader.
+pub fn is_ready(pod: &Pod) -> bool {
+    return pod.status.as_ref()
+        .and_then(|status| status.conditions.as_ref())
+        .map(|conditions| conditions.iter()
+            .any(|condition| condition.type_ == "Ready" && condition.status == "True"))
+        .unwrap_or(false);
+}
+
+/// Returns the total count of restarts of all the containers inside the pod.
+pub fn restarts(pod: &Pod) -> i32 {
+    return pod.status.as_ref()
+        .and_then(|status| status.container_statuses.as_ref())
+        .map(|container_statuses| container_statuses.iter()
+            .map(|container_status| container_status.restart_count)
+            .sum())
+        .unwrap_or(0);
+}
+
+/// Returns name of the node the pod is scheduled to, if scheduled. Otherwise returns None.
+pub fn node(pod: &Pod) -> Option<String> {
+    return pod.spec.as_ref()?
+        .node_name.clone();
+}
+
+/// Returns IP address of the pod, if assigned. Otherwise returns None.
+pub fn ip(pod: &Pod) -> Option<String> {
+    return pod.status.as_ref()?
+        .pod_ip.clone();
+}
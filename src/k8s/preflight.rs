@@ -0,0 +1,473 @@
+use std::collections::BTreeMap;
+
+use k8s_openapi::api::authorization::v1::{ResourceAttributes, SelfSubjectAccessReview, SelfSubjectAccessReviewSpec};
+use k8s_openapi::api::core::v1::{LimitRange, Namespace, Node, ResourceQuota};
+use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+use kube::{Client, Error};
+use kube::api::{ListParams, Meta, PostParams};
+use serde::Serialize;
+use tokio::runtime::Runtime;
+
+use crate::k8s::{DeployOptions, DeploymentSpecification};
+use crate::k8s::retry;
+use crate::k8s::retry::{RetryPolicy, ThrottledApi};
+
+/// Result of a single pre-flight check.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckResult {
+    /// The deployment is not going to fail for the reason checked.
+    Pass,
+    /// The check could not be carried out or the deployment may be limited, e.g. a command other than `deploy` is not permitted.
+    Warn,
+    /// The deployment is going to fail.
+    Fail,
+}
+
+impl CheckResult {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            CheckResult::Pass => { "PASS" }
+            CheckResult::Warn => { "WARN" }
+            CheckResult::Fail => { "FAIL" }
+        };
+    }
+}
+
+/// Outcome of a single pre-flight check with an explanation of the result.
+#[derive(Serialize, Debug, Clone)]
+pub struct Check {
+    pub name: String,
+    pub result: CheckResult,
+    pub explanation: String,
+}
+
+impl Check {
+    fn new(name: &str, result: CheckResult, explanation: String) -> Self {
+        Check { name: name.to_string(), result, explanation }
+    }
+}
+
+/// A Kubernetes API request h2ok issues, checked to be permitted.
+struct Permission {
+    verb: &'static str,
+    group: &'static str,
+    resource: &'static str,
+    /// If true, the deployment fails without the permission. Otherwise, only some of the other commands are limited.
+    required: bool,
+    /// What the permission is needed for.
+    purpose: &'static str,
+}
+
+/// Permissions in the namespace deployed to needed by h2ok, regardless of the deployment options.
+const NAMESPACED_PERMISSIONS: [Permission; 11] = [
+    Permission { verb: "create", group: "", resource: "services", required: true, purpose: "deploy" },
+    Permission { verb: "create", group: "apps", resource: "statefulsets", required: true, purpose: "deploy" },
+    Permission { verb: "delete", group: "", resource: "services", required: true, purpose: "undeploy and roll back a failed deployment" },
+    Permission { verb: "delete", group: "apps", resource: "statefulsets", required: true, purpose: "undeploy and roll back a failed deployment" },
+    Permission { verb: "list", group: "", resource: "pods", required: false, purpose: "wait for the deployment and display its status" },
+    Permission { verb: "watch", group: "", resource: "pods", required: false, purpose: "wait for the deployment and display its status" },
+    Permission { verb: "create", group: "networking.k8s.io", resource: "ingresses", required: false, purpose: "expose the deployment using 'h2ok ingress'" },
+    // 'deploy --protect' annotates the stateful set as created, only changing the protection later patches it.
+    Permission { verb: "patch", group: "apps", resource: "statefulsets", required: false, purpose: "change the protection later using 'h2ok protect/unprotect'" },
+    Permission { verb: "delete", group: "", resource: "persistentvolumeclaims", required: false, purpose: "purge persistent volume claims using 'undeploy --purge'" },
+    Permission { verb: "delete", group: "", resource: "secrets", required: false, purpose: "purge secrets using 'undeploy --purge'" },
+    Permission { verb: "create", group: "coordination.k8s.io", resource: "leases", required: false, purpose: "lock the deployment against concurrent changes" },
+];
+
+/// Checks whether an H2O cluster with the given specification can be deployed: the namespace, permissions of the user,
+/// ResourceQuotas and LimitRanges of the namespace and allocatable capacity of the nodes. Checks the user is not permitted
+/// to carry out result in a warning. Other failed Kubernetes API requests are returned as an error.
+/// The resources of the `replaced` deployment, removed before the new one is deployed, are not counted as used by the ResourceQuotas.
+pub fn preflight(client: &Client, specification: &DeploymentSpecification, options: &DeployOptions,
+                 replaced: Option<&DeploymentSpecification>) -> Result<Vec<Check>, Error> {
+    let mut tokio_runtime: Runtime = tokio::runtime::Runtime::new().unwrap();
+    return tokio_runtime.block_on(run_checks(client, specification, options, replaced));
+}
+
+async fn run_checks(client: &Client, specification: &DeploymentSpecification, options: &DeployOptions,
+                    replaced: Option<&DeploymentSpecification>) -> Result<Vec<Check>, Error> {
+    let namespace: &str = &specification.namespace;
+    let mut checks: Vec<Check> = vec!();
+    checks.push(check_namespace(client, namespace, options).await?);
+
+    for permission in NAMESPACED_PERMISSIONS.iter() {
+        checks.push(check_permission(client, permission, Some(namespace), &options.retry).await?);
+    }
+    if options.create_namespace {
+        let permission: Permission = Permission { verb: "create", group: "", resource: "namespaces", required: false, purpose: "create a missing namespace" };
+        checks.push(check_permission(client, &permission, None, &options.retry).await?);
+    }
+
+    let requested: PodResources = PodResources::of(specification);
+    checks.extend(check_resource_quotas(client, namespace, &requested, specification.num_h2o_nodes, replaced, &options.retry).await?);
+    checks.extend(check_limit_ranges(client, namespace, &requested, &options.retry).await?);
+    checks.push(check_nodes(client, &requested, specification.num_h2o_nodes, &options.retry).await?);
+    return Ok(checks);
+}
+
+/// Checks the namespace exists or is going to be created.
+async fn check_namespace(client: &Client, namespace: &str, options: &DeployOptions) -> Result<Check, Error> {
+    let name: String = format!("Namespace '{}'", namespace);
+    let api: ThrottledApi<Namespace> = ThrottledApi::all(client.clone());
+    return match retry::with_retry(&options.retry, &format!("get Namespace '{}'", namespace), || api.get(namespace)).await {
+        Ok(_) => { Ok(Check::new(&name, CheckResult::Pass, "The namespace exists.".to_string())) }
+        Err(Error::Api(response)) if response.code == 403 => {
+            Ok(Check::new(&name, CheckResult::Warn, "Not permitted to read the namespace, unable to verify it exists.".to_string()))
+        }
+        Err(Error::Api(response)) if response.code == 404 => {
+            if options.create_namespace {
+                Ok(Check::new(&name, CheckResult::Pass, "The namespace does not exist and is going to be created.".to_string()))
+            } else {
+                Ok(Check::new(&name, CheckResult::Fail, "The namespace does not exist. Use '--create-namespace' to create it.".to_string()))
+            }
+        }
+        Err(e) => { Err(e) }
+    };
+}
+
+/// Checks the user is permitted to issue the given request using a SelfSubjectAccessReview.
+async fn check_permission(client: &Client, permission: &Permission, namespace: Option<&str>, retry_policy: &RetryPolicy) -> Result<Check, Error> {
+    let qualified_resource: String = if permission.group.is_empty() {
+        permission.resource.to_string()
+    } else {
+        format!("{}.{}", permission.resource, permission.group)
+    };
+    let name: String = format!("{} {}", permission.verb, qualified_resource);
+    let review: SelfSubjectAccessReview = SelfSubjectAccessReview {
+        metadata: Default::default(),
+        spec: SelfSubjectAccessReviewSpec {
+            resource_attributes: Some(ResourceAttributes {
+                verb: Some(permission.verb.to_string()),
+                group: Some(permission.group.to_string()),
+                resource: Some(permission.resource.to_string()),
+                namespace: namespace.map(str::to_string),
+                ..ResourceAttributes::default()
+            }),
+            non_resource_attributes: None,
+        },
+        status: None,
+    };
+
+    let api: ThrottledApi<SelfSubjectAccessReview> = ThrottledApi::all(client.clone());
+    let post_params: PostParams = PostParams::default();
+    let description: String = format!("create SelfSubjectAccessReview for '{}'", name);
+    let allowed: bool = match retry::with_retry(retry_policy, &description, || api.create(&post_params, &review)).await {
+        Ok(review) => { review.status.map(|status| status.allowed).unwrap_or(false) }
+        Err(Error::Api(response)) if response.code == 403 => {
+            return Ok(Check::new(&name, CheckResult::Warn, "Not permitted to review permissions, unable to verify.".to_string()));
+        }
+        Err(e) => { return Err(e); }
+    };
+
+    return Ok(if allowed {
+        Check::new(&name, CheckResult::Pass, format!("Permitted, needed to {}.", permission.purpose))
+    } else if permission.required {
+        Check::new(&name, CheckResult::Fail, format!("Not permitted, needed to {}.", permission.purpose))
+    } else {
+        Check::new(&name, CheckResult::Warn, format!("Not permitted, unable to {}.", permission.purpose))
+    });
+}
+
+/// Parses a Kubernetes quantity into the base unit of the resource, e.g. millicores or bytes.
+type QuantityParser = fn(&str) -> Option<i64>;
+
+/// CPU and memory requested by a single H2O pod. The requests equal the limits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct PodResources {
+    cpu_millis: i64,
+    memory_bytes: i64,
+}
+
+impl PodResources {
+    fn of(specification: &DeploymentSpecification) -> Self {
+        PodResources {
+            cpu_millis: specification.num_cpu as i64 * 1000,
+            // The memory is validated when parsing user input.
+            memory_bytes: parse_memory(&specification.memory).unwrap_or(0),
+        }
+    }
+}
+
+/// Checks the remaining capacity of each ResourceQuota in the namespace is sufficient for all the H2O pods.
+async fn check_resource_quotas(client: &Client, namespace: &str, requested: &PodResources, num_h2o_nodes: u32,
+                               replaced: Option<&DeploymentSpecification>, retry_policy: &RetryPolicy) -> Result<Vec<Check>, Error> {
+    let api: ThrottledApi<ResourceQuota> = ThrottledApi::namespaced(client.clone(), namespace);
+    let list_params: ListParams = ListParams::default();
+    let description: String = format!("list ResourceQuotas in Namespace '{}'", namespace);
+    let quotas: Vec<ResourceQuota> = match retry::with_retry(retry_policy, &description, || api.list(&list_params)).await {
+        Ok(quotas) => { quotas.items }
+        Err(Error::Api(response)) if response.code == 403 => {
+            return Ok(vec!(Check::new("ResourceQuotas", CheckResult::Warn, "Not permitted to list ResourceQuotas, unable to verify.".to_string())));
+        }
+        Err(e) => { return Err(e); }
+    };
+    if quotas.is_empty() {
+        return Ok(vec!(Check::new("ResourceQuotas", CheckResult::Pass, "There is no ResourceQuota in the namespace.".to_string())));
+    }
+    return Ok(quotas.iter()
+        .map(|quota| evaluate_quota(quota, requested, num_h2o_nodes, replaced))
+        .collect());
+}
+
+/// Compares the remaining capacity of the ResourceQuota with the resources requested by all the H2O pods together.
+/// Resources used by the `replaced` deployment are released before the H2O pods are created, so they count as remaining.
+fn evaluate_quota(quota: &ResourceQuota, requested: &PodResources, num_h2o_nodes: u32, replaced: Option<&DeploymentSpecification>) -> Check {
+    let name: String = format!("ResourceQuota '{}'", quota.name());
+    let status = quota.status.clone().unwrap_or_default();
+    let hard: BTreeMap<String, Quantity> = status.hard
+        .or_else(|| quota.spec.as_ref().and_then(|spec| spec.hard.clone()))
+        .unwrap_or_default();
+    let used: BTreeMap<String, Quantity> = status.used.unwrap_or_default();
+
+    let nodes: i64 = num_h2o_nodes as i64;
+    let replaced_pod: PodResources = replaced.map(PodResources::of).unwrap_or(PodResources { cpu_millis: 0, memory_bytes: 0 });
+    let replaced_nodes: i64 = replaced.map(|replaced| replaced.num_h2o_nodes as i64).unwrap_or(0);
+    let replaced_entities: i64 = if replaced.is_some() { 1 } else { 0 };
+    let mut exceeded: Vec<String> = vec!();
+    for (resource, limit) in hard.iter() {
+        let (requested_total, released, parse): (i64, i64, QuantityParser) = match resource.as_str() {
+            "cpu" | "requests.cpu" | "limits.cpu" => { (requested.cpu_millis * nodes, replaced_pod.cpu_millis * replaced_nodes, parse_cpu_millis) }
+            "memory" | "requests.memory" | "limits.memory" => { (requested.memory_bytes * nodes, replaced_pod.memory_bytes * replaced_nodes, parse_memory) }
+            "pods" | "count/pods" => { (nodes, replaced_nodes, parse_count) }
+            "services" | "count/services" | "count/statefulsets.apps" => { (1, replaced_entities, parse_count) }
+            _ => { continue; }
+        };
+        let limit: i64 = match parse(&limit.0) {
+            Some(limit) => { limit }
+            None => { continue; }
+        };
+        let used: i64 = (used.get(resource).and_then(|used| parse(&used.0)).unwrap_or(0) - released).max(0);
+        if requested_total > limit - used {
+            exceeded.push(format!("{} requested {}, {} of {} remaining", resource, requested_total, (limit - used).max(0), limit));
+        }
+    }
+
+    return if exceeded.is_empty() {
+        Check::new(&name, CheckResult::Pass, "Sufficient capacity remaining for all the H2O pods.".to_string())
+    } else {
+        Check::new(&name, CheckResult::Fail, format!("Quota exceeded: {}. CPU is in millicores, memory in bytes.", exceeded.join(", ")))
+    };
+}
+
+/// Checks the resources requested by each H2O pod are within the bounds of each LimitRange in the namespace.
+async fn check_limit_ranges(client: &Client, namespace: &str, requested: &PodResources, retry_policy: &RetryPolicy) -> Result<Vec<Check>, Error> {
+    let api: ThrottledApi<LimitRange> = ThrottledApi::namespaced(client.clone(), namespace);
+    let list_params: ListParams = ListParams::default();
+    let description: String = format!("list LimitRanges in Namespace '{}'", namespace);
+    let limit_ranges: Vec<LimitRange> = match retry::with_retry(retry_policy, &description, || api.list(&list_params)).await {
+        Ok(limit_ranges) => { limit_ranges.items }
+        Err(Error::Api(response)) if response.code == 403 => {
+            return Ok(vec!(Check::new("LimitRanges", CheckResult::Warn, "Not permitted to list LimitRanges, unable to verify.".to_string())));
+        }
+        Err(e) => { return Err(e); }
+    };
+    if limit_ranges.is_empty() {
+        return Ok(vec!(Check::new("LimitRanges", CheckResult::Pass, "There is no LimitRange in the namespace.".to_string())));
+    }
+    return Ok(limit_ranges.iter()
+        .map(|limit_range| evaluate_limit_range(limit_range, requested))
+        .collect());
+}
+
+/// Compares the resources of a single H2O pod with the minimum and maximum of the LimitRange. An H2O pod has a single container,
+/// therefore both the container and the pod limits apply to the very same values.
+fn evaluate_limit_range(limit_range: &LimitRange, requested: &PodResources) -> Check {
+    let name: String = format!("LimitRange '{}'", limit_range.name());
+    let mut violations: Vec<String> = vec!();
+    for item in limit_range.spec.iter().flat_map(|spec| spec.limits.iter()) {
+        let kind: &str = match item.type_.as_deref() {
+            Some(kind) if kind == "Container" || kind == "Pod" => { kind }
+            _ => { continue; }
+        };
+        let bounds: [(&str, i64, QuantityParser); 2] = [("cpu", requested.cpu_millis, parse_cpu_millis), ("memory", requested.memory_bytes, parse_memory)];
+        for (resource, value, parse) in bounds.iter() {
+            if let Some(max) = item.max.as_ref().and_then(|max| max.get(*resource)).and_then(|max| parse(&max.0)) {
+                if *value > max {
+                    violations.push(format!("{} {} requested {} exceeds the maximum of {}", kind, resource, value, max));
+                }
+            }
+            if let Some(min) = item.min.as_ref().and_then(|min| min.get(*resource)).and_then(|min| parse(&min.0)) {
+                if *value < min {
+                    violations.push(format!("{} {} requested {} is below the minimum of {}", kind, resource, value, min));
+                }
+            }
+        }
+    }
+
+    return if violations.is_empty() {
+        Check::new(&name, CheckResult::Pass, "H2O pod resources are within the limits.".to_string())
+    } else {
+        Check::new(&name, CheckResult::Fail, format!("{}. CPU is in millicores, memory in bytes.", violations.join(", ")))
+    };
+}
+
+/// Checks there are enough schedulable nodes with allocatable capacity sufficient for a single H2O pod each.
+async fn check_nodes(client: &Client, requested: &PodResources, num_h2o_nodes: u32, retry_policy: &RetryPolicy) -> Result<Check, Error> {
+    let api: ThrottledApi<Node> = ThrottledApi::all(client.clone());
+    let list_params: ListParams = ListParams::default();
+    let nodes: Vec<Node> = match retry::with_retry(retry_policy, "list Nodes", || api.list(&list_params)).await {
+        Ok(nodes) => { nodes.items }
+        Err(Error::Api(response)) if response.code == 403 => {
+            return Ok(Check::new("Node capacity", CheckResult::Warn, "Not permitted to list nodes, unable to verify.".to_string()));
+        }
+        Err(e) => { return Err(e); }
+    };
+    return Ok(evaluate_nodes(&nodes, requested, num_h2o_nodes));
+}
+
+/// Counts the schedulable nodes able to run a single H2O pod. Nodes cordoned or tainted so H2O pods are not scheduled
+/// onto them are not counted. Resources already requested by other pods are not taken into account.
+fn evaluate_nodes(nodes: &[Node], requested: &PodResources, num_h2o_nodes: u32) -> Check {
+    let fitting: usize = nodes.iter()
+        .filter(|node| schedulable(node))
+        .filter(|node| {
+            let allocatable: BTreeMap<String, Quantity> = node.status.as_ref().and_then(|status| status.allocatable.clone()).unwrap_or_default();
+            let cpu: i64 = allocatable.get("cpu").and_then(|cpu| parse_cpu_millis(&cpu.0)).unwrap_or(0);
+            let memory: i64 = allocatable.get("memory").and_then(|memory| parse_memory(&memory.0)).unwrap_or(0);
+            cpu >= requested.cpu_millis && memory >= requested.memory_bytes
+        })
+        .count();
+
+    let explanation: String = format!("{} of {} nodes have enough allocatable capacity for an H2O pod, {} H2O nodes requested.",
+                                      fitting, nodes.len(), num_h2o_nodes);
+    return if fitting >= num_h2o_nodes as usize {
+        Check::new("Node capacity", CheckResult::Pass, explanation)
+    } else if fitting > 0 {
+        Check::new("Node capacity", CheckResult::Warn, format!("{} Some H2O pods have to share a node.", explanation))
+    } else {
+        Check::new("Node capacity", CheckResult::Fail, format!("{} H2O pods can not be scheduled.", explanation))
+    };
+}
+
+/// True if pods without tolerations may be scheduled onto the node.
+fn schedulable(node: &Node) -> bool {
+    let spec = match &node.spec {
+        Some(spec) => { spec }
+        None => { return true; }
+    };
+    if spec.unschedulable.unwrap_or(false) {
+        return false;
+    }
+    return spec.taints.iter().flatten()
+        .all(|taint| taint.effect != "NoSchedule" && taint.effect != "NoExecute");
+}
+
+/// Parses a Kubernetes CPU quantity, e.g. `2`, `0.5` or `1500m`, into millicores.
+fn parse_cpu_millis(quantity: &str) -> Option<i64> {
+    return match quantity.strip_suffix('m') {
+        Some(millicores) => { millicores.parse::<i64>().ok() }
+        None => { quantity.parse::<f64>().ok().map(|cores| (cores * 1000.0).ceil() as i64) }
+    };
+}
+
+/// Parses a Kubernetes memory quantity, e.g. `512Mi`, `4Gi` or `1G`, into bytes.
+pub fn parse_memory(quantity: &str) -> Option<i64> {
+    let suffixes: [(&str, f64); 12] = [
+        ("Ki", 1024f64), ("Mi", 1024f64.powi(2)), ("Gi", 1024f64.powi(3)), ("Ti", 1024f64.powi(4)), ("Pi", 1024f64.powi(5)), ("Ei", 1024f64.powi(6)),
+        ("k", 1e3), ("M", 1e6), ("G", 1e9), ("T", 1e12), ("P", 1e15), ("E", 1e18),
+    ];
+    for (suffix, multiplier) in suffixes.iter() {
+        if let Some(number) = quantity.strip_suffix(suffix) {
+            return number.parse::<f64>().ok().map(|number| (number * multiplier).ceil() as i64);
+        }
+    }
+    return quantity.parse::<f64>().ok().map(|bytes| bytes.ceil() as i64);
+}
+
+/// Parses an object count quantity, e.g. `10`.
+fn parse_count(quantity: &str) -> Option<i64> {
+    return quantity.parse::<i64>().ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::core::v1::{LimitRange, Node, ResourceQuota};
+
+    use crate::k8s::DeploymentSpecification;
+
+    use super::{CheckResult, PodResources};
+
+    const POD: PodResources = PodResources { cpu_millis: 2000, memory_bytes: 4 * 1024 * 1024 * 1024 };
+
+    #[test]
+    fn test_parse_quantities() {
+        assert_eq!(Some(1500), super::parse_cpu_millis("1500m"));
+        assert_eq!(Some(500), super::parse_cpu_millis("0.5"));
+        assert_eq!(Some(4000), super::parse_cpu_millis("4"));
+        assert_eq!(Some(512 * 1024 * 1024), super::parse_memory("512Mi"));
+        assert_eq!(Some(1_000_000_000), super::parse_memory("1G"));
+        assert_eq!(Some(1024), super::parse_memory("1024"));
+        assert_eq!(None, super::parse_memory("lots"));
+    }
+
+    #[test]
+    fn test_evaluate_quota() {
+        let quota: ResourceQuota = serde_json::from_value(serde_json::json!({
+            "metadata": { "name": "compute" },
+            "status": {
+                "hard": { "requests.cpu": "8", "requests.memory": "16Gi", "pods": "10" },
+                "used": { "requests.cpu": "2", "requests.memory": "4Gi", "pods": "2" }
+            }
+        })).unwrap();
+        assert_eq!(CheckResult::Pass, super::evaluate_quota(&quota, &POD, 3, None).result);
+        let exceeded = super::evaluate_quota(&quota, &POD, 4, None);
+        assert_eq!(CheckResult::Fail, exceeded.result);
+        assert!(exceeded.explanation.contains("requests.cpu"));
+        assert!(exceeded.explanation.contains("requests.memory"));
+        assert!(!exceeded.explanation.contains("pods"));
+    }
+
+    #[test]
+    fn test_evaluate_quota_replaced() {
+        // Full only because of the deployment being replaced, 3 pods with 2 CPUs and 4Gi each.
+        let quota: ResourceQuota = serde_json::from_value(serde_json::json!({
+            "metadata": { "name": "compute" },
+            "status": {
+                "hard": { "requests.cpu": "6", "requests.memory": "12Gi", "pods": "3", "count/statefulsets.apps": "1" },
+                "used": { "requests.cpu": "6", "requests.memory": "12Gi", "pods": "3", "count/statefulsets.apps": "1" }
+            }
+        })).unwrap();
+        let replaced: DeploymentSpecification = DeploymentSpecification::new("h2o-test".to_string(), "h2o".to_string(), 60,
+                                                                             "4Gi".to_string(), 2, 3, None);
+        assert_eq!(CheckResult::Fail, super::evaluate_quota(&quota, &POD, 3, None).result);
+        assert_eq!(CheckResult::Pass, super::evaluate_quota(&quota, &POD, 3, Some(&replaced)).result);
+        let larger = super::evaluate_quota(&quota, &POD, 4, Some(&replaced));
+        assert_eq!(CheckResult::Fail, larger.result);
+        assert!(larger.explanation.contains("pods requested 4, 3 of 3 remaining"));
+    }
+
+    #[test]
+    fn test_evaluate_limit_range() {
+        let limit_range: LimitRange = serde_json::from_value(serde_json::json!({
+            "metadata": { "name": "limits" },
+            "spec": { "limits": [
+                { "type": "Container", "max": { "cpu": "4", "memory": "2Gi" }, "min": { "cpu": "100m" } },
+                { "type": "PersistentVolumeClaim", "max": { "storage": "1Gi" } }
+            ]}
+        })).unwrap();
+        let violated = super::evaluate_limit_range(&limit_range, &POD);
+        assert_eq!(CheckResult::Fail, violated.result);
+        assert!(violated.explanation.contains("Container memory"));
+        assert!(!violated.explanation.contains("cpu"));
+
+        let small_pod: PodResources = PodResources { cpu_millis: 1000, memory_bytes: 1024 * 1024 * 1024 };
+        assert_eq!(CheckResult::Pass, super::evaluate_limit_range(&limit_range, &small_pod).result);
+    }
+
+    #[test]
+    fn test_evaluate_nodes() {
+        let node = |name: &str, cpu: &str, memory: &str, unschedulable: bool| -> Node {
+            serde_json::from_value(serde_json::json!({
+                "metadata": { "name": name },
+                "spec": { "unschedulable": unschedulable },
+                "status": { "allocatable": { "cpu": cpu, "memory": memory } }
+            })).unwrap()
+        };
+        let nodes: Vec<Node> = vec!(node("large-1", "8", "32Gi", false), node("large-2", "7800m", "30Gi", false),
+                                    node("small", "1", "2Gi", false), node("cordoned", "8", "32Gi", true));
+        assert_eq!(CheckResult::Pass, super::evaluate_nodes(&nodes, &POD, 2).result);
+        assert_eq!(CheckResult::Warn, super::evaluate_nodes(&nodes, &POD, 3).result);
+        assert_eq!(CheckResult::Fail, super::evaluate_nodes(&nodes[2..], &POD, 1).result);
+    }
+}
@@ -0,0 +1,86 @@
+use std::collections::BTreeMap;
+
+use k8s_openapi::api::apps::v1::StatefulSet;
+use kube::{Client, Error};
+use kube::api::{Meta, PatchParams, PatchStrategy};
+use serde_json::json;
+use tokio::runtime::Runtime;
+
+use crate::k8s::{ANNOTATION_PROTECTED, Deployment};
+use crate::k8s::retry;
+use crate::k8s::retry::{RetryPolicy, ThrottledApi};
+
+/// Returns true if the given stateful set is annotated as protected against deletion, otherwise false.
+pub fn is_protected(stateful_set: &StatefulSet) -> bool {
+    return stateful_set.meta().annotations.as_ref()
+        .and_then(|annotations| annotations.get(ANNOTATION_PROTECTED))
+        .map(|protected| protected == "true")
+        .unwrap_or(false);
+}
+
+/// Marks the given stateful set template as protected against deletion.
+pub fn protect_template(stateful_set: &mut StatefulSet) {
+    stateful_set.metadata.annotations.get_or_insert_with(BTreeMap::new)
+        .insert(ANNOTATION_PROTECTED.to_string(), "true".to_string());
+}
+
+/// Sets or removes the deletion protection annotation on all the stateful sets of the given deployment.
+/// The patch is the same however many times applied, so it is retried according to the `retry_policy`.
+pub fn set_protection(client: &Client, deployment: &Deployment, protected: bool, retry_policy: &RetryPolicy) -> Result<(), Error> {
+    let mut tokio_runtime: Runtime = tokio::runtime::Runtime::new().unwrap();
+    let api: ThrottledApi<StatefulSet> = ThrottledApi::namespaced(client.clone(), &deployment.specification.namespace);
+    // JSON merge patch - a null value removes the annotation.
+    let annotation_value: serde_json::Value = if protected { json!("true") } else { json!(null) };
+    let patch: serde_json::Value = json!({
+        "metadata": {
+            "annotations": {
+                ANNOTATION_PROTECTED: annotation_value
+            }
+        }
+    });
+    let patch_params: PatchParams = PatchParams { patch_strategy: PatchStrategy::Merge, ..PatchParams::default() };
+
+    for stateful_set in deployment.stateful_sets.iter() {
+        let name: String = stateful_set.name();
+        let description: String = format!("patch StatefulSet '{}'", name);
+        tokio_runtime.block_on(retry::with_retry(retry_policy, &description, || api.patch(&name, &patch_params, &patch)))?;
+    }
+    return Ok(());
+}
+
+/// Returns names of the stateful sets of the given deployment which are currently protected against deletion.
+/// The up-to-date state is read from the Kubernetes cluster, stateful sets already gone are ignored.
+pub fn protected_stateful_sets(tokio_runtime: &mut Runtime, client: &Client, deployment: &Deployment,
+                               retry_policy: &RetryPolicy) -> Result<Vec<String>, Error> {
+    let api: ThrottledApi<StatefulSet> = ThrottledApi::namespaced(client.clone(), &deployment.specification.namespace);
+    let mut protected: Vec<String> = vec!();
+    for stateful_set in deployment.stateful_sets.iter() {
+        let name: String = stateful_set.name();
+        match tokio_runtime.block_on(retry::with_retry(retry_policy, &format!("get StatefulSet '{}'", name), || api.get(&name))) {
+            Ok(current_stateful_set) => {
+                if is_protected(&current_stateful_set) {
+                    protected.push(current_stateful_set.name());
+                }
+            }
+            Err(Error::Api(response)) if response.code == 404 => {}
+            Err(e) => { return Err(e); }
+        }
+    }
+    return Ok(protected);
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::apps::v1::StatefulSet;
+
+    use crate::k8s::templates;
+
+    #[test]
+    fn test_protect_template() {
+        let mut stateful_set: StatefulSet = templates::h2o_stateful_set("h2o-test", "default", "h2oai/h2o-open-source-k8s", "latest",
+                                                                        1, 50, "1Gi", 1);
+        assert!(!super::is_protected(&stateful_set));
+        super::protect_template(&mut stateful_set);
+        assert!(super::is_protected(&stateful_set));
+    }
+}
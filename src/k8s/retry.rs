@@ -0,0 +1,346 @@
+use std::future::Future;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use futures::TryStreamExt;
+use kube::{Client, Error};
+use kube::api::{DeleteParams, ListParams, ObjectList, PatchParams, PostParams, Resource};
+use kube::error::ErrorResponse;
+use rand::Rng;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+/// Delay before the first retry. Doubled with each subsequent retry.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+/// Upper bound of the delay between two attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+/// Minimal delay after the API server responded with `429 Too Many Requests` without telling when to retry. Kubernetes API server
+/// asks for a retry after 1 second by default.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
+
+/// Policy of retrying Kubernetes API requests failed due to transient errors.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Maximum number of retries of a single request. Zero disables retries.
+    pub retries: u32,
+    /// Maximum duration of a single request attempt. If not set, the attempt is not limited in time.
+    pub request_timeout: Option<Duration>,
+    /// If true, each retry is reported on stderr.
+    pub verbose: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy { retries: 3, request_timeout: None, verbose: false }
+    }
+}
+
+/// Outcome of a single failed request attempt.
+#[derive(Debug)]
+pub enum AttemptError {
+    /// The Kubernetes API request failed.
+    Api(Error),
+    /// The Kubernetes API request failed and the API server asked to retry it after the given delay, e.g. as it is throttled.
+    Throttled(ErrorResponse, Duration),
+    /// The request did not finish within the request timeout.
+    Timeout(Duration),
+}
+
+impl From<Error> for AttemptError {
+    fn from(e: Error) -> Self {
+        return AttemptError::Api(e);
+    }
+}
+
+/// Kubernetes API of a single kind of entities, the same as `kube::Api`, except failed requests report the delay
+/// the API server asked for before a retry, as `AttemptError::Throttled`. Meant to be used with `with_retry`.
+pub struct ThrottledApi<K> {
+    client: Client,
+    resource: Resource,
+    phantom: PhantomData<K>,
+}
+
+impl<K: k8s_openapi::Resource> ThrottledApi<K> {
+    /// Cluster level entities, or entities in all the namespaces.
+    pub fn all(client: Client) -> Self {
+        return ThrottledApi { client, resource: Resource::all::<K>(), phantom: PhantomData };
+    }
+
+    /// Entities in the given namespace.
+    pub fn namespaced(client: Client, namespace: &str) -> Self {
+        return ThrottledApi { client, resource: Resource::namespaced::<K>(namespace), phantom: PhantomData };
+    }
+}
+
+impl<K: Clone + DeserializeOwned> ThrottledApi<K> {
+    pub async fn get(&self, name: &str) -> Result<K, AttemptError> {
+        return request(&self.client, self.resource.get(name)?).await;
+    }
+
+    pub async fn list(&self, list_params: &ListParams) -> Result<ObjectList<K>, AttemptError> {
+        return request(&self.client, self.resource.list(list_params)?).await;
+    }
+
+    pub async fn create(&self, post_params: &PostParams, entity: &K) -> Result<K, AttemptError> where K: Serialize {
+        let body: Vec<u8> = serde_json::to_vec(entity).map_err(Error::SerdeError)?;
+        return request(&self.client, self.resource.create(post_params, body)?).await;
+    }
+
+    pub async fn replace(&self, name: &str, post_params: &PostParams, entity: &K) -> Result<K, AttemptError> where K: Serialize {
+        let body: Vec<u8> = serde_json::to_vec(entity).map_err(Error::SerdeError)?;
+        return request(&self.client, self.resource.replace(name, post_params, body)?).await;
+    }
+
+    pub async fn patch(&self, name: &str, patch_params: &PatchParams, patch: &Value) -> Result<K, AttemptError> {
+        let body: Vec<u8> = serde_json::to_vec(patch).map_err(Error::SerdeError)?;
+        return request(&self.client, self.resource.patch(name, patch_params, body)?).await;
+    }
+
+    /// Deletes the entity with the given name. The entity being deleted, or the Status confirming the deletion, is not returned.
+    pub async fn delete(&self, name: &str, delete_params: &DeleteParams) -> Result<(), AttemptError> {
+        return request::<Value>(&self.client, self.resource.delete(name, delete_params)?).await.map(|_| ());
+    }
+}
+
+/// Sends the Kubernetes API request and deserializes the response. `kube::Client` does not expose the status code and headers
+/// of responses read as a whole, failures are therefore recognized by the Status returned by the API server.
+async fn request<T: DeserializeOwned>(client: &Client, request: http::Request<Vec<u8>>) -> Result<T, AttemptError> {
+    let body: Vec<u8> = client.request_text_stream(request).await?
+        .try_fold(Vec::new(), |mut body, chunk| async move {
+            body.extend_from_slice(&chunk);
+            return Ok(body);
+        }).await?;
+    return parse_response(&body);
+}
+
+/// Parses the body of a Kubernetes API response. A Status with the `Failure` status is returned as an error, along with the delay
+/// before a retry, if the API server asked for one. A response which is not JSON, e.g. an error page of a proxy in front of
+/// the API server, is reported as `502 Bad Gateway`.
+fn parse_response<T: DeserializeOwned>(body: &[u8]) -> Result<T, AttemptError> {
+    let value: Value = match serde_json::from_slice(body) {
+        Ok(value) => { value }
+        Err(_) => {
+            return Err(AttemptError::Api(Error::Api(ErrorResponse {
+                status: "Failure".to_string(),
+                message: String::from_utf8_lossy(body).to_string(),
+                reason: "Failed to parse response".to_string(),
+                code: 502,
+            })));
+        }
+    };
+    if value["kind"] == "Status" && value["status"] == "Failure" {
+        let response: ErrorResponse = ErrorResponse {
+            status: "Failure".to_string(),
+            message: value["message"].as_str().unwrap_or_default().to_string(),
+            reason: value["reason"].as_str().unwrap_or_default().to_string(),
+            code: value["code"].as_u64().map(|code| code as u16).unwrap_or(500),
+        };
+        return match value["details"]["retryAfterSeconds"].as_u64().filter(|seconds| *seconds > 0) {
+            Some(seconds) => { Err(AttemptError::Throttled(response, Duration::from_secs(seconds))) }
+            None => { Err(AttemptError::Api(Error::Api(response))) }
+        };
+    }
+    return serde_json::from_value(value).map_err(|e| AttemptError::Api(Error::SerdeError(e)));
+}
+
+/// Runs the given `operation` until it succeeds, fails with a non-retryable error or the number of retries
+/// defined by the `policy` is exhausted. Retries are delayed by an exponential backoff with jitter, or as long as
+/// the API server asks for, if it does. `description` of the operation, e.g. `create Service 'h2o-service'`, is used
+/// to report retries in verbose mode.
+pub async fn with_retry<T, E, F, Fut>(policy: &RetryPolicy, description: &str, mut operation: F) -> Result<T, Error>
+    where F: FnMut() -> Fut,
+          Fut: Future<Output=Result<T, E>>,
+          E: Into<AttemptError> {
+    let mut attempt: u32 = 0;
+    loop {
+        let result: Result<T, AttemptError> = match policy.request_timeout {
+            None => { operation().await.map_err(Into::into) }
+            Some(request_timeout) => {
+                match tokio::time::timeout(request_timeout, operation()).await {
+                    Ok(result) => { result.map_err(Into::into) }
+                    Err(_) => { Err(AttemptError::Timeout(request_timeout)) }
+                }
+            }
+        };
+
+        let error: AttemptError = match result {
+            Ok(value) => { return Ok(value); }
+            Err(error) => { error }
+        };
+        if attempt >= policy.retries || !is_retryable(&error) {
+            return Err(into_error(error));
+        }
+
+        attempt += 1;
+        let delay: Duration = retry_delay(attempt, &error);
+        if policy.verbose {
+            eprintln!("Unable to {} (attempt {} of {}). Reason: {}. Retrying in {} ms.", description, attempt, policy.retries + 1,
+                      describe(&error), delay.as_millis());
+        }
+        tokio::time::delay_for(delay).await;
+    }
+}
+
+/// Returns true if the request failed due to a transient condition and may succeed when retried - the API server
+/// being throttled or temporarily unavailable, or a network failure.
+pub fn is_retryable(error: &AttemptError) -> bool {
+    return match error {
+        AttemptError::Timeout(_) => { true }
+        AttemptError::Api(Error::Api(response)) | AttemptError::Throttled(response, _) => { matches!(response.code, 429 | 500 | 502 | 503 | 504) }
+        AttemptError::Api(Error::ReqwestError(e)) => { !e.is_builder() && !e.is_decode() && !e.is_redirect() }
+        AttemptError::Api(_) => { false }
+    };
+}
+
+/// Delay before the given retry attempt (starting with 1). Exponential backoff, capped at `MAX_BACKOFF`, with a random jitter
+/// of up to half of the delay. If the API server asks for a delay, it is used instead, capped at `MAX_BACKOFF` as well.
+/// Throttled requests are never retried sooner than `DEFAULT_RETRY_AFTER`.
+fn retry_delay(attempt: u32, error: &AttemptError) -> Duration {
+    let backoff: Duration = INITIAL_BACKOFF.checked_mul(2u32.saturating_pow(attempt - 1))
+        .map(|backoff| backoff.min(MAX_BACKOFF))
+        .unwrap_or(MAX_BACKOFF);
+    let jitter: Duration = backoff.mul_f64(rand::thread_rng().gen_range(0.0, 0.5));
+    let delay: Duration = backoff - jitter;
+    return match error {
+        AttemptError::Throttled(_, retry_after) => { (*retry_after).min(MAX_BACKOFF) }
+        AttemptError::Api(Error::Api(response)) if response.code == 429 => { delay.max(DEFAULT_RETRY_AFTER) }
+        _ => { delay }
+    };
+}
+
+fn describe(error: &AttemptError) -> String {
+    return match error {
+        AttemptError::Api(e) => { e.to_string() }
+        AttemptError::Throttled(response, _) => { Error::Api(response.clone()).to_string() }
+        AttemptError::Timeout(request_timeout) => { format!("Request timed out after {:?}", request_timeout) }
+    };
+}
+
+/// Converts the error of the last attempt into a Kubernetes API error. A timed out request is reported
+/// as `504 Gateway Timeout`.
+pub fn into_error(error: AttemptError) -> Error {
+    return match error {
+        AttemptError::Api(e) => { e }
+        AttemptError::Throttled(response, _) => { Error::Api(response) }
+        AttemptError::Timeout(request_timeout) => {
+            Error::Api(ErrorResponse {
+                status: "Failure".to_string(),
+                message: format!("Request timed out after {:?}", request_timeout),
+                reason: "Timeout".to_string(),
+                code: 504,
+            })
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use kube::Error;
+    use kube::error::ErrorResponse;
+
+    use serde_json::Value;
+
+    use super::{AttemptError, MAX_BACKOFF, RetryPolicy};
+
+    fn api_error(code: u16) -> AttemptError {
+        return AttemptError::Api(Error::Api(ErrorResponse { status: "Failure".to_string(), message: String::new(), reason: String::new(), code }));
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        assert!(super::is_retryable(&api_error(429)));
+        assert!(super::is_retryable(&api_error(500)));
+        assert!(super::is_retryable(&api_error(503)));
+        assert!(super::is_retryable(&AttemptError::Timeout(Duration::from_secs(1))));
+        assert!(!super::is_retryable(&api_error(404)));
+        assert!(!super::is_retryable(&api_error(409)));
+        assert!(!super::is_retryable(&api_error(422)));
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        for attempt in 1..20 {
+            let delay: Duration = super::retry_delay(attempt, &api_error(500));
+            assert!(delay <= MAX_BACKOFF);
+            assert!(delay >= Duration::from_millis(250));
+        }
+        assert!(super::retry_delay(1, &api_error(429)) >= Duration::from_secs(1));
+        let throttled: AttemptError = AttemptError::Throttled(ErrorResponse { status: "Failure".to_string(), message: String::new(), reason: String::new(), code: 429 },
+                                                              Duration::from_secs(5));
+        assert_eq!(Duration::from_secs(5), super::retry_delay(1, &throttled));
+        let throttled: AttemptError = AttemptError::Throttled(ErrorResponse { status: "Failure".to_string(), message: String::new(), reason: String::new(), code: 429 },
+                                                              Duration::from_secs(600));
+        assert_eq!(MAX_BACKOFF, super::retry_delay(1, &throttled));
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let throttled: &str = r#"{"kind":"Status","apiVersion":"v1","status":"Failure","message":"Too many requests, please try again later.",
+                                  "reason":"TooManyRequests","details":{"retryAfterSeconds":7},"code":429}"#;
+        match super::parse_response::<Value>(throttled.as_bytes()) {
+            Err(AttemptError::Throttled(response, retry_after)) => {
+                assert_eq!(429, response.code);
+                assert_eq!("TooManyRequests", response.reason);
+                assert_eq!(Duration::from_secs(7), retry_after);
+            }
+            _ => { panic!("Expected the request to be throttled."); }
+        }
+
+        let not_found: &str = r#"{"kind":"Status","apiVersion":"v1","status":"Failure","message":"services \"h2o\" not found","reason":"NotFound","code":404}"#;
+        match super::parse_response::<Value>(not_found.as_bytes()) {
+            Err(AttemptError::Api(Error::Api(response))) => { assert_eq!(404, response.code); }
+            _ => { panic!("Expected the request to fail."); }
+        }
+        match super::parse_response::<Value>(b"<html>Bad Gateway</html>") {
+            Err(AttemptError::Api(Error::Api(response))) => { assert_eq!(502, response.code); }
+            _ => { panic!("Expected the request to fail."); }
+        }
+        // A successful deletion is confirmed by a Status as well.
+        let deleted: &str = r#"{"kind":"Status","apiVersion":"v1","status":"Success","details":{"name":"h2o","kind":"services"}}"#;
+        assert!(super::parse_response::<Value>(deleted.as_bytes()).is_ok());
+    }
+
+    #[test]
+    fn test_with_retry() {
+        let policy: RetryPolicy = RetryPolicy { retries: 2, request_timeout: None, verbose: false };
+        let mut runtime = tokio::runtime::Runtime::new().unwrap();
+        let mut attempts: u32 = 0;
+        let result: Result<(), Error> = runtime.block_on(super::with_retry(&policy, "test", || {
+            attempts += 1;
+            async { Err(Error::RequestValidation("Non-retryable".to_string())) }
+        }));
+        assert!(result.is_err());
+        assert_eq!(1, attempts);
+
+        // Transient errors are retried until the operation succeeds.
+        let mut attempts: u32 = 0;
+        let result: Result<u32, Error> = runtime.block_on(super::with_retry(&policy, "test", || {
+            attempts += 1;
+            let attempt: u32 = attempts;
+            async move {
+                return match attempt {
+                    1 => { Err(api_error(503)) }
+                    2 => { Err(api_error(429)) }
+                    _ => { Ok(attempt) }
+                };
+            }
+        }));
+        assert_eq!(3, result.unwrap());
+        assert_eq!(3, attempts);
+
+        // The last error is returned once the retries are exhausted.
+        let mut attempts: u32 = 0;
+        let result: Result<(), Error> = runtime.block_on(super::with_retry(&policy, "test", || {
+            attempts += 1;
+            async { Err(api_error(500)) }
+        }));
+        match result {
+            Err(Error::Api(response)) => { assert_eq!(500, response.code); }
+            _ => { panic!("Expected the retries to be exhausted."); }
+        }
+        assert_eq!(policy.retries + 1, attempts);
+    }
+}
@@ -0,0 +1,119 @@
+#[cfg(unix)]
+use std::sync::Mutex;
+
+/// Termination signals intercepted while a deployment in progress can be rolled back.
+#[cfg(unix)]
+const TERMINATION_SIGNALS: [libc::c_int; 2] = [libc::SIGINT, libc::SIGTERM];
+
+/// Handlers of the termination signals installed by tokio, set aside while no deployment is in progress.
+#[cfg(unix)]
+static SUSPENDED_HANDLERS: Mutex<Vec<(libc::c_int, libc::sigaction)>> = Mutex::new(Vec::new());
+
+/// Time a deployment in progress intercepts termination signals in, to roll itself back. Once tokio handles a signal,
+/// its handler stays installed for the rest of the process and swallows the signal when nobody listens. The handlers
+/// are therefore set aside once the window is dropped, so Ctrl-C terminates h2ok again, and put back by the next window.
+/// On Windows, Ctrl-C nobody listens to is passed to the default handler by tokio itself.
+pub struct SignalWindow {
+    _private: (),
+}
+
+impl SignalWindow {
+    pub fn open() -> Self {
+        resume_handlers();
+        return SignalWindow { _private: () };
+    }
+}
+
+impl Drop for SignalWindow {
+    fn drop(&mut self) {
+        suspend_handlers();
+    }
+}
+
+/// Resolves once the process receives a termination request - SIGINT (Ctrl-C) or SIGTERM. Returns name of the signal,
+/// or an error if the signals can not be intercepted.
+#[cfg(unix)]
+pub async fn shutdown_signal() -> Result<&'static str, std::io::Error> {
+    let mut interrupt = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::interrupt())?;
+    let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+    return Ok(tokio::select! {
+        _ = interrupt.recv() => { "SIGINT" }
+        _ = terminate.recv() => { "SIGTERM" }
+    });
+}
+
+/// Resolves once the process receives a termination request - Ctrl-C. Returns name of the signal,
+/// or an error if the signal can not be intercepted.
+#[cfg(not(unix))]
+pub async fn shutdown_signal() -> Result<&'static str, std::io::Error> {
+    tokio::signal::ctrl_c().await?;
+    return Ok("Ctrl-C");
+}
+
+#[cfg(unix)]
+fn resume_handlers() {
+    let mut suspended = SUSPENDED_HANDLERS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    for (signal, handler) in suspended.drain(..) {
+        // Safe, the handler has been installed by tokio before.
+        unsafe { libc::sigaction(signal, &handler, std::ptr::null_mut()); }
+    }
+}
+
+#[cfg(unix)]
+fn suspend_handlers() {
+    let mut suspended = SUSPENDED_HANDLERS.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    for signal in TERMINATION_SIGNALS.iter() {
+        // Safe, both structures are fully initialized and the default action is always a valid one.
+        unsafe {
+            let mut default: libc::sigaction = std::mem::zeroed();
+            default.sa_sigaction = libc::SIG_DFL;
+            let mut handler: libc::sigaction = std::mem::zeroed();
+            if libc::sigaction(*signal, &default, &mut handler) == 0 && handler.sa_sigaction != libc::SIG_DFL {
+                suspended.push((*signal, handler));
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn resume_handlers() {}
+
+#[cfg(not(unix))]
+fn suspend_handlers() {}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::time::Duration;
+
+    use super::SignalWindow;
+
+    fn is_default_handler(signal: libc::c_int) -> bool {
+        // Safe, the current handler is only read.
+        unsafe {
+            let mut handler: libc::sigaction = std::mem::zeroed();
+            libc::sigaction(signal, std::ptr::null(), &mut handler);
+            return handler.sa_sigaction == libc::SIG_DFL;
+        }
+    }
+
+    #[test]
+    fn test_signal_window() {
+        let mut runtime = tokio::runtime::Runtime::new().unwrap();
+        let window: SignalWindow = SignalWindow::open();
+        runtime.block_on(async {
+            tokio::select! {
+                signal = super::shutdown_signal() => { panic!("Unexpected signal: {:?}", signal); }
+                _ = tokio::time::delay_for(Duration::from_millis(10)) => {}
+            }
+        });
+        assert!(!is_default_handler(libc::SIGTERM));
+        drop(window);
+        assert!(is_default_handler(libc::SIGTERM));
+
+        // Handlers installed once are put back by the next window.
+        let window: SignalWindow = SignalWindow::open();
+        assert!(!is_default_handler(libc::SIGTERM));
+        drop(window);
+        assert!(is_default_handler(libc::SIGTERM));
+    }
+}
@@ -0,0 +1,285 @@
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::k8s::{Deployment, DeployOptions, DeploymentSpecification, protection};
+use crate::k8s::preflight::parse_memory;
+use crate::k8s::retry::RetryPolicy;
+
+/// Version of the cluster spec format understood by this version of h2ok.
+pub const API_VERSION: &str = "h2ok/v1";
+/// Kind of the only document a cluster spec consists of.
+pub const KIND: &str = "H2OCluster";
+
+/// Declarative specification of an H2O cluster, as written by the user into a cluster spec file, e.g.:
+///
+/// ```yaml
+/// apiVersion: h2ok/v1
+/// kind: H2OCluster
+/// metadata:
+///   name: h2o-team
+///   namespace: h2o
+/// spec:
+///   nodes: 3
+///   memory: 4Gi
+///   cpus: 2
+///   ingress:
+///     enabled: true
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ClusterSpec {
+    pub api_version: String,
+    pub kind: String,
+    pub metadata: SpecMetadata,
+    pub spec: H2OClusterSpec,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SpecMetadata {
+    /// Name of the deployment, used as prefix of the Kubernetes entities.
+    pub name: String,
+    /// Namespace to deploy to. If not specified, kubeconfig default is used.
+    pub namespace: Option<String>,
+}
+
+/// Desired state of the H2O cluster. Defaults are the same as the ones of the `deploy` command.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct H2OClusterSpec {
+    /// Total count of H2O nodes inside the cluster.
+    pub nodes: u32,
+    /// Total memory for each H2O node. Effectively a pod memory request and limit.
+    #[serde(default = "default_memory")]
+    pub memory: String,
+    /// Number of CPUs allocated for each H2O node. Effectively a pod CPU request and limit.
+    #[serde(default = "default_cpus")]
+    pub cpus: u32,
+    /// Memory percentage to allocate by the JVM running H2O inside the docker container.
+    #[serde(default = "default_memory_percentage")]
+    pub memory_percentage: u8,
+    /// If true, the H2O stateful set is protected against deletion.
+    #[serde(default)]
+    pub protect: bool,
+    /// If true, a missing namespace is created.
+    #[serde(default)]
+    pub create_namespace: bool,
+    #[serde(default)]
+    pub ingress: IngressSpec,
+}
+
+/// Exposure of the H2O cluster outside of the Kubernetes cluster.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct IngressSpec {
+    /// If true, the H2O cluster is exposed by an ingress.
+    #[serde(default)]
+    pub enabled: bool,
+}
+
+fn default_memory() -> String {
+    return "1Gi".to_string();
+}
+
+fn default_cpus() -> u32 {
+    return 1;
+}
+
+fn default_memory_percentage() -> u8 {
+    return 50;
+}
+
+impl ClusterSpec {
+    /// Parses a cluster spec from a YAML document. As JSON is a subset of YAML, JSON cluster specs are accepted as well.
+    /// Returns a human-readable reason if the document is not a valid cluster spec.
+    pub fn parse(document: &str) -> Result<Self, String> {
+        let cluster_spec: ClusterSpec = serde_yaml::from_str(document).map_err(|e| e.to_string())?;
+        cluster_spec.validate()?;
+        return Ok(cluster_spec);
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.api_version != API_VERSION {
+            return Err(format!("Unsupported apiVersion '{}', expected '{}'.", self.api_version, API_VERSION));
+        }
+        if self.kind != KIND {
+            return Err(format!("Unsupported kind '{}', expected '{}'.", self.kind, KIND));
+        }
+        if self.metadata.name.is_empty() {
+            return Err("The name must not be empty.".to_string());
+        }
+        let spec: &H2OClusterSpec = &self.spec;
+        if spec.nodes < 1 || spec.cpus < 1 {
+            return Err("The count of nodes and CPUs must be greater than zero.".to_string());
+        }
+        if spec.memory_percentage > 100 {
+            return Err("The memory percentage must be within range <0,100>.".to_string());
+        }
+        if parse_memory(&spec.memory).is_none() {
+            return Err(format!("Invalid memory '{}'. Use a format accepted by Kubernetes, e.g. 4Gi.", spec.memory));
+        }
+        return Ok(());
+    }
+
+    /// Specification of a deployment of the H2O cluster into the given namespace. The cluster spec file is recorded in it,
+    /// so the deployment descriptor points back to the file the deployment is managed by.
+    pub fn deployment_specification(&self, namespace: String, kubeconfig_path: Option<PathBuf>, spec_file: PathBuf) -> DeploymentSpecification {
+        let mut deployment_specification: DeploymentSpecification = DeploymentSpecification::new(self.metadata.name.clone(), namespace, self.spec.memory_percentage,
+                                                                                                 self.spec.memory.clone(), self.spec.cpus, self.spec.nodes,
+                                                                                                 kubeconfig_path);
+        deployment_specification.spec_file = Some(spec_file);
+        return deployment_specification;
+    }
+
+    pub fn deploy_options(&self, retry: RetryPolicy) -> DeployOptions {
+        return DeployOptions {
+            protect: self.spec.protect,
+            create_namespace: self.spec.create_namespace,
+            retry,
+            dry_run: None,
+        };
+    }
+}
+
+/// A single change required to bring an H2O deployment to the state specified by a cluster spec.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// The H2O cluster does not exist and is to be deployed.
+    Create,
+    /// A value of the H2O cluster differs. H2O nodes form a cloud of a fixed size and configuration once started,
+    /// the H2O cluster therefore has to be recreated, losing all the data held in memory.
+    Modify { field: &'static str, current: String, desired: String },
+    /// An ingress is to be created.
+    CreateIngress,
+    /// The ingresses are to be removed.
+    RemoveIngress,
+    /// The deletion protection is to be set (true) or removed (false).
+    Protect(bool),
+}
+
+impl Change {
+    /// Returns true if the change can only be made by recreating the H2O cluster.
+    pub fn requires_recreation(&self) -> bool {
+        return matches!(self, Change::Modify { .. });
+    }
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            Change::Create => { write!(f, "create the H2O cluster") }
+            Change::Modify { field, current, desired } => { write!(f, "change {} from {} to {} (recreates the H2O cluster)", field, current, desired) }
+            Change::CreateIngress => { write!(f, "create an ingress") }
+            Change::RemoveIngress => { write!(f, "remove the ingress") }
+            Change::Protect(true) => { write!(f, "protect against deletion") }
+            Change::Protect(false) => { write!(f, "remove the deletion protection") }
+        };
+    }
+}
+
+/// Lists the changes required to bring the `current` deployment, as found in the Kubernetes cluster, to the state specified
+/// by the cluster spec. None as the `current` deployment means there is no such deployment. An empty list means the deployment
+/// matches the cluster spec.
+pub fn plan(cluster_spec: &ClusterSpec, current: Option<&Deployment>) -> Vec<Change> {
+    let spec: &H2OClusterSpec = &cluster_spec.spec;
+    let current: &Deployment = match current {
+        Some(current) => { current }
+        None => {
+            let mut changes: Vec<Change> = vec!(Change::Create);
+            if spec.ingress.enabled {
+                changes.push(Change::CreateIngress);
+            }
+            return changes;
+        }
+    };
+
+    let mut changes: Vec<Change> = vec!();
+    let specification: &DeploymentSpecification = &current.specification;
+    if specification.num_h2o_nodes != spec.nodes {
+        changes.push(Change::Modify { field: "nodes", current: specification.num_h2o_nodes.to_string(), desired: spec.nodes.to_string() });
+    }
+    // Equal quantities might be written differently, e.g. 1Gi and 1024Mi.
+    if specification.memory != spec.memory && parse_memory(&specification.memory) != parse_memory(&spec.memory) {
+        changes.push(Change::Modify { field: "memory", current: specification.memory.clone(), desired: spec.memory.clone() });
+    }
+    if specification.num_cpu != spec.cpus {
+        changes.push(Change::Modify { field: "cpus", current: specification.num_cpu.to_string(), desired: spec.cpus.to_string() });
+    }
+    if specification.memory_percentage != spec.memory_percentage {
+        changes.push(Change::Modify {
+            field: "memoryPercentage",
+            current: specification.memory_percentage.to_string(),
+            desired: spec.memory_percentage.to_string(),
+        });
+    }
+
+    let exposed: bool = !current.ingresses.is_empty();
+    if spec.ingress.enabled && !exposed {
+        changes.push(Change::CreateIngress);
+    } else if !spec.ingress.enabled && exposed {
+        changes.push(Change::RemoveIngress);
+    }
+    let protected: bool = current.stateful_sets.iter().any(protection::is_protected);
+    if spec.protect != protected {
+        changes.push(Change::Protect(spec.protect));
+    }
+    return changes;
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::networking::v1beta1::Ingress;
+
+    use crate::k8s::{Deployment, DeploymentSpecification};
+
+    use super::{Change, ClusterSpec};
+
+    const CLUSTER_SPEC: &str = r#"
+apiVersion: h2ok/v1
+kind: H2OCluster
+metadata:
+  name: h2o-test
+  namespace: h2o
+spec:
+  nodes: 3
+  memory: 4Gi
+  cpus: 2
+  ingress:
+    enabled: true
+"#;
+
+    #[test]
+    fn test_parse() {
+        let cluster_spec: ClusterSpec = ClusterSpec::parse(CLUSTER_SPEC).unwrap();
+        assert_eq!("h2o-test", cluster_spec.metadata.name);
+        assert_eq!(50, cluster_spec.spec.memory_percentage);
+        assert!(cluster_spec.spec.ingress.enabled);
+        assert!(!cluster_spec.spec.protect);
+
+        assert!(ClusterSpec::parse(&CLUSTER_SPEC.replace("h2ok/v1", "h2ok/v2")).unwrap_err().contains("apiVersion"));
+        assert!(ClusterSpec::parse(&CLUSTER_SPEC.replace("cpus: 2", "cpus: 0")).is_err());
+        assert!(ClusterSpec::parse(&CLUSTER_SPEC.replace("4Gi", "4 gigs")).is_err());
+        // Typos are reported instead of being silently ignored.
+        assert!(ClusterSpec::parse(&CLUSTER_SPEC.replace("cpus:", "cpu:")).is_err());
+    }
+
+    #[test]
+    fn test_plan() {
+        let cluster_spec: ClusterSpec = ClusterSpec::parse(CLUSTER_SPEC).unwrap();
+        assert_eq!(vec!(Change::Create, Change::CreateIngress), super::plan(&cluster_spec, None));
+
+        let mut deployment: Deployment = Deployment::new(DeploymentSpecification::new("h2o-test".to_string(), "h2o".to_string(), 50,
+                                                                                      "4096Mi".to_string(), 2, 3, None));
+        deployment.ingresses.push(Ingress::default());
+        assert!(super::plan(&cluster_spec, Some(&deployment)).is_empty());
+
+        deployment.specification.num_h2o_nodes = 2;
+        deployment.ingresses.clear();
+        let changes: Vec<Change> = super::plan(&cluster_spec, Some(&deployment));
+        assert_eq!(vec!(Change::Modify { field: "nodes", current: "2".to_string(), desired: "3".to_string() }, Change::CreateIngress), changes);
+        assert!(changes[0].requires_recreation());
+        assert!(!changes[1].requires_recreation());
+    }
+}
@@ -0,0 +1,193 @@
+use std::time::Duration;
+
+use futures::{StreamExt, TryStreamExt};
+use k8s_openapi::api::core::v1::Pod;
+use kube::{Api, Client, Error};
+use kube::api::{ListParams, Meta, ObjectList, WatchEvent};
+use serde::{Deserialize, Serialize};
+use tokio::runtime::Runtime;
+
+use crate::k8s::{Deployment, pod};
+
+/// Port H2O REST API listens on inside the H2O pods.
+const H2O_API_PORT: u16 = 54321;
+
+/// Server-side timeout of a single watch request in seconds used when watching the status.
+const WATCH_TIMEOUT_SECONDS: u32 = 290;
+/// Time the H2O leader node is given to report the H2O cloud status.
+const CLOUD_STATUS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Status of an existing H2O deployment, as observed in the Kubernetes cluster.
+#[derive(Serialize, Debug)]
+pub struct DeploymentStatus {
+    /// Name of the deployment.
+    pub name: String,
+    /// Namespace the deployment lives in.
+    pub namespace: String,
+    /// Status of each H2O pod found.
+    pub pods: Vec<PodStatus>,
+    /// Name of the H2O leader pod, if already elected.
+    pub leader: Option<String>,
+    /// Status of the H2O cloud as reported by the leader node, if available.
+    pub cloud: Option<CloudStatus>,
+    /// Reason the H2O cloud status is not available, if any.
+    pub cloud_error: Option<String>,
+}
+
+/// Status of a single H2O pod.
+#[derive(Serialize, Debug)]
+pub struct PodStatus {
+    pub name: String,
+    pub phase: Option<String>,
+    pub restarts: i32,
+    pub node: Option<String>,
+    pub ip: Option<String>,
+    pub ready: bool,
+}
+
+/// Subset of the H2O `/3/Cloud` endpoint response.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CloudStatus {
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub cloud_name: String,
+    #[serde(default)]
+    pub cloud_size: u32,
+    #[serde(default)]
+    pub cloud_healthy: bool,
+    #[serde(default)]
+    pub consensus: bool,
+    #[serde(default)]
+    pub locked: bool,
+    #[serde(default)]
+    pub nodes: Vec<CloudNodeStatus>,
+}
+
+/// Status of a single H2O node, as reported by the H2O `/3/Cloud` endpoint.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CloudNodeStatus {
+    #[serde(default)]
+    pub ip_port: String,
+    #[serde(default)]
+    pub healthy: bool,
+    /// Free memory in bytes.
+    #[serde(default)]
+    pub free_mem: i64,
+    /// Maximum memory available to the H2O node in bytes.
+    #[serde(default)]
+    pub max_mem: i64,
+}
+
+/// Gathers status of the H2O pods of given `deployment` and H2O cloud status from the leader node, if there is one.
+pub fn deployment_status(client: &Client, deployment: &Deployment) -> Result<DeploymentStatus, Error> {
+    let mut tokio_runtime: Runtime = tokio::runtime::Runtime::new().unwrap();
+    return tokio_runtime.block_on(gather_status(client, deployment)).map(|(status, _)| status);
+}
+
+/// Repeatedly gathers status of the given `deployment` and passes it to `on_status`. The status is re-gathered
+/// each time an H2O pod changes and at least every `refresh_interval`. Returns only on error.
+pub fn watch_status<F>(client: &Client, deployment: &Deployment, refresh_interval: Duration, mut on_status: F) -> Result<(), Error>
+    where F: FnMut(&DeploymentStatus) {
+    let mut tokio_runtime: Runtime = tokio::runtime::Runtime::new().unwrap();
+    let api: Api<Pod> = Api::namespaced(client.clone(), &deployment.specification.namespace);
+    let list_params: ListParams = ListParams::default()
+        .labels(&pod::selector(deployment))
+        .timeout(WATCH_TIMEOUT_SECONDS);
+
+    return tokio_runtime.block_on(async {
+        loop {
+            // Watching from the version listed, there are no synthetic events for the pods already known.
+            let (status, resource_version): (DeploymentStatus, String) = gather_status(client, deployment).await?;
+            on_status(&status);
+            let mut event_stream = api.watch(&list_params, &resource_version).await?.boxed();
+            loop {
+                match tokio::time::timeout(refresh_interval, event_stream.try_next()).await {
+                    // The version listed is too old to watch from, the pods are listed again.
+                    Ok(Ok(Some(WatchEvent::Error(response)))) if response.code == 410 => { break; }
+                    Ok(Ok(Some(_))) | Err(_) => {
+                        on_status(&gather_status(client, deployment).await?.0);
+                    }
+                    Ok(Ok(None)) => { break; }
+                    Ok(Err(e)) => { return Err(e); }
+                }
+            }
+        }
+    });
+}
+
+/// Gathers status of the given `deployment`. Returns the status along with the resource version of the pods listed.
+async fn gather_status(client: &Client, deployment: &Deployment) -> Result<(DeploymentStatus, String), Error> {
+    let api: Api<Pod> = Api::namespaced(client.clone(), &deployment.specification.namespace);
+    let list_params: ListParams = ListParams::default()
+        .labels(&pod::selector(deployment));
+    let pod_list: ObjectList<Pod> = api.list(&list_params).await?;
+    let resource_version: String = pod_list.metadata.resource_version.unwrap_or_else(|| "0".to_string());
+    let mut pods: Vec<Pod> = pod_list.items;
+    pods.sort_by_key(|pod| pod.name());
+
+    let leader: Option<String> = pods.iter()
+        .find(|pod| pod::is_ready(pod))
+        .map(|pod| pod.name());
+
+    let (cloud, cloud_error): (Option<CloudStatus>, Option<String>) = match &leader {
+        None => { (None, Some("No H2O leader node elected yet.".to_string())) }
+        Some(leader_pod) => {
+            // A hung H2O node must not block the status of the pods.
+            match tokio::time::timeout(CLOUD_STATUS_TIMEOUT, cloud_status(client, &deployment.specification.namespace, leader_pod)).await {
+                Ok(Ok(cloud)) => { (Some(cloud), None) }
+                Ok(Err(e)) => { (None, Some(format!("Unable to query H2O cloud status. Reason: {}", e))) }
+                Err(_) => {
+                    (None, Some(format!("Unable to query H2O cloud status. Reason: No response within {} seconds.", CLOUD_STATUS_TIMEOUT.as_secs())))
+                }
+            }
+        }
+    };
+
+    let pods: Vec<PodStatus> = pods.iter()
+        .map(|pod| PodStatus {
+            name: pod.name(),
+            phase: pod::phase(pod).map(String::from),
+            restarts: pod::restarts(pod),
+            node: pod::node(pod),
+            ip: pod::ip(pod),
+            ready: pod::is_ready(pod),
+        })
+        .collect();
+
+    let status: DeploymentStatus = DeploymentStatus {
+        name: deployment.specification.name.clone(),
+        namespace: deployment.specification.namespace.clone(),
+        pods,
+        leader,
+        cloud,
+        cloud_error,
+    };
+    return Ok((status, resource_version));
+}
+
+/// Queries the H2O `/3/Cloud` endpoint of the given pod through the Kubernetes API server pod proxy.
+async fn cloud_status(client: &Client, namespace: &str, pod_name: &str) -> Result<CloudStatus, Error> {
+    let uri: String = format!("/api/v1/namespaces/{}/pods/{}:{}/proxy/3/Cloud", namespace, pod_name, H2O_API_PORT);
+    let request: http::Request<Vec<u8>> = http::Request::get(uri).body(vec!())?;
+    return client.request::<CloudStatus>(request).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CloudStatus;
+
+    #[test]
+    fn test_parse_cloud_status() {
+        let response: &str = r#"{"__meta": {"schema_version": 3}, "version": "3.30.1.3", "cloud_name": "h2o-cluster", "cloud_size": 2,
+        "cloud_healthy": true, "consensus": true, "locked": false, "nodes": [
+        {"h2o": "/10.42.0.10:54321", "ip_port": "10.42.0.10:54321", "healthy": true, "free_mem": 2147483648, "max_mem": 4294967296},
+        {"h2o": "/10.42.0.11:54321", "ip_port": "10.42.0.11:54321", "healthy": false, "free_mem": 1073741824, "max_mem": 4294967296}]}"#;
+        let cloud: CloudStatus = serde_json::from_str(response).unwrap();
+        assert_eq!("3.30.1.3", cloud.version);
+        assert_eq!(2, cloud.cloud_size);
+        assert_eq!(2, cloud.nodes.len());
+        assert_eq!(2147483648, cloud.nodes[0].free_mem);
+        assert!(!cloud.nodes[1].healthy);
+    }
+}
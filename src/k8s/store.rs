@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+
+use k8s_openapi::api::apps::v1::StatefulSet;
+use k8s_openapi::api::core::v1::ConfigMap;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference;
+use kube::{Client, Error};
+use kube::api::{Meta, PostParams};
+use tokio::runtime::Runtime;
+
+use crate::k8s::Deployment;
+use crate::k8s::retry::{AttemptError, RetryPolicy, ThrottledApi, with_retry};
+use crate::k8s::templates;
+
+/// Key of the deployment descriptor in the data of the ConfigMap.
+pub const DESCRIPTOR_KEY: &str = "descriptor.json";
+
+/// Name of the ConfigMap holding the deployment descriptor of the deployment with the given name.
+pub fn config_map_name(deployment_name: &str) -> String {
+    return format!("{}-descriptor", deployment_name);
+}
+
+/// Builds the ConfigMap holding the serialized deployment descriptor of the given deployment. The ConfigMap is owned
+/// by the stateful sets of the deployment, so it is garbage-collected along with them. Stateful sets without a uid,
+/// e.g. of a deployment not yet created, are not referenced.
+pub fn descriptor_config_map(deployment: &Deployment, descriptor: String) -> ConfigMap {
+    let mut config_map: ConfigMap = templates::h2o_descriptor_config_map(&deployment.specification.name, &deployment.specification.namespace);
+    let mut data: BTreeMap<String, String> = BTreeMap::new();
+    data.insert(DESCRIPTOR_KEY.to_string(), descriptor);
+    config_map.data = Some(data);
+    let owner_references: Vec<OwnerReference> = deployment.stateful_sets.iter()
+        .filter_map(owner_reference)
+        .collect();
+    if !owner_references.is_empty() {
+        config_map.metadata.owner_references = Some(owner_references);
+    }
+    return config_map;
+}
+
+/// Saves the serialized deployment descriptor of the given deployment into a ConfigMap in the namespace of the deployment,
+/// replacing the descriptor saved previously, if any. Returns the name of the ConfigMap.
+pub fn save(client: &Client, deployment: &Deployment, descriptor: String, retry_policy: &RetryPolicy) -> Result<String, Error> {
+    let mut tokio_runtime: Runtime = tokio::runtime::Runtime::new().unwrap();
+    let api: ThrottledApi<ConfigMap> = ThrottledApi::namespaced(client.clone(), &deployment.specification.namespace);
+    let config_map: ConfigMap = descriptor_config_map(deployment, descriptor);
+    let name: String = config_map.name();
+    let description: String = format!("save ConfigMap '{}'", name);
+
+    tokio_runtime.block_on(with_retry(retry_policy, &description, || create_or_replace(&api, config_map.clone())))?;
+    return Ok(name);
+}
+
+/// Loads the serialized deployment descriptor of the deployment with the given name from its ConfigMap. Returns None
+/// if there is no such ConfigMap or it holds no deployment descriptor.
+pub fn load(client: &Client, deployment_name: &str, namespace: &str, retry_policy: &RetryPolicy) -> Result<Option<String>, Error> {
+    let mut tokio_runtime: Runtime = tokio::runtime::Runtime::new().unwrap();
+    let api: ThrottledApi<ConfigMap> = ThrottledApi::namespaced(client.clone(), namespace);
+    let name: String = config_map_name(deployment_name);
+    let description: String = format!("get ConfigMap '{}'", name);
+
+    return match tokio_runtime.block_on(with_retry(retry_policy, &description, || api.get(&name))) {
+        Ok(config_map) => {
+            Ok(config_map.data.and_then(|mut data| data.remove(DESCRIPTOR_KEY)))
+        }
+        Err(Error::Api(response)) if response.code == 404 => { Ok(None) }
+        Err(e) => { Err(e) }
+    };
+}
+
+/// Creates the ConfigMap, or replaces the existing one of the same name. The creation metadata of the existing ConfigMap are kept.
+async fn create_or_replace(api: &ThrottledApi<ConfigMap>, mut config_map: ConfigMap) -> Result<ConfigMap, AttemptError> {
+    let name: String = config_map.name();
+    return match api.get(&name).await {
+        Ok(existing) => {
+            config_map.metadata.resource_version = existing.metadata.resource_version;
+            config_map.metadata.annotations = existing.metadata.annotations;
+            api.replace(&name, &PostParams::default(), &config_map).await
+        }
+        Err(AttemptError::Api(Error::Api(response))) if response.code == 404 => {
+            api.create(&PostParams::default(), &config_map).await
+        }
+        Err(e) => { Err(e) }
+    };
+}
+
+fn owner_reference(stateful_set: &StatefulSet) -> Option<OwnerReference> {
+    let uid: String = stateful_set.metadata.uid.clone()?;
+    return Some(OwnerReference {
+        api_version: "apps/v1".to_string(),
+        kind: "StatefulSet".to_string(),
+        name: stateful_set.name(),
+        uid,
+        block_owner_deletion: None,
+        controller: None,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::apps::v1::StatefulSet;
+    use k8s_openapi::api::core::v1::ConfigMap;
+
+    use crate::k8s::{Deployment, DeploymentSpecification, LABEL_INSTANCE, LABEL_MANAGED_BY, MANAGED_BY};
+    use crate::k8s::templates;
+
+    use super::DESCRIPTOR_KEY;
+
+    #[test]
+    fn test_descriptor_config_map() {
+        let mut deployment: Deployment = Deployment::new(DeploymentSpecification::new("h2o-test".to_string(), "h2o".to_string(), 50,
+                                                                                      "1Gi".to_string(), 1, 2, None));
+        let mut stateful_set: StatefulSet = templates::h2o_stateful_set("h2o-test", "h2o", "h2oai/h2o-open-source-k8s", "latest", 2, 50, "1Gi", 1);
+        deployment.stateful_sets.push(stateful_set.clone());
+        let config_map: ConfigMap = super::descriptor_config_map(&deployment, "{}".to_string());
+        // The stateful set has not been created yet, there is no uid to refer to.
+        assert!(config_map.metadata.owner_references.is_none());
+
+        stateful_set.metadata.uid = Some("5b1e6f6a-0c1d-4e8f-9a3b-2d7c8e9f0a1b".to_string());
+        deployment.stateful_sets = vec!(stateful_set);
+        let config_map: ConfigMap = super::descriptor_config_map(&deployment, "{}".to_string());
+        assert_eq!(Some("h2o-test-descriptor".to_string()), config_map.metadata.name);
+        assert_eq!(Some("h2o".to_string()), config_map.metadata.namespace);
+        let labels = config_map.metadata.labels.as_ref().unwrap();
+        assert_eq!(MANAGED_BY, labels.get(LABEL_MANAGED_BY).unwrap());
+        assert_eq!("h2o-test", labels.get(LABEL_INSTANCE).unwrap());
+        assert_eq!("true", labels.get("h2ok/descriptor").unwrap());
+        assert_eq!("{}", config_map.data.as_ref().unwrap().get(DESCRIPTOR_KEY).unwrap());
+        let owner_references = config_map.metadata.owner_references.unwrap();
+        assert_eq!(1, owner_references.len());
+        assert_eq!("StatefulSet", owner_references[0].kind);
+        assert_eq!("h2o-test-stateful-set", owner_references[0].name);
+        assert_eq!("5b1e6f6a-0c1d-4e8f-9a3b-2d7c8e9f0a1b", owner_references[0].uid);
+    }
+}
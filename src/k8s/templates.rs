@@ -1,17 +1,25 @@
 /// Contains YAML templates for Kubernetes deployment used by H2O cluster.
 /// The templates have corresponding methods to fill the template and return a new instance of
 /// respective Kubernetes component.
+use chrono::{SecondsFormat, Utc};
 use k8s_openapi::api::apps::v1::StatefulSet;
-use k8s_openapi::api::core::v1::Service;
+use k8s_openapi::api::coordination::v1::Lease;
+use k8s_openapi::api::core::v1::{ConfigMap, Namespace, Service};
 use serde_yaml;
 use k8s_openapi::api::networking::v1beta1::Ingress;
 
-const STATEFUL_SET_TEMPLATE: &str = r#"
+pub const STATEFUL_SET_TEMPLATE: &str = r#"
 apiVersion: apps/v1
 kind: StatefulSet
 metadata:
   name: <name>-stateful-set
   namespace: <namespace>
+  labels:
+    app.kubernetes.io/managed-by: h2ok
+    app.kubernetes.io/instance: '<name>'
+  annotations:
+    h2ok/creator: '<creator>'
+    h2ok/created-at: '<created-at>'
 spec:
   serviceName: h2o-service
   podManagementPolicy: "Parallel"
@@ -23,6 +31,8 @@ spec:
     metadata:
       labels:
         app: <name>
+        app.kubernetes.io/managed-by: h2ok
+        app.kubernetes.io/instance: '<name>'
     spec:
       containers:
         - name: <name>
@@ -58,7 +68,7 @@ spec:
 
 pub fn h2o_stateful_set(name: &str, namespace: &str, docker_img_name: &str, docker_img_tag: &str, nodes: u32,
                         memory_percentage: u8, memory: &str, num_cpu: u32) -> StatefulSet {
-    let stateful_set_definition = STATEFUL_SET_TEMPLATE.replace("<name>", name)
+    let stateful_set_definition = fill_creation_metadata(STATEFUL_SET_TEMPLATE).replace("<name>", name)
         .replace("<namespace>", namespace)
         .replace("<docker-img-name>", docker_img_name)
         .replace("<docker-img-tag>", docker_img_tag)
@@ -71,12 +81,18 @@ pub fn h2o_stateful_set(name: &str, namespace: &str, docker_img_name: &str, dock
     return stateful_set;
 }
 
-const SERVICE_TEMPLATE: &str = r#"
+pub const SERVICE_TEMPLATE: &str = r#"
 apiVersion: v1
 kind: Service
 metadata:
   name: <name>-service
   namespace: <namespace>
+  labels:
+    app.kubernetes.io/managed-by: h2ok
+    app.kubernetes.io/instance: '<name>'
+  annotations:
+    h2ok/creator: '<creator>'
+    h2ok/created-at: '<created-at>'
 spec:
   type: ClusterIP
   clusterIP: None
@@ -89,21 +105,26 @@ spec:
 "#;
 
 pub fn h2o_service(name: &str, namespace: &str) -> Service {
-    let service_definition = SERVICE_TEMPLATE.replace("<name>", name)
+    let service_definition = fill_creation_metadata(SERVICE_TEMPLATE).replace("<name>", name)
         .replace("<namespace>", namespace);
 
     let service: Service = serde_yaml::from_str(&service_definition).unwrap();
     return service;
 }
 
-const INGRESS_TEMPLATE: &str = r#"
+pub const INGRESS_TEMPLATE: &str = r#"
 apiVersion: networking.k8s.io/v1beta1
 kind: Ingress
 metadata:
   name: <name>-ingress
+  labels:
+    app.kubernetes.io/managed-by: h2ok
+    app.kubernetes.io/instance: '<name>'
   annotations:
     nginx.ingress.kubernetes.io/rewrite-target: /$2
     traefik.frontend.rule.type: PathPrefixStrip
+    h2ok/creator: '<creator>'
+    h2ok/created-at: '<created-at>'
 spec:
   rules:
   - http:
@@ -116,9 +137,123 @@ spec:
 "#;
 
 pub fn h2o_ingress(name: &str, namespace: &str) -> Ingress {
-    let ingress_definition = INGRESS_TEMPLATE.replace("<name>", name)
+    let ingress_definition = fill_creation_metadata(INGRESS_TEMPLATE).replace("<name>", name)
         .replace("<namespace>", namespace);
 
     let ingress: Ingress = serde_yaml::from_str(&ingress_definition).unwrap();
     return ingress;
 }
+
+const NAMESPACE_TEMPLATE: &str = r#"
+apiVersion: v1
+kind: Namespace
+metadata:
+  name: <namespace>
+  labels:
+    app.kubernetes.io/managed-by: h2ok
+    app.kubernetes.io/instance: '<name>'
+  annotations:
+    h2ok/creator: '<creator>'
+    h2ok/created-at: '<created-at>'
+"#;
+
+pub fn h2o_namespace(name: &str, namespace: &str) -> Namespace {
+    let namespace_definition = fill_creation_metadata(NAMESPACE_TEMPLATE).replace("<name>", name)
+        .replace("<namespace>", namespace);
+
+    let namespace: Namespace = serde_yaml::from_str(&namespace_definition).unwrap();
+    return namespace;
+}
+
+const DESCRIPTOR_CONFIG_MAP_TEMPLATE: &str = r#"
+apiVersion: v1
+kind: ConfigMap
+metadata:
+  name: <name>-descriptor
+  namespace: <namespace>
+  labels:
+    app.kubernetes.io/managed-by: h2ok
+    app.kubernetes.io/instance: '<name>'
+    h2ok/descriptor: 'true'
+  annotations:
+    h2ok/creator: '<creator>'
+    h2ok/created-at: '<created-at>'
+"#;
+
+pub fn h2o_descriptor_config_map(name: &str, namespace: &str) -> ConfigMap {
+    let config_map_definition = fill_creation_metadata(DESCRIPTOR_CONFIG_MAP_TEMPLATE).replace("<name>", name)
+        .replace("<namespace>", namespace);
+
+    let config_map: ConfigMap = serde_yaml::from_str(&config_map_definition).unwrap();
+    return config_map;
+}
+
+const LEASE_TEMPLATE: &str = r#"
+apiVersion: coordination.k8s.io/v1
+kind: Lease
+metadata:
+  name: <name>-lock
+  namespace: <namespace>
+  labels:
+    app.kubernetes.io/managed-by: h2ok
+    app.kubernetes.io/instance: '<name>'
+  annotations:
+    h2ok/creator: '<creator>'
+    h2ok/created-at: '<created-at>'
+"#;
+
+pub fn h2o_lease(name: &str, namespace: &str) -> Lease {
+    let lease_definition = fill_creation_metadata(LEASE_TEMPLATE).replace("<name>", name)
+        .replace("<namespace>", namespace);
+
+    let lease: Lease = serde_yaml::from_str(&lease_definition).unwrap();
+    return lease;
+}
+
+/// Fills the `<creator>` and `<created-at>` placeholders of the given template with name of the user running h2ok
+/// and current UTC time respectively.
+pub fn fill_creation_metadata(template: &str) -> String {
+    let creator: String = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string());
+    return template.replace("<creator>", &creator.replace("'", "''"))
+        .replace("<created-at>", &Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true));
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::apps::v1::StatefulSet;
+    use k8s_openapi::api::core::v1::{Namespace, Service};
+    use k8s_openapi::api::networking::v1beta1::Ingress;
+    use kube::api::Meta;
+
+    use crate::k8s::{ANNOTATION_CREATED_AT, ANNOTATION_CREATOR, LABEL_INSTANCE, LABEL_MANAGED_BY, MANAGED_BY};
+
+    fn assert_h2ok_metadata<K: Meta>(entity: &K, name: &str) {
+        let labels = entity.meta().labels.as_ref().unwrap();
+        assert_eq!(MANAGED_BY, labels.get(LABEL_MANAGED_BY).unwrap());
+        assert_eq!(name, labels.get(LABEL_INSTANCE).unwrap());
+        let annotations = entity.meta().annotations.as_ref().unwrap();
+        assert!(annotations.contains_key(ANNOTATION_CREATOR));
+        assert!(annotations.contains_key(ANNOTATION_CREATED_AT));
+    }
+
+    #[test]
+    fn test_h2ok_metadata() {
+        let stateful_set: StatefulSet = super::h2o_stateful_set("h2o-test", "default", "h2oai/h2o-open-source-k8s", "latest",
+                                                                3, 50, "1Gi", 1);
+        assert_h2ok_metadata(&stateful_set, "h2o-test");
+        let pod_labels = stateful_set.spec.unwrap().template.metadata.unwrap().labels.unwrap();
+        assert_eq!("h2o-test", pod_labels.get(LABEL_INSTANCE).unwrap());
+
+        let service: Service = super::h2o_service("h2o-test", "default");
+        assert_h2ok_metadata(&service, "h2o-test");
+
+        let ingress: Ingress = super::h2o_ingress("h2o-test", "default");
+        assert_h2ok_metadata(&ingress, "h2o-test");
+
+        let namespace: Namespace = super::h2o_namespace("h2o-test", "h2o-team");
+        assert_h2ok_metadata(&namespace, "h2o-test");
+        assert_eq!("h2o-team", namespace.metadata.name.unwrap());
+    }
+}
@@ -0,0 +1,325 @@
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Display, Formatter};
+use std::time::Duration;
+
+use futures::{StreamExt, TryStreamExt};
+use k8s_openapi::api::apps::v1::StatefulSet;
+use k8s_openapi::api::core::v1::{Pod, Service};
+use k8s_openapi::api::networking::v1beta1::Ingress;
+use kube::{Api, Client, Error};
+use kube::api::{ListParams, Meta, WatchEvent};
+use serde::de::DeserializeOwned;
+use tokio::runtime::Runtime;
+
+use crate::k8s::{Deployment, pod};
+
+/// Server-side timeout of a single watch request in seconds. Once a watch ends, it is re-established.
+const WATCH_TIMEOUT_SECONDS: u32 = 290;
+
+/// Container waiting reasons considered terminal - a pod in such a state is not going to recover on its own.
+const TERMINAL_WAITING_REASONS: [&str; 5] = ["CrashLoopBackOff", "ErrImagePull", "ImagePullBackOff", "InvalidImageName", "CreateContainerConfigError"];
+
+/// Condition of an existing H2O deployment to wait for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WaitCondition {
+    /// All H2O nodes are running and the H2O cluster has a leader node elected.
+    Ready,
+    /// The leader node of the H2O cluster is elected and ready to accept requests.
+    Leader,
+    /// All the Kubernetes entities recorded in the deployment and all the H2O pods are gone.
+    Deleted,
+}
+
+impl WaitCondition {
+    /// Parses a condition from its user-facing name. Returns None for unknown names.
+    pub fn from_name(name: &str) -> Option<Self> {
+        return match name {
+            "ready" => Some(WaitCondition::Ready),
+            "leader" => Some(WaitCondition::Leader),
+            "deleted" => Some(WaitCondition::Deleted),
+            _ => None
+        };
+    }
+
+    /// User-facing name of the condition, the inverse of `from_name`.
+    pub fn name(&self) -> &'static str {
+        return match self {
+            WaitCondition::Ready => "ready",
+            WaitCondition::Leader => "leader",
+            WaitCondition::Deleted => "deleted",
+        };
+    }
+}
+
+/// Reason the awaited condition has not been met.
+#[derive(Debug)]
+pub enum WaitError {
+    /// The condition has not been met in the given time.
+    Timeout,
+    /// The condition can never be met, e.g. a pod crashed or the stateful set has been removed.
+    Failed(String),
+    /// Communication with the Kubernetes API failed.
+    Api(Error),
+}
+
+impl Display for WaitError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            WaitError::Timeout => { write!(f, "Timed out") }
+            WaitError::Failed(reason) => { write!(f, "{}", reason) }
+            WaitError::Api(error) => { write!(f, "Kubernetes API error: {}", error) }
+        };
+    }
+}
+
+impl From<Error> for WaitError {
+    fn from(error: Error) -> Self {
+        WaitError::Api(error)
+    }
+}
+
+/// State of H2O pods evaluated against a `WaitCondition`.
+#[derive(Debug, PartialEq)]
+enum PodsState {
+    Satisfied,
+    Pending,
+    Failed(String),
+}
+
+/// Blocks until the given `condition` is met by the `deployment` or until `timeout` elapses.
+/// Kubernetes watch streams are used to observe the entities recorded in the deployment.
+pub fn wait_for(client: &Client, deployment: &Deployment, condition: WaitCondition, timeout: Duration) -> Result<(), WaitError> {
+    let mut tokio_runtime: Runtime = tokio::runtime::Runtime::new().unwrap();
+    let wait = async {
+        match condition {
+            WaitCondition::Ready => {
+                ensure_stateful_sets_exist(client, deployment).await?;
+                let expected_nodes: usize = deployment.specification.num_h2o_nodes as usize;
+                watch_pods(client, deployment, |pods| evaluate_ready(pods, expected_nodes)).await
+            }
+            WaitCondition::Leader => {
+                ensure_stateful_sets_exist(client, deployment).await?;
+                watch_pods(client, deployment, evaluate_leader).await
+            }
+            WaitCondition::Deleted => {
+                wait_for_deletion(client, deployment).await
+            }
+        }
+    };
+
+    return match tokio_runtime.block_on(tokio::time::timeout(timeout, wait)) {
+        Ok(result) => { result }
+        Err(_) => { Err(WaitError::Timeout) }
+    };
+}
+
+/// Fails if any of the stateful sets recorded in the deployment does not exist anymore, as the pods would never appear.
+async fn ensure_stateful_sets_exist(client: &Client, deployment: &Deployment) -> Result<(), WaitError> {
+    let api: Api<StatefulSet> = Api::namespaced(client.clone(), &deployment.specification.namespace);
+    for stateful_set in deployment.stateful_sets.iter() {
+        match api.get(&stateful_set.name()).await {
+            Ok(_) => {}
+            Err(Error::Api(response)) if response.code == 404 => {
+                return Err(WaitError::Failed(format!("Stateful set '{}' does not exist.", stateful_set.name())));
+            }
+            Err(e) => { return Err(WaitError::Api(e)); }
+        }
+    }
+    return Ok(());
+}
+
+/// Watches pods of the deployment, keeping an up-to-date view of them, until `evaluate` reports the state
+/// as satisfied or failed. The watch is re-established whenever the server closes it.
+async fn watch_pods<F>(client: &Client, deployment: &Deployment, evaluate: F) -> Result<(), WaitError>
+    where F: Fn(&BTreeMap<String, Pod>) -> PodsState {
+    let api: Api<Pod> = Api::namespaced(client.clone(), &deployment.specification.namespace);
+    let list_params: ListParams = ListParams::default()
+        .labels(&pod::selector(deployment));
+
+    loop {
+        let pod_list = api.list(&list_params).await?;
+        let mut pods: BTreeMap<String, Pod> = pod_list.items.into_iter()
+            .map(|pod| (pod.name(), pod))
+            .collect();
+        match evaluate(&pods) {
+            PodsState::Satisfied => { return Ok(()); }
+            PodsState::Failed(reason) => { return Err(WaitError::Failed(reason)); }
+            PodsState::Pending => {}
+        }
+
+        let resource_version: String = pod_list.metadata.resource_version.unwrap_or_else(|| "0".to_string());
+        let watch_params: ListParams = list_params.clone().timeout(WATCH_TIMEOUT_SECONDS);
+        let mut event_stream = api.watch(&watch_params, &resource_version).await?.boxed();
+
+        while let Some(event) = event_stream.try_next().await? {
+            match event {
+                WatchEvent::Added(pod) | WatchEvent::Modified(pod) => {
+                    pods.insert(pod.name(), pod);
+                }
+                WatchEvent::Deleted(pod) => {
+                    pods.remove(&pod.name());
+                }
+                WatchEvent::Bookmark(_) => {}
+                WatchEvent::Error(response) => {
+                    if response.code == 410 {
+                        // Resource version too old - start over with a fresh list.
+                        break;
+                    }
+                    return Err(WaitError::Api(Error::Api(response)));
+                }
+            }
+            match evaluate(&pods) {
+                PodsState::Satisfied => { return Ok(()); }
+                PodsState::Failed(reason) => { return Err(WaitError::Failed(reason)); }
+                PodsState::Pending => {}
+            }
+        }
+    }
+}
+
+/// Waits until every entity recorded in the deployment and every H2O pod is deleted.
+async fn wait_for_deletion(client: &Client, deployment: &Deployment) -> Result<(), WaitError> {
+    let namespace: &str = &deployment.specification.namespace;
+
+    let api: Api<Ingress> = Api::namespaced(client.clone(), namespace);
+    for ingress in deployment.ingresses.iter() {
+        wait_until_deleted(&api, &ingress.name()).await?;
+    }
+
+    let api: Api<Service> = Api::namespaced(client.clone(), namespace);
+    for service in deployment.services.iter() {
+        wait_until_deleted(&api, &service.name()).await?;
+    }
+
+    let api: Api<StatefulSet> = Api::namespaced(client.clone(), namespace);
+    for stateful_set in deployment.stateful_sets.iter() {
+        wait_until_deleted(&api, &stateful_set.name()).await?;
+    }
+
+    return watch_pods(client, deployment, |pods| {
+        if pods.is_empty() { PodsState::Satisfied } else { PodsState::Pending }
+    }).await;
+}
+
+/// Watches a single named entity until it is deleted. Returns immediately if the entity does not exist.
+async fn wait_until_deleted<K>(api: &Api<K>, name: &str) -> Result<(), WaitError>
+    where K: Clone + DeserializeOwned + Meta + Debug + Send {
+    loop {
+        let resource_version: String = match api.get(name).await {
+            Ok(entity) => { entity.resource_ver().unwrap_or_else(|| "0".to_string()) }
+            Err(Error::Api(response)) if response.code == 404 => { return Ok(()); }
+            Err(e) => { return Err(WaitError::Api(e)); }
+        };
+
+        let watch_params: ListParams = ListParams::default()
+            .fields(&format!("metadata.name={}", name))
+            .timeout(WATCH_TIMEOUT_SECONDS);
+        let mut event_stream = api.watch(&watch_params, &resource_version).await?.boxed();
+
+        while let Some(event) = event_stream.try_next().await? {
+            match event {
+                WatchEvent::Deleted(_) => { return Ok(()); }
+                WatchEvent::Error(response) if response.code != 410 => {
+                    return Err(WaitError::Api(Error::Api(response)));
+                }
+                WatchEvent::Error(_) => { break; }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Ready means all the expected H2O pods are running and one of them has been elected as the leader.
+fn evaluate_ready(pods: &BTreeMap<String, Pod>, expected_nodes: usize) -> PodsState {
+    if let Some(reason) = any_pod_failure(pods) {
+        return PodsState::Failed(reason);
+    }
+    let running_pods: usize = pods.values()
+        .filter(|pod| pod::phase(pod) == Some("Running"))
+        .count();
+
+    return if running_pods >= expected_nodes && pods.values().any(pod::is_ready) {
+        PodsState::Satisfied
+    } else {
+        PodsState::Pending
+    };
+}
+
+/// Leader means one of the H2O pods passes the readiness probe, which only succeeds on the leader node.
+fn evaluate_leader(pods: &BTreeMap<String, Pod>) -> PodsState {
+    if let Some(reason) = any_pod_failure(pods) {
+        return PodsState::Failed(reason);
+    }
+    return if pods.values().any(pod::is_ready) {
+        PodsState::Satisfied
+    } else {
+        PodsState::Pending
+    };
+}
+
+/// Returns a human-readable reason of the first pod found in a state it can not recover from, if any.
+fn any_pod_failure(pods: &BTreeMap<String, Pod>) -> Option<String> {
+    for (name, pod) in pods.iter() {
+        if pod::phase(pod) == Some("Failed") {
+            return Some(format!("Pod '{}' failed.", name));
+        }
+        let container_statuses = pod.status.as_ref()
+            .and_then(|status| status.container_statuses.as_ref());
+        for container_status in container_statuses.into_iter().flatten() {
+            let waiting_reason: Option<&String> = container_status.state.as_ref()
+                .and_then(|state| state.waiting.as_ref())
+                .and_then(|waiting| waiting.reason.as_ref());
+            if let Some(reason) = waiting_reason {
+                if TERMINAL_WAITING_REASONS.contains(&reason.as_str()) {
+                    return Some(format!("Pod '{}' is unable to start: {}.", name, reason));
+                }
+            }
+        }
+    }
+    return None;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use k8s_openapi::api::core::v1::Pod;
+
+    use super::PodsState;
+
+    fn pod(phase: &str, ready: bool, waiting_reason: Option<&str>) -> Pod {
+        let waiting: String = match waiting_reason {
+            None => { String::new() }
+            Some(reason) => { format!(r#", "containerStatuses": [{{"name": "h2o", "image": "h2o", "imageID": "", "ready": false, "restartCount": 3, "state": {{"waiting": {{"reason": "{}"}}}}}}]"#, reason) }
+        };
+        let json: String = format!(r#"{{"metadata": {{}}, "status": {{"phase": "{}", "conditions": [{{"type": "Ready", "status": "{}"}}]{}}}}}"#,
+                                   phase, if ready { "True" } else { "False" }, waiting);
+        return serde_json::from_str(&json).unwrap();
+    }
+
+    #[test]
+    fn test_evaluate_ready() {
+        let mut pods: BTreeMap<String, Pod> = BTreeMap::new();
+        pods.insert("h2o-0".to_string(), pod("Running", true, None));
+        assert_eq!(PodsState::Pending, super::evaluate_ready(&pods, 2));
+
+        pods.insert("h2o-1".to_string(), pod("Pending", false, None));
+        assert_eq!(PodsState::Pending, super::evaluate_ready(&pods, 2));
+
+        pods.insert("h2o-1".to_string(), pod("Running", false, None));
+        assert_eq!(PodsState::Satisfied, super::evaluate_ready(&pods, 2));
+    }
+
+    #[test]
+    fn test_evaluate_leader() {
+        let mut pods: BTreeMap<String, Pod> = BTreeMap::new();
+        pods.insert("h2o-0".to_string(), pod("Running", false, None));
+        assert_eq!(PodsState::Pending, super::evaluate_leader(&pods));
+
+        pods.insert("h2o-1".to_string(), pod("Running", true, None));
+        assert_eq!(PodsState::Satisfied, super::evaluate_leader(&pods));
+
+        pods.insert("h2o-2".to_string(), pod("Pending", false, Some("ImagePullBackOff")));
+        assert!(matches!(super::evaluate_leader(&pods), PodsState::Failed(_)));
+    }
+}
@@ -0,0 +1,176 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use fs2::FileExt;
+use serde_json::{json, Value};
+
+use crate::k8s::lease;
+use crate::k8s::lease::LockHolder;
+
+/// Suffix of the lock files, placed next to the deployment descriptors they lock.
+const LOCK_SUFFIX: &str = ".lock";
+
+/// Reasons a deployment descriptor could not be locked.
+#[derive(Debug)]
+pub enum LockError {
+    /// The deployment descriptor is locked by another h2ok process.
+    Held(LockHolder),
+    Io(std::io::Error),
+}
+
+impl From<std::io::Error> for LockError {
+    fn from(e: std::io::Error) -> Self {
+        return LockError::Io(e);
+    }
+}
+
+/// Advisory lock of a deployment descriptor file, preventing concurrent updates of the same deployment descriptor by multiple h2ok processes.
+/// The lock is held on `<descriptor>.lock`, which also records the holder. The operating system releases the lock once the process
+/// ends, even if killed. Released once dropped, the lock file is then removed if the deployment descriptor is gone, under a lock
+/// taken again, so a process locking the removed file meanwhile takes the lock on the new lock file instead.
+pub struct DescriptorLock {
+    file: File,
+    path: PathBuf,
+    descriptor: PathBuf,
+}
+
+impl DescriptorLock {
+    /// Locks the given deployment descriptor for the `operation`, e.g. `ingress`. Fails immediately if the deployment descriptor
+    /// is already locked, with the holder recorded in the lock file.
+    pub fn acquire(descriptor: &Path, operation: &str) -> Result<Self, LockError> {
+        let path: PathBuf = lock_path(descriptor);
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file: File = loop {
+            let mut file: File = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+            if let Err(e) = file.try_lock_exclusive() {
+                if e.kind() != fs2::lock_contended_error().kind() {
+                    return Err(LockError::Io(e));
+                }
+                let mut content: String = String::new();
+                // The holder may not have recorded itself yet. On some platforms, the locked file can not be read at all.
+                let _ = file.read_to_string(&mut content);
+                return Err(LockError::Held(parse_holder(&content)));
+            }
+            // The previous holder may have removed the lock file after it has been opened. Locking the removed file
+            // excludes nobody, so the lock is taken again on the lock file now in place.
+            if is_in_place(&file, &path) {
+                break file;
+            }
+        };
+
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(holder_record(&lease::holder_identity(), operation, Utc::now()).as_bytes())?;
+        file.flush()?;
+        return Ok(DescriptorLock { file, path, descriptor: descriptor.to_path_buf() });
+    }
+}
+
+impl Drop for DescriptorLock {
+    fn drop(&mut self) {
+        let _ = self.file.set_len(0);
+        let _ = self.file.unlock();
+        if self.descriptor.exists() {
+            return;
+        }
+        // Removed only while locked again, so no other process holds a lock on the removed file. Others having it opened
+        // already find it removed once they lock it, and lock the new one instead.
+        if let Ok(file) = OpenOptions::new().read(true).write(true).open(&self.path) {
+            if file.try_lock_exclusive().is_ok() && is_in_place(&file, &self.path) && !self.descriptor.exists() {
+                let _ = std::fs::remove_file(&self.path);
+            }
+            let _ = file.unlock();
+        }
+    }
+}
+
+/// Whether the opened lock file is still the one at the given path, not removed or replaced since opened.
+#[cfg(unix)]
+fn is_in_place(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    return match (file.metadata(), std::fs::metadata(path)) {
+        (Ok(opened), Ok(current)) => { opened.dev() == current.dev() && opened.ino() == current.ino() }
+        _ => { false }
+    };
+}
+
+/// Whether the opened lock file is still the one at the given path. Without file identities at hand, the path
+/// still existing is taken for the lock file being in place.
+#[cfg(not(unix))]
+fn is_in_place(_file: &File, path: &Path) -> bool {
+    return path.exists();
+}
+
+/// Path to the lock file of the given deployment descriptor, e.g. `h2o-test.json.lock`.
+fn lock_path(descriptor: &Path) -> PathBuf {
+    let mut file_name = descriptor.file_name().map(|name| name.to_os_string()).unwrap_or_default();
+    file_name.push(LOCK_SUFFIX);
+    return descriptor.with_file_name(file_name);
+}
+
+/// The holder recorded in the lock file, as a JSON document.
+fn holder_record(identity: &str, operation: &str, since: DateTime<Utc>) -> String {
+    return json!({
+        "holder": identity,
+        "operation": operation,
+        "since": since.to_rfc3339_opts(SecondsFormat::Secs, true),
+    }).to_string();
+}
+
+/// Parses the holder recorded in the lock file. A holder not recorded is reported as unknown.
+fn parse_holder(record: &str) -> LockHolder {
+    let record: Value = serde_json::from_str(record).unwrap_or(Value::Null);
+    return LockHolder {
+        identity: record["holder"].as_str().unwrap_or("unknown").to_string(),
+        operation: record["operation"].as_str().map(str::to_string),
+        since: record["since"].as_str()
+            .and_then(|since| DateTime::parse_from_rfc3339(since).ok())
+            .map(|since| since.with_timezone(&Utc)),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::{DescriptorLock, LockError};
+
+    #[test]
+    fn test_descriptor_lock() {
+        let directory: PathBuf = std::env::temp_dir().join(format!("h2ok-lock-test-{}", std::process::id()));
+        let descriptor: PathBuf = directory.join("h2o-test.json");
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(&descriptor, "{}").unwrap();
+
+        let lock: DescriptorLock = DescriptorLock::acquire(&descriptor, "ingress").unwrap();
+        assert!(directory.join("h2o-test.json.lock").is_file());
+        match DescriptorLock::acquire(&descriptor, "undeploy") {
+            Err(LockError::Held(holder)) => {
+                assert_eq!(Some("ingress".to_string()), holder.operation);
+                assert!(holder.since.is_some());
+            }
+            _ => { panic!("The deployment descriptor is expected to be locked."); }
+        }
+        drop(lock);
+
+        // Once the deployment descriptor is gone, so is its lock file.
+        let lock: DescriptorLock = DescriptorLock::acquire(&descriptor, "undeploy").unwrap();
+        std::fs::remove_file(&descriptor).unwrap();
+        drop(lock);
+        assert!(!directory.join("h2o-test.json.lock").exists());
+
+        // A lock on a lock file removed meanwhile is not in place any more.
+        std::fs::write(&descriptor, "{}").unwrap();
+        let lock: DescriptorLock = DescriptorLock::acquire(&descriptor, "ingress").unwrap();
+        assert!(super::is_in_place(&lock.file, &lock.path));
+        std::fs::remove_file(&lock.path).unwrap();
+        assert!(!super::is_in_place(&lock.file, &lock.path));
+        drop(lock);
+        std::fs::remove_file(&descriptor).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -1,166 +1,1326 @@
 extern crate clap;
 
-use std::fs::File;
-use std::io::Write;
-use std::path::Path;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::thread::JoinHandle;
+use std::time::Duration;
 
 use atty::Stream;
 use kube::Client;
 
-use crate::cli::{Command, UserDeploymentSpecification};
-use crate::k8s::{Deployment, DeploymentSpecification};
+use chrono::Utc;
+
+use crate::cli::CommandErrorKind::ConfirmationRequired;
+use crate::cli::{AdoptSpecification, ApplySpecification, Command, ConfigSpecification, DescriptorAction, DescriptorSource, DescriptorSpecification, DescriptorStore, ExportSpecification, IngressSpecification, ListSpecification, OutputFormat, ProtectSpecification, StatusSpecification, UndeploySpecification,
+                 UndeployTarget, UserDeploymentSpecification, WaitSpecification};
+use crate::cli::UserInputError;
+use crate::error::H2okError;
+use crate::k8s::{ClusterConnection, ConnectedCluster, Deployment, DeploymentFailure, DeploymentSpecification, DeployOptions, DryRun, UndeployOptions};
+use crate::k8s::discovery::{AdoptionError, DeploymentSummary};
+use crate::k8s::export::ExportedFile;
+use crate::k8s::identity::ClusterIdentity;
+use crate::k8s::lease::{DeploymentLease, LeaseError};
+use crate::k8s::preflight::{Check, CheckResult};
+use crate::k8s::retry::RetryPolicy;
+use crate::k8s::spec::{Change, ClusterSpec};
+use crate::k8s::status::DeploymentStatus;
+use crate::k8s::wait::{WaitCondition, WaitError};
+use crate::output::{ApplyDocument, ConfigDocument, ConfigValueDocument, DeploymentDocument, DescriptorDocument, DescriptorReport, DescriptorResult, ExportDocument, PreflightDocument, ProtectionDocument, SummaryDocument, SummaryList, UndeploymentDocument, UndeployReport, UndeployResult, WaitDocument};
+use crate::lock::{DescriptorLock, LockError};
+use crate::state::StateDir;
 
 mod cli;
+mod descriptor;
+mod error;
 mod k8s;
+mod lock;
+mod output;
+mod state;
 #[cfg(test)]
 mod tests;
 
+/// Interval to refresh the status in when watching status of a deployment.
+const STATUS_REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+/// Maximum time to wait for H2O pods to be gone before an H2O cluster is deployed again by `apply --recreate`.
+const RECREATION_TIMEOUT: Duration = Duration::from_secs(300);
+
 fn main() {
-    let command: Command = match cli::get_command() {
-        Ok(cmd) => { cmd }
-        Err(error) => {
-            eprintln!("Unable to process user input: {:?}", error);
-            std::process::exit(1);
+    if let Err(error) = run() {
+        eprintln!("Error: {}", error);
+        if let Some(hint) = error.hint() {
+            eprintln!("Hint: {}", hint);
         }
-    };
-    match command {
+        std::process::exit(error.exit_code());
+    }
+}
+
+fn run() -> Result<(), H2okError> {
+    let command: Command = cli::get_command()?;
+    return match command {
         Command::Deployment(deployment) => {
-            deploy(deployment);
+            deploy(deployment)
+        }
+        Command::Preflight(deployment) => {
+            preflight(deployment)
+        }
+        Command::Undeploy(undeploy_specification) => {
+            undeploy(undeploy_specification)
+        }
+        Command::Ingress(ingress_specification) => {
+            ingress(ingress_specification)
         }
-        Command::Undeploy(deployment_path) => {
-            undeploy(deployment_path.as_path())
+        Command::Wait(wait_specification) => {
+            wait(wait_specification)
         }
-        Command::Ingress(deployment_path) => {
-            ingress(&deployment_path);
+        Command::Status(status_specification) => {
+            status(status_specification)
+        }
+        Command::List(list_specification) => {
+            list(list_specification)
+        }
+        Command::Adopt(adopt_specification) => {
+            adopt(adopt_specification)
+        }
+        Command::Protect(protect_specification) => {
+            protect(protect_specification)
+        }
+        Command::Export(export_specification) => {
+            export(export_specification)
+        }
+        Command::Apply(apply_specification) => {
+            apply(apply_specification)
+        }
+        Command::Config(config_specification) => {
+            show_config(config_specification);
+            Ok(())
+        }
+        Command::Descriptor(descriptor_specification) => {
+            descriptor(descriptor_specification)
         }
     };
 }
 
-fn deploy(user_deployment_spec: UserDeploymentSpecification) {
-    let (client, namespace): (Client, String) = if let Some(kubeconfig) = &user_deployment_spec.kubeconfig_path {
-        k8s::from_kubeconfig(kubeconfig.as_path())
-    } else {
-        match k8s::try_default() {
-            Ok(client_namespace) => {
-                client_namespace
-            }
-            Err(_) => { panic!("No kubeconfig provided by the user and search in well-known kubeconfig locations failed") }
+fn deploy(user_deployment_spec: UserDeploymentSpecification) -> Result<(), H2okError> {
+    if user_deployment_spec.options.dry_run == Some(DryRun::Client) {
+        return render(user_deployment_spec);
+    }
+    let cluster: ConnectedCluster = kubernetes_client(&user_deployment_spec.kubeconfig_path, &user_deployment_spec.connection)?;
+    let namespace: String = user_deployment_spec.namespace.unwrap_or_else(|| cluster.default_namespace.clone());
+
+    let mut deployment_spec: DeploymentSpecification = DeploymentSpecification::new(user_deployment_spec.name, namespace, user_deployment_spec.memory_percentage, user_deployment_spec.memory, user_deployment_spec.num_cpu, user_deployment_spec.num_h2o_nodes,
+                                                                                    user_deployment_spec.kubeconfig_path);
+    deployment_spec.connection = user_deployment_spec.connection;
+    if user_deployment_spec.options.dry_run.is_none() {
+        let cluster_identity: ClusterIdentity = k8s::identity::identify(&cluster, &user_deployment_spec.options.retry)
+            .map_err(|e| H2okError::api("Unable to identify the Kubernetes cluster", e))?;
+        deployment_spec.cluster_identity = Some(cluster_identity);
+    }
+    if user_deployment_spec.preflight {
+        run_preflight(&cluster, &deployment_spec, &user_deployment_spec.options, None)?;
+    }
+    let output: OutputFormat = user_deployment_spec.output;
+    if user_deployment_spec.options.dry_run == Some(DryRun::Server) {
+        let deployment: Deployment = k8s::deploy_h2o_cluster(&cluster.client, deployment_spec, &user_deployment_spec.options)
+            .map_err(|error| match error.failure {
+                DeploymentFailure::Api(e) => { H2okError::api("H2O cluster rejected by the Kubernetes API server", e) }
+                _ => { H2okError::rollback("Unable to validate H2O cluster", error) }
+            })?;
+        print_manifests(&deployment, output);
+        if deployment.namespace_created {
+            return Err(H2okError::Incomplete(format!("Namespace '{}' does not exist yet. The H2O entities in it have been rendered, \
+            not validated by the Kubernetes API server.", deployment.specification.namespace)));
         }
+        return Ok(());
+    }
+
+    let deployment: Deployment = k8s::deploy_h2o_cluster(&cluster.client, deployment_spec, &user_deployment_spec.options)
+        .map_err(|error| H2okError::rollback("Unable to deploy H2O cluster", error))?;
+    let persisted_filename = persist_new_deployment(&deployment, &cluster, user_deployment_spec.store, &user_deployment_spec.state_dir,
+                                                    &user_deployment_spec.descriptor_out, &user_deployment_spec.options.retry)?;
+
+    if output != OutputFormat::Text {
+        output::print_document(&DeploymentDocument::new(&deployment, Some(persisted_filename)), output);
+    } else if running_on_terminal() {
+        println!("Deployment of '{}' completed successfully.", deployment.specification.name);
+        println!("To undeploy, use the '{}' command.", undeploy_command(&deployment.specification.name, &deployment.specification.namespace, &persisted_filename,
+                                                                        stored_in_cluster(user_deployment_spec.store, &user_deployment_spec.descriptor_out)));
+    } else {
+        // If not running on a terminal, print only the path to the deployment descriptor actually written.
+        print!("{}", persisted_filename);
+    }
+    return Ok(());
+}
+
+/// Renders the Kubernetes entities of an H2O cluster without contacting the Kubernetes cluster. The kubeconfig is only read
+/// if no namespace has been given, to find the default namespace.
+fn render(user_deployment_spec: UserDeploymentSpecification) -> Result<(), H2okError> {
+    let namespace: String = match user_deployment_spec.namespace {
+        Some(namespace) => { namespace }
+        None => { kubernetes_client(&user_deployment_spec.kubeconfig_path, &user_deployment_spec.connection)?.default_namespace }
     };
+    let deployment_spec: DeploymentSpecification = DeploymentSpecification::new(user_deployment_spec.name, namespace, user_deployment_spec.memory_percentage, user_deployment_spec.memory, user_deployment_spec.num_cpu, user_deployment_spec.num_h2o_nodes,
+                                                                                user_deployment_spec.kubeconfig_path);
+    let deployment: Deployment = k8s::render_h2o_cluster(deployment_spec, &user_deployment_spec.options);
+    print_manifests(&deployment, user_deployment_spec.output);
+    return Ok(());
+}
+
+/// Prints the Kubernetes entities of the deployment to stdout as a multi-document YAML. With `--output json`,
+/// each entity is printed as a JSON document on a separate line instead.
+fn print_manifests(deployment: &Deployment, output: OutputFormat) {
+    let format: OutputFormat = if output == OutputFormat::Json { OutputFormat::Json } else { OutputFormat::Yaml };
+    for manifest in k8s::manifests(deployment).iter() {
+        output::print_document(manifest, format);
+    }
+}
 
+/// Runs the pre-flight checks of a deployment with the given specification without deploying it.
+fn preflight(user_deployment_spec: UserDeploymentSpecification) -> Result<(), H2okError> {
+    let cluster: ConnectedCluster = kubernetes_client(&user_deployment_spec.kubeconfig_path, &user_deployment_spec.connection)?;
+    let namespace: String = user_deployment_spec.namespace.unwrap_or_else(|| cluster.default_namespace.clone());
     let deployment_spec: DeploymentSpecification = DeploymentSpecification::new(user_deployment_spec.name, namespace, user_deployment_spec.memory_percentage, user_deployment_spec.memory, user_deployment_spec.num_cpu, user_deployment_spec.num_h2o_nodes,
                                                                                 user_deployment_spec.kubeconfig_path);
+    let checks: Vec<Check> = k8s::preflight::preflight(&cluster.client, &deployment_spec, &user_deployment_spec.options, None)
+        .map_err(|e| H2okError::api("Unable to run pre-flight checks", e))?;
 
-    let deployment: Deployment = match k8s::deploy_h2o_cluster(&client, deployment_spec) {
-        Ok(successful_deployment) => { successful_deployment }
-        Err(error) => {
-            panic!("Unable to deploy H2O cluster. Error:\n{}", error);
+    let output: OutputFormat = user_deployment_spec.output;
+    if output != OutputFormat::Text {
+        output::print_document(&PreflightDocument::new(&deployment_spec.name, &deployment_spec.namespace, checks.clone()), output);
+    } else {
+        for check in checks.iter() {
+            println!("{}", format_check(check));
         }
+    }
+    return preflight_outcome(&checks);
+}
+
+/// Runs the pre-flight checks of a deployment about to be made. Only the checks worth attention are reported to stderr,
+/// so the output of a successful deployment stays the same. The resources of the `replaced` deployment count as available.
+fn run_preflight(cluster: &ConnectedCluster, deployment_spec: &DeploymentSpecification, options: &DeployOptions,
+                 replaced: Option<&DeploymentSpecification>) -> Result<(), H2okError> {
+    let checks: Vec<Check> = k8s::preflight::preflight(&cluster.client, deployment_spec, options, replaced)
+        .map_err(|e| H2okError::api("Unable to run pre-flight checks", e))?;
+    for check in checks.iter().filter(|check| check.result != CheckResult::Pass) {
+        eprintln!("{}", format_check(check));
+    }
+    return preflight_outcome(&checks);
+}
+
+/// Formats a single pre-flight check as a line of text, e.g. `FAIL Node capacity: ...`.
+fn format_check(check: &Check) -> String {
+    return format!("{} {}: {}", check.result.name(), check.name, check.explanation);
+}
+
+/// Returns an error if any of the pre-flight checks failed.
+fn preflight_outcome(checks: &[Check]) -> Result<(), H2okError> {
+    let failed: usize = checks.iter().filter(|check| check.result == CheckResult::Fail).count();
+    return if failed == 0 {
+        Ok(())
+    } else {
+        Err(H2okError::Preflight(format!("{} of {} pre-flight checks failed.", failed, checks.len())))
     };
-    let persisted_filename = persist_deployment(&deployment, false).unwrap();
+}
 
-    if running_on_terminal() {
-        println!("Deployment of '{}' completed successfully.", deployment.specification.name);
-        println!("To undeploy, use the 'h2ok undeploy -f {}' command.", persisted_filename);
+/// Connects to a Kubernetes cluster, using the user-provided kubeconfig if there is one.
+/// Otherwise, kubeconfig is searched for in the environment and well-known locations. The kubeconfig context, cluster, user
+/// and impersonation are chosen by the `connection`.
+fn kubernetes_client(kubeconfig_path: &Option<PathBuf>, connection: &ClusterConnection) -> Result<ConnectedCluster, H2okError> {
+    return k8s::connect(kubeconfig_path.as_deref(), connection)
+        .map_err(H2okError::kubeconfig);
+}
+
+/// Name of the deployment descriptor file for a deployment with the given name, as written to the current working directory
+/// if there is no state directory.
+fn deployment_file_name(deployment_name: &str) -> String {
+    return format!("{}.h2ok", deployment_name);
+}
+
+/// Path the deployment descriptor of a deployment is written to. Unless the user has chosen the path using `descriptor_out`,
+/// it is `<context>/<namespace>/<name>.json` in the state directory, or `<name>.h2ok` in the current working directory if there is no state directory.
+fn descriptor_path(name: &str, namespace: &str, context: Option<&str>, state_dir: &Option<StateDir>, descriptor_out: &Option<PathBuf>) -> PathBuf {
+    if let Some(descriptor_out) = descriptor_out {
+        return descriptor_out.clone();
+    }
+    return match state_dir {
+        Some(state_dir) => { state_dir.descriptor_path(context, namespace, name) }
+        None => { PathBuf::from(deployment_file_name(name)) }
+    };
+}
+
+/// Persists the deployment descriptor of a newly made deployment into the store chosen. A stale deployment descriptor in the state directory
+/// or under the path chosen by the user is overwritten. In the current working directory, a number is appended to the file name instead.
+/// Returns the path to the deployment descriptor file or the ConfigMap it has been stored in.
+fn persist_new_deployment(deployment: &Deployment, cluster: &ConnectedCluster, store: DescriptorStore, state_dir: &Option<StateDir>,
+                          descriptor_out: &Option<PathBuf>, retry_policy: &RetryPolicy) -> Result<String, H2okError> {
+    if store == DescriptorStore::Cluster {
+        return store_deployment(&cluster.client, deployment, descriptor_out, retry_policy);
+    }
+    let path: PathBuf = descriptor_path(&deployment.specification.name, &deployment.specification.namespace, cluster.context.as_deref(),
+                                        state_dir, descriptor_out);
+    return persist_deployment(deployment, &path, state_dir.is_some() || descriptor_out.is_some());
+}
+
+/// Stores the deployment descriptor in a ConfigMap in the namespace of the deployment, replacing the one stored previously. If `descriptor_out`
+/// is given, the deployment descriptor is written to that file as well and its path is returned. Otherwise, the ConfigMap is returned
+/// as `configmap/<namespace>/<name>`.
+fn store_deployment(client: &Client, deployment: &Deployment, descriptor_out: &Option<PathBuf>, retry_policy: &RetryPolicy) -> Result<String, H2okError> {
+    let config_map: String = k8s::store::save(client, deployment, descriptor::to_json(deployment), retry_policy)
+        .map_err(|e| H2okError::api(&format!("Unable to store the deployment descriptor of '{}' in the Kubernetes cluster", deployment.specification.name), e))?;
+    return match descriptor_out {
+        Some(descriptor_out) => { persist_deployment(deployment, descriptor_out, true) }
+        None => { Ok(stored_descriptor_reference(&deployment.specification.namespace, &config_map)) }
+    };
+}
+
+/// Reference to a deployment descriptor stored in the Kubernetes cluster, reported instead of a deployment descriptor path.
+fn stored_descriptor_reference(namespace: &str, config_map: &str) -> String {
+    return format!("configmap/{}/{}", namespace, config_map);
+}
+
+/// Returns true if the deployment descriptor persisted is only stored in the Kubernetes cluster, with no deployment descriptor file written.
+fn stored_in_cluster(store: DescriptorStore, descriptor_out: &Option<PathBuf>) -> bool {
+    return store == DescriptorStore::Cluster && descriptor_out.is_none();
+}
+
+/// Command to undeploy the deployment with, as suggested to the user once the deployment descriptor has been persisted.
+fn undeploy_command(name: &str, namespace: &str, persisted: &str, stored_in_cluster: bool) -> String {
+    return if stored_in_cluster {
+        format!("h2ok undeploy --name {} --namespace {}", name, namespace)
     } else {
-        // If not running on a terminal, print only the deployment name.
-        print!("{}.h2ok", deployment.specification.name);
+        format!("h2ok undeploy -f {}", persisted)
+    };
+}
+
+/// Persists a Deployment into a deployment descriptor file under the given path, creating the missing parent directories. If the file
+/// exists and `overwrite` is false, a number is appended to the file name, e.g. `h2o-test(1).h2ok`. Returns the path actually written.
+fn persist_deployment(deployment: &Deployment, path: &Path, overwrite: bool) -> Result<String, H2okError> {
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).map_err(|e| H2okError::descriptor(path, e))?;
+    }
+    let mut path: PathBuf = path.to_path_buf();
+    if path.exists() && !overwrite {
+        let stem: String = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+        let extension: String = path.extension().map(|extension| format!(".{}", extension.to_string_lossy())).unwrap_or_default();
+        let mut duplicate_deployment_count: i64 = 0;
+        while path.exists() {
+            duplicate_deployment_count += 1;
+            path = path.with_file_name(format!("{}({}){}", stem, duplicate_deployment_count, extension));
+        }
     }
+    descriptor::write(deployment, &path).map_err(|e| H2okError::descriptor(&path, e))?;
+    return Ok(path.to_string_lossy().to_string());
+}
+
+/// Result of undeployment of a single H2O deployment.
+enum UndeployOutcome {
+    /// All the Kubernetes entities of the deployment were removed.
+    Removed,
+    /// Some of the Kubernetes entities of the deployment were not removed. Contains the reasons.
+    PartiallyRemoved(Vec<String>),
+    /// None of the Kubernetes entities of the deployment were removed. Contains the reasons.
+    Failed(Vec<String>),
 }
 
-///Persists a Deployment into current workdir. Name of the resulting file is the name of the deployment name followed by `.h2ok` suffix.
-fn persist_deployment(deployment: &Deployment, overwrite: bool) -> Result<String, std::io::Error> {
-    let mut file_name = format!("{}.h2ok", deployment.specification.name);
-    let mut path: &Path = Path::new(file_name.as_str());
-    let mut duplicate_deployment_count: i64 = 0;
+/// Deployment with the entities still present, if its deployment descriptor could be read, and the outcome of its undeployment.
+type Undeployment = (Option<Deployment>, UndeployOutcome);
 
-    if path.exists() {
-        if overwrite {
-            match std::fs::remove_file(path) {
-                Ok(_) => {}
-                Err(e) => { panic!("Unable to remove existing deployment file. Reason: \n{}", e) }
+impl UndeployOutcome {
+    /// Converts the outcome into a machine-readable document describing the given deployment, if known, and its deployment descriptor.
+    fn into_document(self, deployment: Option<&Deployment>, descriptor: Option<String>) -> UndeploymentDocument {
+        let (result, errors): (UndeployResult, Vec<String>) = match self {
+            UndeployOutcome::Removed => { (UndeployResult::Removed, vec!()) }
+            UndeployOutcome::PartiallyRemoved(reasons) => { (UndeployResult::PartiallyRemoved, reasons) }
+            UndeployOutcome::Failed(reasons) => { (UndeployResult::Failed, reasons) }
+        };
+        return UndeploymentDocument::new(deployment, descriptor, result, errors);
+    }
+}
+
+fn undeploy(undeploy_specification: UndeploySpecification) -> Result<(), H2okError> {
+    return match &undeploy_specification.target {
+        UndeployTarget::Descriptors(deployment_descriptors) => {
+            undeploy_descriptors(deployment_descriptors, &undeploy_specification.options, undeploy_specification.ignore_cluster_mismatch,
+                                 undeploy_specification.wait_timeout, undeploy_specification.output)
+        }
+        _ => {
+            undeploy_discovered(&undeploy_specification)
+        }
+    };
+}
+
+/// Undeploys all the H2O deployments described by the given deployment descriptors concurrently.
+/// If there is more than one deployment descriptor, a summary is printed at the end. Returns an error
+/// if any of the H2O deployments has not been fully removed.
+fn undeploy_descriptors(deployment_descriptors: &[PathBuf], options: &UndeployOptions, ignore_cluster_mismatch: bool, wait_timeout: Option<Duration>,
+                        output: OutputFormat) -> Result<(), H2okError> {
+    let undeployments: Vec<(PathBuf, JoinHandle<Undeployment>)> = deployment_descriptors.iter()
+        .map(|deployment_descriptor| {
+            let descriptor_path: PathBuf = deployment_descriptor.clone();
+            let options: UndeployOptions = options.clone();
+            (deployment_descriptor.clone(), std::thread::spawn(move || {
+                undeploy_descriptor(&descriptor_path, &options, ignore_cluster_mismatch, wait_timeout, output)
+            }))
+        })
+        .collect();
+
+    let outcomes: Vec<(PathBuf, Option<Deployment>, UndeployOutcome)> = undeployments.into_iter()
+        .map(|(deployment_descriptor, undeployment)| {
+            let (deployment, outcome): Undeployment = undeployment.join().unwrap_or_else(|panic| {
+                let reason: String = panic.downcast_ref::<String>().cloned()
+                    .or_else(|| panic.downcast_ref::<&str>().map(|reason| reason.to_string()))
+                    .unwrap_or_else(|| "Unknown error".to_string());
+                (None, UndeployOutcome::Failed(vec!(reason)))
+            });
+            (deployment_descriptor, deployment, outcome)
+        })
+        .collect();
+
+    let not_removed: usize = outcomes.iter()
+        .filter(|(_, _, outcome)| !matches!(outcome, UndeployOutcome::Removed))
+        .count();
+    let outcomes_count: usize = outcomes.len();
+
+    if output != OutputFormat::Text {
+        let deployments: Vec<UndeploymentDocument> = outcomes.into_iter()
+            .map(|(deployment_descriptor, deployment, outcome)| {
+                outcome.into_document(deployment.as_ref(), Some(deployment_descriptor.to_string_lossy().to_string()))
+            })
+            .collect();
+        output::print_document(&UndeployReport { deployments }, output);
+    } else if outcomes_count > 1 {
+        println!();
+        println!("{:<40} {:<30} {:<18} DETAILS", "DESCRIPTOR", "DEPLOYMENT", "RESULT");
+        for (deployment_descriptor, deployment, outcome) in outcomes.iter() {
+            let name: &str = deployment.as_ref().map(|deployment| deployment.specification.name.as_str()).unwrap_or("-");
+            let (result, details): (&str, String) = match outcome {
+                UndeployOutcome::Removed => { ("removed", String::new()) }
+                UndeployOutcome::PartiallyRemoved(reasons) => { ("partially removed", reasons.join("; ")) }
+                UndeployOutcome::Failed(reasons) => { ("failed", reasons.join("; ")) }
+            };
+            println!("{:<40} {:<30} {:<18} {}", deployment_descriptor.display(), name, result, details.replace('\n', " "));
+        }
+    }
+
+    return if not_removed > 0 {
+        Err(H2okError::Incomplete(format!("{} of {} H2O deployments not fully removed.", not_removed, outcomes_count)))
+    } else {
+        Ok(())
+    };
+}
+
+/// Undeploys the H2O deployment described by the given deployment descriptor. If `wait_timeout` is defined, waits
+/// until all H2O pods are gone. Unless `ignore_cluster_mismatch` is true, the deployment is only undeployed from the Kubernetes cluster
+/// recorded in the deployment descriptor. Returns the deployment with the entities still present, if the deployment descriptor could be read,
+/// and the outcome of the undeployment.
+fn undeploy_descriptor(deployment_descriptor: &Path, options: &UndeployOptions, ignore_cluster_mismatch: bool, wait_timeout: Option<Duration>,
+                       output: OutputFormat) -> Undeployment {
+    let source: DescriptorSource = DescriptorSource::File(deployment_descriptor.to_path_buf());
+    let (mut deployment, client, _locks): (Deployment, Client, DeploymentLocks) = match extract_deployment_to_modify(&source, "undeploy", ignore_cluster_mismatch,
+                                                                                                                     &options.retry) {
+        Ok(deployment_client) => { deployment_client }
+        Err(e) => {
+            eprintln!("{}", e);
+            return (None, UndeployOutcome::Failed(vec!(e.to_string())));
+        }
+    };
+    let entities_count: usize = k8s::describe_entities(&deployment).len();
+
+    let outcome: UndeployOutcome = match k8s::undeploy_h2o(&client, &mut deployment, options) {
+        Ok(_) => {
+            match wait_until_pods_gone(&client, &deployment, wait_timeout) {
+                Ok(_) => {
+                    report(output, &format!("Removed deployment '{}'.", deployment.specification.name));
+                    if let Err(e) = std::fs::remove_file(deployment_descriptor) {
+                        eprintln!("Unable to remove deployment descriptor '{}'. Reason: {}", deployment_descriptor.display(), e);
+                    }
+                    UndeployOutcome::Removed
+                }
+                Err(reason) => {
+                    // The descriptor is kept, so the user is able to wait for the H2O pods to be gone using the `wait` command.
+                    if let Err(e) = descriptor::write(&deployment, deployment_descriptor) {
+                        eprintln!("Unable to update deployment descriptor '{}'. Reason: {}", deployment_descriptor.display(), e);
+                    }
+                    eprintln!("{}", reason);
+                    UndeployOutcome::PartiallyRemoved(vec!(reason))
+                }
             }
-        } else {
-            while path.exists() {
-                println!("Writing file");
-                duplicate_deployment_count += 1;
-                file_name = format!("{}({}).h2ok", deployment.specification.name, duplicate_deployment_count);
-                path = Path::new(file_name.as_str());
+        }
+        Err(deployment_errs) => {
+            for undeployed in deployment_errs.iter() {
+                eprintln!("Unable to undeploy {}", undeployed)
+            }
+            // Keep track of entities still present, so another undeploy attempt only retries those.
+            match descriptor::write(&deployment, deployment_descriptor) {
+                Ok(_) => {
+                    eprintln!("Deployment '{}' has not been fully removed. Deployment descriptor '{}' now only contains the remaining entities, \
+                    undeploy it again to retry.", deployment.specification.name, deployment_descriptor.display());
+                }
+                Err(e) => {
+                    eprintln!("Deployment '{}' has not been fully removed and deployment descriptor '{}' could not be updated. Reason: {}",
+                              deployment.specification.name, deployment_descriptor.display(), e);
+                }
             }
+            if k8s::describe_entities(&deployment).len() < entities_count {
+                UndeployOutcome::PartiallyRemoved(deployment_errs)
+            } else {
+                UndeployOutcome::Failed(deployment_errs)
+            }
+        }
+    };
+    return (Some(deployment), outcome);
+}
+
+/// Undeploys H2O deployments found in the Kubernetes cluster by name, label selector or all the H2O deployments in a namespace.
+/// Unless the user has chosen otherwise, asks for confirmation first.
+fn undeploy_discovered(undeploy_specification: &UndeploySpecification) -> Result<(), H2okError> {
+    let cluster: ConnectedCluster = kubernetes_client(&undeploy_specification.kubeconfig_path, &undeploy_specification.connection)?;
+    let client: Client = cluster.client.clone();
+    let context: Option<String> = cluster.context.clone();
+    let namespace: String = undeploy_specification.namespace.clone().unwrap_or_else(|| cluster.default_namespace.clone());
+    let kubeconfig_path: Option<PathBuf> = undeploy_specification.kubeconfig_path.clone();
+    let output: OutputFormat = undeploy_specification.output;
+
+    let found_deployments = match &undeploy_specification.target {
+        UndeployTarget::Name(name) => {
+            k8s::discovery::adopt_deployment(&client, name, &namespace, kubeconfig_path).map(|deployment| vec!(deployment))
+        }
+        UndeployTarget::Selector(selector) => {
+            k8s::discovery::find_deployments(&client, &namespace, Some(selector), kubeconfig_path)
         }
+        UndeployTarget::All => {
+            k8s::discovery::find_deployments(&client, &namespace, None, kubeconfig_path)
+        }
+        UndeployTarget::Descriptors(_) => { unreachable!("Deployments described by a descriptor are not searched for.") }
+    };
+    let deployments: Vec<Deployment> = found_deployments
+        .map_err(|e| H2okError::adoption("Unable to find H2O deployments to undeploy", e))?;
+
+    if deployments.is_empty() {
+        report(output, &format!("No H2O deployments found in namespace '{}'.", namespace));
+        output::print_document(&UndeployReport { deployments: vec!() }, output);
+        return Ok(());
     }
-    let mut file: File = match File::create(path) {
-        Ok(file) => { file }
-        Err(err) => {
-            println!("Unable to write deployment file '{}' - skipping. Reason: {}", path.to_str().unwrap(), err);
-            return Err(err);
+
+    report(output, "The following Kubernetes entities will be deleted:");
+    for deployment in deployments.iter() {
+        report(output, &format!("H2O deployment '{}' in namespace '{}':", deployment.specification.name, deployment.specification.namespace));
+        for entity in k8s::describe_entities(deployment).iter() {
+            report(output, &format!("  {}", entity));
+        }
+        if undeploy_specification.options.purge && deployment.namespace_created {
+            report(output, &format!("  Namespace {}", deployment.specification.namespace));
         }
+    }
+    if !undeploy_specification.assume_yes && !confirm("Proceed with undeployment?")? {
+        report(output, "Undeployment cancelled.");
+        output::print_document(&UndeployReport { deployments: vec!() }, output);
+        return Ok(());
+    }
+
+    // Local deployment descriptors of same-named deployments in other clusters are kept. If the cluster UID can not be read,
+    // the API server and the context are still compared.
+    let cluster_identity: ClusterIdentity = k8s::identity::identify(&cluster, &undeploy_specification.options.retry)
+        .unwrap_or_else(|_| ClusterIdentity { api_server: cluster.api_server.clone(), context: cluster.context.clone(), cluster_uid: None });
+    let mut documents: Vec<UndeploymentDocument> = vec!();
+    for mut deployment in deployments.into_iter() {
+        let _lease: Option<DeploymentLease> = match lock_deployment(&client, &deployment.specification.name, &deployment.specification.namespace,
+                                                                    "undeploy", &undeploy_specification.options.retry) {
+            Ok(lease) => { lease }
+            Err(e) => {
+                eprintln!("{}", e);
+                documents.push(UndeployOutcome::Failed(vec!(e.to_string())).into_document(Some(&deployment), None));
+                continue;
+            }
+        };
+        let entities_count: usize = k8s::describe_entities(&deployment).len();
+        let (outcome, removed_descriptor): (UndeployOutcome, Option<String>) = match k8s::undeploy_h2o(&client, &mut deployment, &undeploy_specification.options) {
+            Ok(_) => {
+                match wait_until_pods_gone(&client, &deployment, undeploy_specification.wait_timeout) {
+                    Ok(_) => {
+                        report(output, &format!("Removed deployment '{}'.", deployment.specification.name));
+                        (UndeployOutcome::Removed, remove_local_descriptor(&deployment, &cluster_identity, context.as_deref(), &undeploy_specification.state_dir, output))
+                    }
+                    Err(reason) => {
+                        eprintln!("{}", reason);
+                        (UndeployOutcome::PartiallyRemoved(vec!(reason)), None)
+                    }
+                }
+            }
+            Err(deployment_errs) => {
+                for undeployed in deployment_errs.iter() {
+                    eprintln!("Unable to undeploy {}", undeployed)
+                }
+                eprintln!("Deployment '{}' has not been fully removed.", deployment.specification.name);
+                if k8s::describe_entities(&deployment).len() < entities_count {
+                    (UndeployOutcome::PartiallyRemoved(deployment_errs), None)
+                } else {
+                    (UndeployOutcome::Failed(deployment_errs), None)
+                }
+            }
+        };
+        documents.push(outcome.into_document(Some(&deployment), removed_descriptor));
+    }
+
+    let all_removed: bool = documents.iter().all(|document| document.result == UndeployResult::Removed);
+    output::print_document(&UndeployReport { deployments: documents }, output);
+    return if all_removed {
+        Ok(())
+    } else {
+        Err(H2okError::Incomplete("Some of the H2O deployments have not been fully removed.".to_string()))
     };
-    file.write_all(serde_json::to_string(deployment).unwrap().as_bytes()).unwrap();
-    return Ok(String::from(path.to_str().unwrap()));
 }
 
-fn undeploy(deployment_descriptor: &Path) {
-    let (deployment, client): (Deployment, Client) = extract_existing_deployment(deployment_descriptor);
-    match k8s::undeploy_h2o(&client, &deployment) {
-        Ok(_) => {}
-        Err(deployment_errs) => {
-            for undeployed in deployment_errs.iter() {
-                print!("Unable to undeploy '{}' - skipping.", undeployed)
+/// Waits until all H2O pods of the undeployed deployment are gone, if `wait_timeout` is defined. Otherwise returns immediately.
+fn wait_until_pods_gone(client: &Client, deployment: &Deployment, wait_timeout: Option<Duration>) -> Result<(), String> {
+    let timeout: Duration = match wait_timeout {
+        None => { return Ok(()); }
+        Some(timeout) => { timeout }
+    };
+    return k8s::wait::wait_for(client, deployment, WaitCondition::Deleted, timeout)
+        .map_err(|e| format!("H2O pods of deployment '{}' are still present. Reason: {}", deployment.specification.name, e));
+}
+
+/// Removes deployment descriptors of the given deployment from the state directory and the current working directory, if there are any
+/// describing the very same deployment in the Kubernetes cluster identified by `cluster_identity`. Deployment descriptors not recording
+/// the cluster are kept, as they may describe a deployment of the same name in another cluster. Returns path to the deployment descriptor removed.
+fn remove_local_descriptor(deployment: &Deployment, cluster_identity: &ClusterIdentity, context: Option<&str>, state_dir: &Option<StateDir>,
+                           output: OutputFormat) -> Option<String> {
+    let mut removed: Option<String> = None;
+    for path in local_descriptor_paths(&deployment.specification.name, &deployment.specification.namespace, context, state_dir).iter() {
+        let local_deployment: Deployment = match descriptor::read(path) {
+            Ok(local_deployment) if local_deployment.specification.namespace == deployment.specification.namespace => { local_deployment }
+            _ => { continue; }
+        };
+        match &local_deployment.specification.cluster_identity {
+            Some(recorded) if k8s::identity::differences(recorded, cluster_identity).is_empty() => {}
+            Some(_) => { continue; }
+            None => {
+                report(output, &format!("Kept deployment descriptor '{}', it does not record the Kubernetes cluster deployed to.", path.display()));
+                continue;
+            }
+        }
+        match std::fs::remove_file(path) {
+            Ok(_) => {
+                report(output, &format!("Removed deployment descriptor '{}'.", path.display()));
+                removed = removed.or_else(|| Some(path.to_string_lossy().to_string()));
+            }
+            Err(e) => {
+                eprintln!("Unable to remove deployment descriptor '{}'. Reason: {}", path.display(), e);
             }
         }
     }
-    println!("Removed deployment '{}'.", deployment.specification.name);
-    std::fs::remove_file(deployment_descriptor).unwrap();
+    return removed;
 }
 
-fn ingress(deployment_descriptor: &Path) {
-    let (mut deployment, client): (Deployment, Client) = extract_existing_deployment(deployment_descriptor);
+/// Paths to the existing deployment descriptors of a deployment with the given name: the one in the state directory and
+/// the one written to the current working directory by older versions of h2ok.
+fn local_descriptor_paths(name: &str, namespace: &str, context: Option<&str>, state_dir: &Option<StateDir>) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = vec!();
+    if let Some(state_dir) = state_dir {
+        paths.push(state_dir.descriptor_path(context, namespace, name));
+    }
+    paths.push(PathBuf::from(deployment_file_name(name)));
+    return paths.into_iter().filter(|path| path.is_file()).collect();
+}
 
-    match k8s::deploy_ingress(&client, &mut deployment) {
-        Ok(_) => {
-            let deployment_file_name: String = persist_deployment(&deployment, true).unwrap();
-            if running_on_terminal() {
-                println!("Ingress '{}' deployed successfully.", &deployment.specification.name);
-                let ingress_ip: Option<String> = k8s::ingress::any_ip(deployment.ingresses.last().unwrap());
-                let ingress_path: Option<String> = k8s::ingress::any_path(deployment.ingresses.last().unwrap());
-
-                if ingress_ip.is_some() && ingress_path.is_some() {
-                    println!("You may now use 'h2o.connect()' to connect to the H2O cluster:");
-                    println!("Python: 'h2o.connect(url=\"http://{}:80{}\")'", ingress_ip.as_ref().unwrap(), ingress_path.as_ref().unwrap());
-                    println!("R: 'h2o.connect(ip = \"{}\", context_path = \"{}\", port=80)'", ingress_ip.as_ref().unwrap(), ingress_path.unwrap().strip_prefix("/").unwrap())
+/// Prints a progress message. With the text output, the message is printed to stdout, otherwise to stderr,
+/// so stdout only contains the machine-readable document.
+fn report(output: OutputFormat, message: &str) {
+    if output == OutputFormat::Text {
+        println!("{}", message);
+    } else {
+        eprintln!("{}", message);
+    }
+}
+
+/// Asks the user a yes/no question on the terminal. The question is printed to stderr, so it does not mix with the command output.
+/// Returns an error if there is no terminal to ask on, as the confirmation is required in such a case.
+fn confirm(question: &str) -> Result<bool, H2okError> {
+    if !atty::is(Stream::Stdin) {
+        return Err(H2okError::UserInput(UserInputError::new(ConfirmationRequired)));
+    }
+    eprint!("{} [y/N] ", question);
+    let mut answer: String = String::new();
+    if std::io::stderr().flush().and_then(|_| std::io::stdin().lock().read_line(&mut answer)).is_err() {
+        return Err(H2okError::UserInput(UserInputError::new(ConfirmationRequired)));
+    }
+    let answer: String = answer.trim().to_lowercase();
+    return Ok(answer == "y" || answer == "yes");
+}
+
+fn ingress(ingress_specification: IngressSpecification) -> Result<(), H2okError> {
+    let (mut deployment, client, _locks): (Deployment, Client, DeploymentLocks) = extract_deployment_to_modify(&ingress_specification.descriptor, "ingress",
+                                                                                                            ingress_specification.ignore_cluster_mismatch, &ingress_specification.retry_policy)?;
+
+    k8s::deploy_ingress(&client, &mut deployment, &ingress_specification.retry_policy)
+        .map_err(|error| H2okError::rollback(&format!("Unable to create ingress for {} deployment", &deployment.specification.name), error))?;
+    let deployment_file_name: String = save_deployment(&deployment, &ingress_specification.descriptor, &client, &ingress_specification.retry_policy)?;
+    let output: OutputFormat = ingress_specification.output;
+    if output != OutputFormat::Text {
+        output::print_document(&DeploymentDocument::new(&deployment, Some(deployment_file_name)), output);
+    } else if running_on_terminal() {
+        println!("Ingress '{}' deployed successfully.", &deployment.specification.name);
+        let ingress_ip: Option<String> = deployment.ingresses.last().and_then(k8s::ingress::any_ip);
+        let ingress_path: Option<String> = deployment.ingresses.last().and_then(k8s::ingress::any_path);
+
+        if let (Some(ingress_ip), Some(ingress_path)) = (ingress_ip, ingress_path) {
+            println!("You may now use 'h2o.connect()' to connect to the H2O cluster:");
+            println!("Python: 'h2o.connect(url=\"http://{}:80{}\")'", ingress_ip, ingress_path);
+            println!("R: 'h2o.connect(ip = \"{}\", context_path = \"{}\", port=80)'", ingress_ip, ingress_path.trim_start_matches('/'))
+        }
+    } else {
+        print!("{}", deployment_file_name);
+    }
+    return Ok(());
+}
+
+fn protect(protect_specification: ProtectSpecification) -> Result<(), H2okError> {
+    let operation: &str = if protect_specification.protected { "protect" } else { "unprotect" };
+    let (deployment, client, _locks): (Deployment, Client, DeploymentLocks) = extract_deployment_to_modify(&protect_specification.descriptor, operation,
+                                                                                                        protect_specification.ignore_cluster_mismatch, &protect_specification.retry_policy)?;
+
+    k8s::protection::set_protection(&client, &deployment, protect_specification.protected, &protect_specification.retry_policy)
+        .map_err(|e| H2okError::api(&format!("Unable to change deletion protection of deployment '{}'", deployment.specification.name), e))?;
+    let output: OutputFormat = protect_specification.output;
+    if output != OutputFormat::Text {
+        output::print_document(&ProtectionDocument {
+            name: deployment.specification.name.clone(),
+            namespace: deployment.specification.namespace.clone(),
+            protected: protect_specification.protected,
+        }, output);
+    } else if running_on_terminal() {
+        if protect_specification.protected {
+            println!("Deployment '{}' is now protected against deletion.", deployment.specification.name);
+        } else {
+            println!("Deletion protection of deployment '{}' removed.", deployment.specification.name);
+        }
+    }
+    return Ok(());
+}
+
+/// Exports the H2O deployment described by the deployment descriptor. The Kubernetes cluster is not contacted, unless the deployment
+/// descriptor is stored there.
+fn export(export_specification: ExportSpecification) -> Result<(), H2okError> {
+    let deployment: Deployment = read_existing_deployment(&export_specification.descriptor, &export_specification.retry_policy)?;
+    let files: Vec<ExportedFile> = k8s::export::export(&deployment, export_specification.format);
+
+    let out: PathBuf = match export_specification.out {
+        Some(out) => { out }
+        None => {
+            // Only a YAML export is not required to be written to a directory.
+            for file in files.iter() {
+                print!("{}", file.content);
+            }
+            return Ok(());
+        }
+    };
+    let mut written: Vec<String> = vec!();
+    for file in files.iter() {
+        let path: PathBuf = out.join(&file.path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| H2okError::export(parent, e))?;
+        }
+        std::fs::write(&path, &file.content).map_err(|e| H2okError::export(&path, e))?;
+        written.push(path.to_string_lossy().to_string());
+    }
+
+    let output: OutputFormat = export_specification.output;
+    if output != OutputFormat::Text {
+        output::print_document(&ExportDocument {
+            name: deployment.specification.name.clone(),
+            format: export_specification.format.name().to_string(),
+            files: written,
+        }, output);
+    } else if running_on_terminal() {
+        println!("Deployment '{}' exported to '{}':", deployment.specification.name, out.display());
+        for path in written.iter() {
+            println!("  {}", path);
+        }
+    } else {
+        print!("{}", out.display());
+    }
+    return Ok(());
+}
+
+/// Brings the H2O deployment named in the cluster spec file to the state specified. The current state is read from the Kubernetes
+/// cluster, so changes made outside of h2ok are detected as well. Changes requiring the H2O cluster to be recreated are only made
+/// if requested, none of the changes are made otherwise.
+fn apply(apply_specification: ApplySpecification) -> Result<(), H2okError> {
+    let spec_path: &Path = &apply_specification.spec_path;
+    let document: String = std::fs::read_to_string(spec_path).map_err(|e| H2okError::spec(spec_path, e))?;
+    let cluster_spec: ClusterSpec = ClusterSpec::parse(&document).map_err(|reason| H2okError::spec(spec_path, reason))?;
+    // Recorded in the deployment descriptor as an absolute path, so it points to the cluster spec regardless of the working directory.
+    let spec_file: PathBuf = std::fs::canonicalize(spec_path).unwrap_or_else(|_| spec_path.to_path_buf());
+
+    let cluster: ConnectedCluster = kubernetes_client(&apply_specification.kubeconfig_path, &apply_specification.connection)?;
+    let namespace: String = cluster_spec.metadata.namespace.clone().unwrap_or_else(|| cluster.default_namespace.clone());
+    let name: &str = &cluster_spec.metadata.name;
+    let retry_policy: &RetryPolicy = &apply_specification.retry_policy;
+    let descriptor_path: PathBuf = descriptor_path(name, &namespace, cluster.context.as_deref(), &apply_specification.state_dir,
+                                                   &apply_specification.descriptor_out);
+    let store: DescriptorStore = apply_specification.store;
+    // Changes are only made while holding the locks, the plan is only reported.
+    let _locks: Option<DeploymentLocks> = if apply_specification.plan {
+        None
+    } else {
+        Some(DeploymentLocks {
+            _descriptor: if store == DescriptorStore::Local { Some(lock_descriptor(&descriptor_path, "apply")?) } else { None },
+            _lease: lock_deployment(&cluster.client, name, &namespace, "apply", retry_policy)?,
+        })
+    };
+    if store == DescriptorStore::Local && descriptor_path.is_file() && !apply_specification.plan {
+        verify_cluster_identity(&read_deployment(&descriptor_path)?, &cluster, apply_specification.ignore_cluster_mismatch, retry_policy)?;
+    }
+
+    let current: Option<Deployment> = match k8s::discovery::adopt_deployment(&cluster.client, name, &namespace, apply_specification.kubeconfig_path.clone()) {
+        Ok(current) => { Some(current) }
+        Err(AdoptionError::NotFound(_)) => { None }
+        Err(e) => { return Err(H2okError::adoption(&format!("Unable to read H2O deployment '{}'", name), e)); }
+    };
+    let changes: Vec<Change> = k8s::spec::plan(&cluster_spec, current.as_ref());
+    let existing_descriptor: Option<String> = match store {
+        DescriptorStore::Local => { Some(descriptor_path.to_string_lossy().to_string()).filter(|_| descriptor_path.is_file()) }
+        DescriptorStore::Cluster => {
+            k8s::store::load(&cluster.client, name, &namespace, retry_policy)
+                .map_err(|e| H2okError::api(&format!("Unable to load the deployment descriptor of '{}' from the Kubernetes cluster", name), e))?
+                .map(|_| stored_descriptor_reference(&namespace, &k8s::store::config_map_name(name)))
+        }
+    };
+    if apply_specification.plan {
+        print_apply_outcome(&cluster_spec, &namespace, existing_descriptor, &changes, false, &apply_specification);
+        return Ok(());
+    }
+    let recreation_required: bool = changes.iter().any(Change::requires_recreation);
+    if recreation_required && !apply_specification.recreate {
+        let modifications: Vec<String> = changes.iter().filter(|change| change.requires_recreation()).map(Change::to_string).collect();
+        return Err(H2okError::Incomplete(format!("Deployment '{}' differs from the cluster spec, no changes made: {}. Use '--recreate' to recreate \
+        the H2O cluster, losing all the data held in memory.", name, modifications.join(", "))));
+    }
+    if changes.is_empty() && existing_descriptor.is_some() {
+        print_apply_outcome(&cluster_spec, &namespace, existing_descriptor, &changes, true, &apply_specification);
+        return Ok(());
+    }
+
+    let created: bool = current.is_none() || recreation_required;
+    let mut deployment: Deployment = match current {
+        Some(current) if !recreation_required => { current }
+        current => {
+            let deployment_spec: DeploymentSpecification = cluster_spec.deployment_specification(namespace.clone(), apply_specification.kubeconfig_path.clone(),
+                                                                                                  spec_file.clone());
+            let options: DeployOptions = cluster_spec.deploy_options(retry_policy.clone());
+            // Checked before the current deployment is removed, so a failed check leaves it running. The resources it uses
+            // are released before the recreated one is deployed.
+            run_preflight(&cluster, &deployment_spec, &options, current.as_ref().map(|current| &current.specification))?;
+            if let Some(mut current) = current {
+                if current.stateful_sets.iter().any(k8s::protection::is_protected) {
+                    return Err(H2okError::Incomplete(format!("Deployment '{}' is protected against deletion and can not be recreated. \
+                    Remove the protection first using 'h2ok unprotect'.", name)));
                 }
-            } else {
-                print!("{}", deployment_file_name);
+                let options: UndeployOptions = UndeployOptions { force: true, retry: retry_policy.clone(), ..UndeployOptions::default() };
+                k8s::undeploy_h2o(&cluster.client, &mut current, &options)
+                    .map_err(|reasons| H2okError::Incomplete(format!("Unable to remove deployment '{}' to recreate it: {}", name, reasons.join(" "))))?;
+                wait_until_pods_gone(&cluster.client, &current, Some(RECREATION_TIMEOUT)).map_err(H2okError::Timeout)?;
+            }
+            k8s::deploy_h2o_cluster(&cluster.client, deployment_spec, &options)
+                .map_err(|error| H2okError::rollback("Unable to deploy H2O cluster", error))?
+        }
+    };
+    for change in changes.iter() {
+        match change {
+            Change::CreateIngress => {
+                k8s::deploy_ingress(&cluster.client, &mut deployment, retry_policy)
+                    .map_err(|error| H2okError::rollback(&format!("Unable to create ingress for {} deployment", name), error))?;
+            }
+            Change::RemoveIngress => {
+                k8s::remove_ingresses(&cluster.client, &mut deployment, retry_policy)
+                    .map_err(|reasons| H2okError::Incomplete(format!("Unable to remove ingress of deployment '{}': {}", name, reasons.join(" "))))?;
+            }
+            // A created deployment is already protected, if requested.
+            Change::Protect(protected) if !created => {
+                k8s::protection::set_protection(&cluster.client, &deployment, *protected, retry_policy)
+                    .map_err(|e| H2okError::api(&format!("Unable to change deletion protection of deployment '{}'", name), e))?;
+            }
+            _ => {}
+        }
+    }
+
+    deployment.specification.connection = apply_specification.connection.clone();
+    deployment.specification.spec_file = Some(spec_file);
+    deployment.specification.cluster_identity = Some(k8s::identity::identify(&cluster, retry_policy)
+        .map_err(|e| H2okError::api("Unable to identify the Kubernetes cluster", e))?);
+    let persisted_filename: String = match store {
+        DescriptorStore::Local => { persist_deployment(&deployment, &descriptor_path, true)? }
+        DescriptorStore::Cluster => { store_deployment(&cluster.client, &deployment, &apply_specification.descriptor_out, retry_policy)? }
+    };
+    print_apply_outcome(&cluster_spec, &namespace, Some(persisted_filename), &changes, true, &apply_specification);
+    return Ok(());
+}
+
+/// Prints the changes planned or made by `apply`. If not running on a terminal, only the deployment descriptor path is printed
+/// once applied, the same way `deploy` does.
+fn print_apply_outcome(cluster_spec: &ClusterSpec, namespace: &str, descriptor: Option<String>, changes: &[Change], applied: bool,
+                       apply_specification: &ApplySpecification) {
+    let name: &str = &cluster_spec.metadata.name;
+    let spec_path: String = apply_specification.spec_path.to_string_lossy().to_string();
+    let output: OutputFormat = apply_specification.output;
+    if output != OutputFormat::Text {
+        output::print_document(&ApplyDocument {
+            name: name.to_string(),
+            namespace: namespace.to_string(),
+            spec: spec_path,
+            descriptor,
+            changes: changes.iter().map(Change::to_string).collect(),
+            applied,
+        }, output);
+    } else if changes.is_empty() && running_on_terminal() {
+        println!("Deployment '{}' matches the cluster spec '{}', nothing to do.", name, spec_path);
+    } else if !applied {
+        if !changes.is_empty() {
+            println!("Changes required to bring deployment '{}' to the cluster spec '{}':", name, spec_path);
+            for change in changes.iter() {
+                println!("  - {}", change);
+            }
+        }
+    } else if running_on_terminal() {
+        if !changes.is_empty() {
+            println!("Cluster spec '{}' applied to deployment '{}':", spec_path, name);
+            for change in changes.iter() {
+                println!("  - {}", change);
             }
         }
+        if let Some(descriptor) = descriptor {
+            println!("To undeploy, use the '{}' command.", undeploy_command(name, namespace, &descriptor,
+                                                                            stored_in_cluster(apply_specification.store, &apply_specification.descriptor_out)));
+        }
+    } else if let Some(descriptor) = descriptor {
+        print!("{}", descriptor);
+    }
+}
+
+/// Displays the effective values of the `deploy` arguments, each with the source it comes from.
+fn show_config(config_specification: ConfigSpecification) {
+    let files: Vec<String> = config_specification.config.files.iter()
+        .map(|file| file.path.to_string_lossy().to_string())
+        .collect();
+    let presets: Vec<String> = config_specification.config.preset_names();
+    let output: OutputFormat = config_specification.output;
+    if output != OutputFormat::Text {
+        output::print_document(&ConfigDocument {
+            files,
+            presets,
+            values: config_specification.values.iter().map(ConfigValueDocument::new).collect(),
+        }, output);
+        return;
+    }
+
+    if files.is_empty() {
+        println!("No configuration files found.");
+    } else {
+        println!("Configuration files: {}", files.join(", "));
+    }
+    if !presets.is_empty() {
+        println!("Presets: {}", presets.join(", "));
+    }
+    for effective_value in config_specification.values.iter() {
+        println!("{:<18} {:<24} {}", effective_value.name, effective_value.value.as_deref().unwrap_or("-"), effective_value.source);
+    }
+}
+
+fn descriptor(descriptor_specification: DescriptorSpecification) -> Result<(), H2okError> {
+    let (paths, migrate): (Vec<PathBuf>, bool) = match descriptor_specification.action {
+        DescriptorAction::Schema => {
+            print!("{}", descriptor::SCHEMA);
+            return Ok(());
+        }
+        DescriptorAction::Migrate(paths) => { (paths, true) }
+        DescriptorAction::Validate(paths) => { (paths, false) }
+    };
+
+    let output: OutputFormat = descriptor_specification.output;
+    let mut documents: Vec<DescriptorDocument> = Vec::with_capacity(paths.len());
+    let mut failure: Option<H2okError> = None;
+    for path in paths.iter() {
+        let document: DescriptorDocument = check_descriptor(path, migrate);
+        if output == OutputFormat::Text {
+            print_descriptor_outcome(&document);
+        }
+        if document.result == DescriptorResult::Invalid && failure.is_none() {
+            failure = Some(H2okError::descriptor(path, document.problems.join(" ")));
+        }
+        documents.push(document);
+    }
+    output::print_document(&DescriptorReport { current_version: descriptor::DESCRIPTOR_VERSION, descriptors: documents }, output);
+    return match failure {
+        Some(e) => { Err(e) }
+        None => { Ok(()) }
+    };
+}
+
+/// Reads the deployment descriptor under the given path and checks the deployment it describes. If `migrate` is true,
+/// a valid descriptor of an older version is rewritten in the current version of the descriptor format.
+fn check_descriptor(path: &Path, migrate: bool) -> DescriptorDocument {
+    let path_name: String = path.to_string_lossy().to_string();
+    let _lock: Option<DescriptorLock> = if migrate {
+        match lock_descriptor(path, "descriptor migrate") {
+            Ok(lock) => { Some(lock) }
+            Err(e) => { return DescriptorDocument { path: path_name, version: None, result: DescriptorResult::Invalid, problems: vec!(e.to_string()) }; }
+        }
+    } else {
+        None
+    };
+    let (deployment, version): (Deployment, u32) = match descriptor::read_versioned(path) {
+        Ok(read) => { read }
         Err(e) => {
-            panic!("Unable to create ingress for {} deployment. Reason: \n{}", &deployment.specification.name, e);
+            return DescriptorDocument { path: path_name, version: None, result: DescriptorResult::Invalid, problems: vec!(e.to_string()) };
+        }
+    };
+    let problems: Vec<String> = descriptor::problems(&deployment);
+    let result: DescriptorResult = if !problems.is_empty() {
+        DescriptorResult::Invalid
+    } else if version == descriptor::DESCRIPTOR_VERSION {
+        DescriptorResult::Valid
+    } else if !migrate {
+        DescriptorResult::Outdated
+    } else {
+        if let Err(e) = descriptor::write(&deployment, path) {
+            return DescriptorDocument { path: path_name, version: Some(version), result: DescriptorResult::Invalid, problems: vec!(e.to_string()) };
+        }
+        DescriptorResult::Migrated
+    };
+    return DescriptorDocument { path: path_name, version: Some(version), result, problems };
+}
+
+fn print_descriptor_outcome(document: &DescriptorDocument) {
+    let current: u32 = descriptor::DESCRIPTOR_VERSION;
+    match document.result {
+        DescriptorResult::Valid => { println!("VALID     {} (version {})", document.path, current); }
+        DescriptorResult::Outdated => {
+            println!("OUTDATED  {} (version {}, current version is {}). Use 'h2ok descriptor migrate' to upgrade it.",
+                     document.path, document.version.unwrap_or_default(), current);
         }
+        DescriptorResult::Migrated => { println!("MIGRATED  {} (version {} to {})", document.path, document.version.unwrap_or_default(), current); }
+        DescriptorResult::Invalid => { println!("INVALID   {}: {}", document.path, document.problems.join(" ")); }
     }
 }
 
-/// Extracts a deployment descriptor and a Client from a deployment descriptor file.
-/// It is assumed the caller has verified the given file exists - panics otherwise.
-/// If there is no Client described in the `deployment_descriptor`, it is assumed the one from the
+fn wait(wait_specification: WaitSpecification) -> Result<(), H2okError> {
+    let (deployment, client): (Deployment, Client) = extract_existing_deployment(&wait_specification.descriptor, &wait_specification.retry_policy)?;
+
+    return match k8s::wait::wait_for(&client, &deployment, wait_specification.condition, wait_specification.timeout) {
+        Ok(_) => {
+            let output: OutputFormat = wait_specification.output;
+            if output != OutputFormat::Text {
+                output::print_document(&WaitDocument {
+                    name: deployment.specification.name.clone(),
+                    namespace: deployment.specification.namespace.clone(),
+                    condition: wait_specification.condition.name().to_string(),
+                }, output);
+            } else if running_on_terminal() {
+                println!("Deployment '{}' is {:?}.", deployment.specification.name, wait_specification.condition);
+            }
+            Ok(())
+        }
+        Err(WaitError::Timeout) => {
+            Err(H2okError::Timeout(format!("Timed out after {:?} waiting for deployment '{}' to become {:?}.", wait_specification.timeout,
+                                           deployment.specification.name, wait_specification.condition)))
+        }
+        Err(WaitError::Api(error)) => {
+            Err(H2okError::api(&format!("Unable to wait for deployment '{}' to become {:?}", deployment.specification.name, wait_specification.condition), error))
+        }
+        Err(error) => {
+            Err(H2okError::Incomplete(format!("Deployment '{}' can not become {:?}. Reason: {}", deployment.specification.name,
+                                              wait_specification.condition, error)))
+        }
+    };
+}
+
+fn status(status_specification: StatusSpecification) -> Result<(), H2okError> {
+    let (deployment, client): (Deployment, Client) = extract_existing_deployment(&status_specification.descriptor, &status_specification.retry_policy)?;
+    let output: OutputFormat = status_specification.output;
+
+    if status_specification.watch {
+        let clear_screen: bool = output == OutputFormat::Text && running_on_terminal();
+        let watch_result = k8s::status::watch_status(&client, &deployment, STATUS_REFRESH_INTERVAL, |deployment_status| {
+            if clear_screen {
+                print!("\x1B[2J\x1B[H");
+            }
+            print_status(deployment_status, output);
+        });
+        watch_result.map_err(|e| H2okError::api(&format!("Unable to watch status of '{}' deployment", &deployment.specification.name), e))?;
+    } else {
+        let deployment_status: DeploymentStatus = k8s::status::deployment_status(&client, &deployment)
+            .map_err(|e| H2okError::api(&format!("Unable to obtain status of '{}' deployment", &deployment.specification.name), e))?;
+        print_status(&deployment_status, output);
+    }
+    return Ok(());
+}
+
+fn print_status(deployment_status: &DeploymentStatus, output: OutputFormat) {
+    if output != OutputFormat::Text {
+        output::print_document(deployment_status, output);
+        return;
+    }
+
+    println!("Deployment '{}' in namespace '{}'", deployment_status.name, deployment_status.namespace);
+    println!();
+    println!("{:<40} {:<10} {:<6} {:<9} {:<25} {:<16}", "POD", "PHASE", "READY", "RESTARTS", "NODE", "IP");
+    for pod in deployment_status.pods.iter() {
+        let leader_mark: &str = if deployment_status.leader.as_ref() == Some(&pod.name) { " (leader)" } else { "" };
+        println!("{:<40} {:<10} {:<6} {:<9} {:<25} {:<16}", format!("{}{}", pod.name, leader_mark),
+                 pod.phase.as_deref().unwrap_or("Unknown"), pod.ready, pod.restarts,
+                 pod.node.as_deref().unwrap_or("-"), pod.ip.as_deref().unwrap_or("-"));
+    }
+    println!();
+
+    match &deployment_status.cloud {
+        None => {
+            println!("H2O cloud status unavailable: {}", deployment_status.cloud_error.as_deref().unwrap_or("unknown reason"));
+        }
+        Some(cloud) => {
+            println!("H2O cloud '{}': version {}, size {}, healthy: {}, consensus: {}, locked: {}", cloud.cloud_name, cloud.version,
+                     cloud.cloud_size, cloud.cloud_healthy, cloud.consensus, cloud.locked);
+            println!("{:<25} {:<8} {:<12} {:<12}", "H2O NODE", "HEALTHY", "FREE MEMORY", "MAX MEMORY");
+            for node in cloud.nodes.iter() {
+                println!("{:<25} {:<8} {:<12} {:<12}", node.ip_port, node.healthy, format_bytes(node.free_mem), format_bytes(node.max_mem));
+            }
+        }
+    }
+}
+
+/// Formats the given number of bytes to a human-readable form, using binary prefixes, e.g. `1.5 GiB`.
+fn format_bytes(bytes: i64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value: f64 = bytes as f64;
+    let mut unit_index: usize = 0;
+    while value >= 1024.0 && unit_index < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit_index += 1;
+    }
+    return format!("{:.1} {}", value, UNITS[unit_index]);
+}
+
+fn list(list_specification: ListSpecification) -> Result<(), H2okError> {
+    let ConnectedCluster { client, default_namespace, context, .. } = kubernetes_client(&list_specification.kubeconfig_path, &list_specification.connection)?;
+    let namespace: Option<String> = if list_specification.all_namespaces {
+        None
+    } else {
+        Some(list_specification.namespace.clone().unwrap_or(default_namespace))
+    };
+
+    let deployments: Vec<DeploymentSummary> = k8s::discovery::list_deployments(&client, namespace.as_deref())
+        .map_err(|e| H2okError::api("Unable to list H2O deployments", e))?;
+
+    if list_specification.output != OutputFormat::Text {
+        let deployments: Vec<SummaryDocument> = deployments.iter()
+            .map(|deployment| {
+                let descriptor: Option<String> = local_descriptor_paths(&deployment.name, &deployment.namespace, context.as_deref(), &list_specification.state_dir)
+                    .first()
+                    .map(|path| path.to_string_lossy().to_string());
+                SummaryDocument::new(deployment, descriptor)
+            })
+            .collect();
+        output::print_document(&SummaryList { deployments }, list_specification.output);
+        return Ok(());
+    }
+
+    println!("{:<30} {:<20} {:<6} {:<5} {:<8} {:<8} {:<15} {:<40} {:<10}", "NAME", "NAMESPACE", "NODES", "CPUS", "MEMORY", "AGE", "CREATOR", "URL", "DESCRIPTOR");
+    for deployment in deployments.iter() {
+        let age: String = deployment.created
+            .map(|created| format_age(Utc::now().signed_duration_since(created)))
+            .unwrap_or_else(|| "-".to_string());
+        let has_descriptor: bool = !local_descriptor_paths(&deployment.name, &deployment.namespace, context.as_deref(), &list_specification.state_dir).is_empty();
+        println!("{:<30} {:<20} {:<6} {:<5} {:<8} {:<8} {:<15} {:<40} {:<10}", deployment.name, deployment.namespace, deployment.num_h2o_nodes,
+                 deployment.cpu.as_deref().unwrap_or("-"), deployment.memory.as_deref().unwrap_or("-"), age,
+                 deployment.creator.as_deref().unwrap_or("-"), deployment.url.as_deref().unwrap_or("-"),
+                 if has_descriptor { "yes" } else { "no" });
+    }
+    return Ok(());
+}
+
+fn adopt(adopt_specification: AdoptSpecification) -> Result<(), H2okError> {
+    let cluster: ConnectedCluster = kubernetes_client(&adopt_specification.kubeconfig_path, &adopt_specification.connection)?;
+    let namespace: String = adopt_specification.namespace.unwrap_or_else(|| cluster.default_namespace.clone());
+
+    let name: &str = &adopt_specification.name;
+
+    let mut deployment: Deployment = k8s::discovery::adopt_deployment(&cluster.client, name, &namespace, adopt_specification.kubeconfig_path)
+        .map_err(|e| H2okError::adoption(&format!("Unable to adopt H2O deployment '{}'", name), e))?;
+    // The deployment descriptor is to reconnect to the very same cluster the deployment has been adopted from.
+    deployment.specification.connection = adopt_specification.connection;
+    deployment.specification.cluster_identity = Some(k8s::identity::identify(&cluster, &adopt_specification.retry_policy)
+        .map_err(|e| H2okError::api("Unable to identify the Kubernetes cluster", e))?);
+    let persisted_filename = persist_new_deployment(&deployment, &cluster, adopt_specification.store, &adopt_specification.state_dir,
+                                                    &adopt_specification.descriptor_out, &adopt_specification.retry_policy)?;
+
+    let output: OutputFormat = adopt_specification.output;
+    if output != OutputFormat::Text {
+        output::print_document(&DeploymentDocument::new(&deployment, Some(persisted_filename)), output);
+    } else if running_on_terminal() {
+        println!("Deployment '{}' adopted: {} stateful set(s), {} service(s), {} ingress(es).", deployment.specification.name,
+                 deployment.stateful_sets.len(), deployment.services.len(), deployment.ingresses.len());
+        println!("To undeploy, use the '{}' command.", undeploy_command(&deployment.specification.name, &deployment.specification.namespace, &persisted_filename,
+                                                                        stored_in_cluster(adopt_specification.store, &adopt_specification.descriptor_out)));
+    } else {
+        print!("{}", persisted_filename);
+    }
+    return Ok(());
+}
+
+/// Formats the given age in the most significant unit only, e.g. `5d` or `3h`, the same way `kubectl` does.
+fn format_age(age: chrono::Duration) -> String {
+    return if age.num_days() > 0 {
+        format!("{}d", age.num_days())
+    } else if age.num_hours() > 0 {
+        format!("{}h", age.num_hours())
+    } else if age.num_minutes() > 0 {
+        format!("{}m", age.num_minutes())
+    } else {
+        format!("{}s", age.num_seconds().max(0))
+    };
+}
+
+/// Extracts a deployment descriptor and a Client from a deployment descriptor file, or from the Kubernetes cluster the deployment descriptor is stored in.
+/// If there is no Client described in the deployment descriptor file, it is assumed the one from the
 /// environment as defined by `KUBECONFIG` environment variable or some well-known places should be used,
 /// as such a kubeconfig was used to create the original deployment described in the file.
-fn extract_existing_deployment(deployment_descriptor: &Path) -> (Deployment, Client) {
-    let deployment_file = File::open(deployment_descriptor).unwrap();
-    let deployment: Deployment = serde_json::from_reader(deployment_file).unwrap();
+fn extract_existing_deployment(descriptor_source: &DescriptorSource, retry_policy: &RetryPolicy) -> Result<(Deployment, Client), H2okError> {
+    let (deployment, cluster): (Deployment, ConnectedCluster) = connect_to_deployment(descriptor_source, retry_policy)?;
+    return Ok((deployment, cluster.client));
+}
 
-    // Attempt to use the very same kubeconfig to undeploy as was used to deploy
-    let (client, _): (Client, String) = match &deployment.specification.kubeconfig_path {
-        None => {
+/// Extracts a deployment descriptor and a Client the same way `extract_existing_deployment` does, for the deployment to be modified
+/// by the `operation`. The deployment descriptor file and the deployment are locked first, so the deployment descriptor read is not changed
+/// by anyone else until the locks returned are dropped.
+/// The Kubernetes cluster connected to is verified to be the one recorded in the deployment descriptor file. On a mismatch, the deployment
+/// is refused to be modified, unless `ignore_cluster_mismatch` is true. Deployment descriptors stored in the Kubernetes cluster are not verified, as they
+/// have been found in the very cluster the deployment lives in, no matter the kubeconfig used.
+fn extract_deployment_to_modify(descriptor_source: &DescriptorSource, operation: &str, ignore_cluster_mismatch: bool,
+                                retry_policy: &RetryPolicy) -> Result<(Deployment, Client, DeploymentLocks), H2okError> {
+    return match descriptor_source {
+        DescriptorSource::File(path) => {
+            let descriptor_lock: DescriptorLock = lock_descriptor(path, operation)?;
+            let (deployment, cluster): (Deployment, ConnectedCluster) = connect_to_deployment(descriptor_source, retry_policy)?;
+            verify_cluster_identity(&deployment, &cluster, ignore_cluster_mismatch, retry_policy)?;
+            let lease: Option<DeploymentLease> = lock_deployment(&cluster.client, &deployment.specification.name, &deployment.specification.namespace,
+                                                                 operation, retry_policy)?;
+            Ok((deployment, cluster.client, DeploymentLocks { _descriptor: Some(descriptor_lock), _lease: lease }))
+        }
+        DescriptorSource::Cluster { name, namespace, kubeconfig_path, connection } => {
+            let cluster: ConnectedCluster = kubernetes_client(kubeconfig_path, connection)?;
+            let namespace: String = namespace.clone().unwrap_or_else(|| cluster.default_namespace.clone());
+            let lease: Option<DeploymentLease> = lock_deployment(&cluster.client, name, &namespace, operation, retry_policy)?;
+            let deployment: Deployment = load_stored_deployment(&cluster.client, name, &namespace, retry_policy)?;
+            Ok((deployment, cluster.client, DeploymentLocks { _descriptor: None, _lease: lease }))
+        }
+    };
+}
+
+/// Locks held while a deployment is being modified, released once dropped.
+struct DeploymentLocks {
+    _descriptor: Option<DescriptorLock>,
+    _lease: Option<DeploymentLease>,
+}
+
+/// Locks the deployment descriptor file for the `operation`, so it is not updated by multiple h2ok processes at once.
+fn lock_descriptor(path: &Path, operation: &str) -> Result<DescriptorLock, H2okError> {
+    return DescriptorLock::acquire(path, operation).map_err(|e| match e {
+        LockError::Held(holder) => { H2okError::Locked(format!("Deployment descriptor '{}' is locked by {}.", path.display(), holder)) }
+        LockError::Io(e) => { H2okError::descriptor(path, e) }
+    });
+}
+
+/// Locks the deployment in the Kubernetes cluster for the `operation` using a lease, so it is not changed by multiple h2ok processes at once.
+/// If the user is not permitted to manage leases, the operation proceeds without the lock. None is returned also if the namespace
+/// does not exist yet, as there is no deployment to be changed concurrently.
+fn lock_deployment(client: &Client, name: &str, namespace: &str, operation: &str, retry_policy: &RetryPolicy) -> Result<Option<DeploymentLease>, H2okError> {
+    return match k8s::lease::acquire(client, name, namespace, operation, retry_policy) {
+        Ok(lease) => { Ok(Some(lease)) }
+        Err(LeaseError::Held(holder)) => {
+            Err(H2okError::Locked(format!("Deployment '{}' in namespace '{}' is locked by {}.", name, namespace, holder)))
+        }
+        Err(LeaseError::Api(kube::Error::Api(response))) if response.code == 403 => {
+            eprintln!("Warning: Not permitted to create lease '{}' in namespace '{}'. Proceeding without locking deployment '{}'.",
+                      k8s::lease::lease_name(name), namespace, name);
+            Ok(None)
+        }
+        Err(LeaseError::Api(kube::Error::Api(response))) if response.code == 404 => { Ok(None) }
+        Err(LeaseError::Api(e)) => { Err(H2okError::api(&format!("Unable to lock deployment '{}'", name), e)) }
+    };
+}
+
+/// Reads the deployment descriptor of an existing deployment and connects to the Kubernetes cluster the deployment lives in.
+fn connect_to_deployment(descriptor_source: &DescriptorSource, retry_policy: &RetryPolicy) -> Result<(Deployment, ConnectedCluster), H2okError> {
+    return match descriptor_source {
+        DescriptorSource::File(path) => {
+            let deployment: Deployment = read_deployment(path)?;
+            // Attempt to use the very same kubeconfig, context and identity to undeploy as was used to deploy.
             // No kubeconfig specified means the one from the environment should be used.
-            k8s::try_default().unwrap()
+            let cluster: ConnectedCluster = kubernetes_client(&deployment.specification.kubeconfig_path, &deployment.specification.connection)?;
+            Ok((deployment, cluster))
         }
-        Some(kubeconfig_path) => {
-            k8s::from_kubeconfig(kubeconfig_path)
+        DescriptorSource::Cluster { name, namespace, kubeconfig_path, connection } => {
+            let cluster: ConnectedCluster = kubernetes_client(kubeconfig_path, connection)?;
+            let namespace: String = namespace.clone().unwrap_or_else(|| cluster.default_namespace.clone());
+            let deployment: Deployment = load_stored_deployment(&cluster.client, name, &namespace, retry_policy)?;
+            Ok((deployment, cluster))
         }
     };
+}
+
+/// Reads the deployment descriptor of an existing deployment. The Kubernetes cluster is only connected to if the deployment descriptor is stored there.
+fn read_existing_deployment(descriptor_source: &DescriptorSource, retry_policy: &RetryPolicy) -> Result<Deployment, H2okError> {
+    return match descriptor_source {
+        DescriptorSource::File(path) => { read_deployment(path) }
+        source => { connect_to_deployment(source, retry_policy).map(|(deployment, _)| deployment) }
+    };
+}
 
-    return (deployment, client);
+/// Loads the deployment descriptor of the deployment with the given name from the Kubernetes cluster, upgrading descriptors of older versions.
+fn load_stored_deployment(client: &Client, name: &str, namespace: &str, retry_policy: &RetryPolicy) -> Result<Deployment, H2okError> {
+    let reference: String = stored_descriptor_reference(namespace, &k8s::store::config_map_name(name));
+    let descriptor: String = k8s::store::load(client, name, namespace, retry_policy)
+        .map_err(|e| H2okError::api(&format!("Unable to load the deployment descriptor of '{}' from the Kubernetes cluster", name), e))?
+        .ok_or_else(|| H2okError::NotFound(format!("No deployment descriptor of deployment '{}' is stored in namespace '{}'. \
+        Deployments made without '--store cluster' can be stored there using 'h2ok adopt {} --store cluster'.", name, namespace, name)))?;
+    return descriptor::from_json(&descriptor).map_err(|e| H2okError::descriptor(Path::new(&reference), e));
+}
+
+/// Writes a modified deployment back to where its deployment descriptor has been read from. Returns the path to the deployment descriptor
+/// file or the ConfigMap it has been stored in.
+fn save_deployment(deployment: &Deployment, descriptor_source: &DescriptorSource, client: &Client, retry_policy: &RetryPolicy) -> Result<String, H2okError> {
+    return match descriptor_source {
+        DescriptorSource::File(path) => { persist_deployment(deployment, path, true) }
+        DescriptorSource::Cluster { .. } => { store_deployment(client, deployment, &None, retry_policy) }
+    };
+}
+
+/// Reads a Deployment from a deployment descriptor file, upgrading descriptors of older versions.
+fn read_deployment(deployment_descriptor: &Path) -> Result<Deployment, H2okError> {
+    return descriptor::read(deployment_descriptor).map_err(|e| H2okError::descriptor(deployment_descriptor, e));
+}
+
+/// Verifies the Kubernetes cluster connected to is the one the deployment has been deployed to. Deployment descriptors
+/// without a cluster identity recorded are not verified. If `ignore_cluster_mismatch` is true, a mismatch is only reported as a warning.
+fn verify_cluster_identity(deployment: &Deployment, cluster: &ConnectedCluster, ignore_cluster_mismatch: bool, retry_policy: &RetryPolicy) -> Result<(), H2okError> {
+    let recorded: &ClusterIdentity = match &deployment.specification.cluster_identity {
+        Some(recorded) => { recorded }
+        None => { return Ok(()); }
+    };
+    let current: ClusterIdentity = k8s::identity::identify(cluster, retry_policy)
+        .map_err(|e| H2okError::api("Unable to identify the Kubernetes cluster", e))?;
+    let differences: Vec<String> = k8s::identity::differences(recorded, &current);
+    if differences.is_empty() {
+        return Ok(());
+    }
+
+    let mismatch: String = format!("Deployment '{}' has been deployed to a different Kubernetes cluster than the one currently selected: {}.",
+                                   deployment.specification.name, differences.join(", "));
+    return if ignore_cluster_mismatch {
+        eprintln!("Warning: {} Proceeding, as '--ignore-cluster-mismatch' is used.", mismatch);
+        Ok(())
+    } else {
+        Err(H2okError::ClusterMismatch(mismatch))
+    };
 }
 
 /// Returns true if the CLI has been invoked from a TTY, otherwise false.
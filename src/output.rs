@@ -0,0 +1,310 @@
+use chrono::{DateTime, Utc};
+use kube::api::Meta;
+use serde::Serialize;
+
+use crate::cli::OutputFormat;
+use crate::cli::config::{EffectiveValue, Source};
+use crate::k8s::Deployment;
+use crate::k8s::discovery::DeploymentSummary;
+use crate::k8s::preflight::{Check, CheckResult};
+
+/// Machine-readable description of an H2O deployment, printed by `deploy`, `adopt` and `ingress`.
+#[derive(Serialize, Debug)]
+pub struct DeploymentDocument {
+    /// Path to the deployment descriptor file actually written, or `configmap/<namespace>/<name>` if stored in the Kubernetes cluster.
+    pub descriptor: Option<String>,
+    pub name: String,
+    pub namespace: String,
+    /// URLs the H2O cluster is exposed on by ingresses, if exposed.
+    pub endpoints: Vec<String>,
+    pub resources: Vec<ResourceDocument>,
+}
+
+impl DeploymentDocument {
+    pub fn new(deployment: &Deployment, descriptor: Option<String>) -> Self {
+        DeploymentDocument {
+            descriptor,
+            name: deployment.specification.name.clone(),
+            namespace: deployment.specification.namespace.clone(),
+            endpoints: deployment.ingresses.iter().filter_map(crate::k8s::ingress::url).collect(),
+            resources: resources(deployment),
+        }
+    }
+}
+
+/// A single Kubernetes entity belonging to an H2O deployment.
+#[derive(Serialize, Debug)]
+pub struct ResourceDocument {
+    pub kind: String,
+    pub name: String,
+}
+
+/// Returns all the Kubernetes entities recorded in the given deployment.
+fn resources(deployment: &Deployment) -> Vec<ResourceDocument> {
+    let mut resources: Vec<ResourceDocument> = vec!();
+    if deployment.namespace_created {
+        resources.push(ResourceDocument { kind: "Namespace".to_string(), name: deployment.specification.namespace.clone() });
+    }
+    resources.extend(deployment.stateful_sets.iter().map(|stateful_set| ResourceDocument { kind: "StatefulSet".to_string(), name: stateful_set.name() }));
+    resources.extend(deployment.services.iter().map(|service| ResourceDocument { kind: "Service".to_string(), name: service.name() }));
+    resources.extend(deployment.ingresses.iter().map(|ingress| ResourceDocument { kind: "Ingress".to_string(), name: ingress.name() }));
+    return resources;
+}
+
+/// Result of undeployment of a single H2O deployment.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum UndeployResult {
+    /// All the Kubernetes entities of the deployment were removed.
+    Removed,
+    /// Some of the Kubernetes entities of the deployment were not removed.
+    PartiallyRemoved,
+    /// None of the Kubernetes entities of the deployment were removed.
+    Failed,
+}
+
+/// Machine-readable outcome of undeployment of a single H2O deployment.
+#[derive(Serialize, Debug)]
+pub struct UndeploymentDocument {
+    /// Path to the deployment descriptor undeployed or removed along with the deployment, if any.
+    pub descriptor: Option<String>,
+    pub name: Option<String>,
+    pub namespace: Option<String>,
+    pub result: UndeployResult,
+    /// Reasons the deployment has not been fully removed.
+    pub errors: Vec<String>,
+    /// Kubernetes entities still present.
+    pub remaining_resources: Vec<ResourceDocument>,
+}
+
+impl UndeploymentDocument {
+    pub fn new(deployment: Option<&Deployment>, descriptor: Option<String>, result: UndeployResult, errors: Vec<String>) -> Self {
+        UndeploymentDocument {
+            descriptor,
+            name: deployment.map(|deployment| deployment.specification.name.clone()),
+            namespace: deployment.map(|deployment| deployment.specification.namespace.clone()),
+            result,
+            errors,
+            remaining_resources: deployment.map(resources).unwrap_or_default(),
+        }
+    }
+}
+
+/// Machine-readable outcome of the `undeploy` command.
+#[derive(Serialize, Debug)]
+pub struct UndeployReport {
+    pub deployments: Vec<UndeploymentDocument>,
+}
+
+/// Machine-readable summary of an H2O deployment found in the Kubernetes cluster, printed by `list`.
+#[derive(Serialize, Debug)]
+pub struct SummaryDocument {
+    pub name: String,
+    pub namespace: String,
+    pub num_h2o_nodes: i32,
+    pub cpu: Option<String>,
+    pub memory: Option<String>,
+    /// Creation time in RFC 3339 format.
+    pub created: Option<String>,
+    pub creator: Option<String>,
+    pub url: Option<String>,
+    /// Path to the deployment descriptor in the current working directory, if there is one.
+    pub descriptor: Option<String>,
+}
+
+impl SummaryDocument {
+    pub fn new(summary: &DeploymentSummary, descriptor: Option<String>) -> Self {
+        SummaryDocument {
+            name: summary.name.clone(),
+            namespace: summary.namespace.clone(),
+            num_h2o_nodes: summary.num_h2o_nodes,
+            cpu: summary.cpu.clone(),
+            memory: summary.memory.clone(),
+            created: summary.created.as_ref().map(DateTime::<Utc>::to_rfc3339),
+            creator: summary.creator.clone(),
+            url: summary.url.clone(),
+            descriptor,
+        }
+    }
+}
+
+/// Machine-readable outcome of the `list` command.
+#[derive(Serialize, Debug)]
+pub struct SummaryList {
+    pub deployments: Vec<SummaryDocument>,
+}
+
+/// Machine-readable outcome of the `protect` and `unprotect` commands.
+#[derive(Serialize, Debug)]
+pub struct ProtectionDocument {
+    pub name: String,
+    pub namespace: String,
+    pub protected: bool,
+}
+
+/// Machine-readable outcome of the `wait` command, printed once the condition is met.
+#[derive(Serialize, Debug)]
+pub struct WaitDocument {
+    pub name: String,
+    pub namespace: String,
+    pub condition: String,
+}
+
+/// Machine-readable outcome of the `preflight` command.
+#[derive(Serialize, Debug)]
+pub struct PreflightDocument {
+    pub name: String,
+    pub namespace: String,
+    /// True if none of the checks failed.
+    pub passed: bool,
+    pub checks: Vec<Check>,
+}
+
+impl PreflightDocument {
+    pub fn new(name: &str, namespace: &str, checks: Vec<Check>) -> Self {
+        PreflightDocument {
+            name: name.to_string(),
+            namespace: namespace.to_string(),
+            passed: checks.iter().all(|check| check.result != CheckResult::Fail),
+            checks,
+        }
+    }
+}
+
+/// Machine-readable outcome of the `export` command.
+#[derive(Serialize, Debug)]
+pub struct ExportDocument {
+    pub name: String,
+    pub format: String,
+    /// Paths to the files written.
+    pub files: Vec<String>,
+}
+
+/// Machine-readable outcome of the `apply` command.
+#[derive(Serialize, Debug)]
+pub struct ApplyDocument {
+    pub name: String,
+    pub namespace: String,
+    /// Path to the cluster spec file applied.
+    pub spec: String,
+    /// Path to the deployment descriptor of the deployment managed by the cluster spec, or the ConfigMap it is stored in, if there is one.
+    pub descriptor: Option<String>,
+    /// Changes required to bring the deployment to the state specified, empty if it matches the cluster spec.
+    pub changes: Vec<String>,
+    /// True if the changes have been made, false if they have only been planned.
+    pub applied: bool,
+}
+
+/// Machine-readable effective configuration, printed by `config show`.
+#[derive(Serialize, Debug)]
+pub struct ConfigDocument {
+    /// Paths to the configuration files loaded, in the order of increasing precedence.
+    pub files: Vec<String>,
+    /// Names of the presets defined.
+    pub presets: Vec<String>,
+    pub values: Vec<ConfigValueDocument>,
+}
+
+/// Effective value of a single configurable argument.
+#[derive(Serialize, Debug)]
+pub struct ConfigValueDocument {
+    pub name: String,
+    pub value: Option<String>,
+    /// Kind of the source of the value - `flag`, `preset`, `config`, `default` or `unset`.
+    pub source: String,
+    /// Name of the preset the value comes from, if any.
+    pub preset: Option<String>,
+    /// Path to the configuration file the value comes from, if any.
+    pub file: Option<String>,
+}
+
+impl ConfigValueDocument {
+    pub fn new(effective_value: &EffectiveValue) -> Self {
+        let preset: Option<String> = match &effective_value.source {
+            Source::Preset { name, .. } => { Some(name.clone()) }
+            _ => { None }
+        };
+        ConfigValueDocument {
+            name: effective_value.name.to_string(),
+            value: effective_value.value.clone(),
+            source: effective_value.source.kind().to_string(),
+            preset,
+            file: effective_value.source.path().map(|path| path.to_string_lossy().to_string()),
+        }
+    }
+}
+
+/// Outcome of the `descriptor validate` or `descriptor migrate` command for a single deployment descriptor.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DescriptorResult {
+    /// The descriptor is valid and in the current version of the descriptor format.
+    Valid,
+    /// The descriptor is valid, yet in an older version of the descriptor format. It is upgraded whenever read.
+    Outdated,
+    /// The descriptor has been rewritten in the current version of the descriptor format.
+    Migrated,
+    /// The descriptor can not be read or does not describe a valid deployment.
+    Invalid,
+}
+
+/// Machine-readable outcome of the `descriptor validate` or `descriptor migrate` command for a single deployment descriptor.
+#[derive(Serialize, Debug)]
+pub struct DescriptorDocument {
+    pub path: String,
+    /// Version of the descriptor format the descriptor has been found in. None if the version could not be determined.
+    pub version: Option<u32>,
+    pub result: DescriptorResult,
+    /// Reasons the descriptor is invalid.
+    pub problems: Vec<String>,
+}
+
+/// Machine-readable outcome of the `descriptor validate` and `descriptor migrate` commands.
+#[derive(Serialize, Debug)]
+pub struct DescriptorReport {
+    /// Version of the descriptor format written by this version of h2ok.
+    pub current_version: u32,
+    pub descriptors: Vec<DescriptorDocument>,
+}
+
+/// Prints the given document to stdout in the given machine-readable format. A JSON document is printed on a single line,
+/// a YAML document starts with the `---` separator, so a stream of documents can be parsed. Nothing is printed for the text format.
+pub fn print_document<T: Serialize>(document: &T, output: OutputFormat) {
+    // Documents consist of strings, numbers and sequences only, their serialization can not fail.
+    match output {
+        OutputFormat::Text => {}
+        OutputFormat::Json => { println!("{}", serde_json::to_string(document).unwrap()); }
+        OutputFormat::Yaml => { println!("{}", serde_yaml::to_string(document).unwrap()); }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::core::v1::Service;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+
+    use crate::k8s::{Deployment, DeploymentSpecification};
+
+    use super::{DeploymentDocument, UndeploymentDocument, UndeployResult};
+
+    #[test]
+    fn test_deployment_document() {
+        let mut deployment: Deployment = Deployment::new(DeploymentSpecification::new("h2o-test".to_string(), "h2o".to_string(), 50,
+                                                                                      "1Gi".to_string(), 1, 2, None));
+        deployment.services.push(Service {
+            metadata: ObjectMeta { name: Some("h2o-test-service".to_string()), ..ObjectMeta::default() },
+            ..Service::default()
+        });
+        let document: DeploymentDocument = DeploymentDocument::new(&deployment, Some("h2o-test(1).h2ok".to_string()));
+        let json: serde_json::Value = serde_json::to_value(&document).unwrap();
+        assert_eq!("h2o-test(1).h2ok", json["descriptor"]);
+        assert_eq!("h2o", json["namespace"]);
+        assert_eq!("Service", json["resources"][0]["kind"]);
+        assert_eq!("h2o-test-service", json["resources"][0]["name"]);
+        assert!(json["endpoints"].as_array().unwrap().is_empty());
+
+        let undeployed: UndeploymentDocument = UndeploymentDocument::new(Some(&deployment), None, UndeployResult::PartiallyRemoved, vec!());
+        let yaml: String = serde_yaml::to_string(&undeployed).unwrap();
+        assert!(yaml.contains("result: partially_removed"));
+    }
+}
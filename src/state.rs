@@ -0,0 +1,116 @@
+use std::path::{Path, PathBuf};
+
+/// Name of the directory holding deployment descriptors of deployments made using the in-cluster configuration, as there is no kubeconfig context.
+const IN_CLUSTER_CONTEXT: &str = "in-cluster";
+/// Suffix of the deployment descriptor files in the state directory.
+const DESCRIPTOR_SUFFIX: &str = ".json";
+
+/// Directory the deployment descriptors are stored in, one per deployment under `<context>/<namespace>/<name>.json`,
+/// so deployments of the same name in different namespaces or Kubernetes clusters do not collide.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateDir {
+    pub root: PathBuf,
+}
+
+impl StateDir {
+    pub fn new(root: PathBuf) -> Self {
+        StateDir { root }
+    }
+
+    /// The state directory used unless the user chooses otherwise - `$XDG_STATE_HOME/h2ok`, defaulting to `~/.local/state/h2ok`.
+    /// None if neither of them is known.
+    pub fn default_location() -> Option<Self> {
+        return std::env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .or_else(|| dirs::home_dir().map(|home| home.join(".local").join("state")))
+            .map(|state_home| StateDir::new(state_home.join("h2ok")));
+    }
+
+    /// Path to the deployment descriptor of a deployment with the given name in the given namespace. The `context` is the kubeconfig
+    /// context the deployment has been made in, None if made using the in-cluster configuration.
+    pub fn descriptor_path(&self, context: Option<&str>, namespace: &str, name: &str) -> PathBuf {
+        return self.root
+            .join(path_segment(context.unwrap_or(IN_CLUSTER_CONTEXT)))
+            .join(path_segment(namespace))
+            .join(format!("{}{}", path_segment(name), DESCRIPTOR_SUFFIX));
+    }
+
+    /// Finds deployment descriptors of the deployments with the given name in any kubeconfig context. If `namespace` is given,
+    /// only the deployments in that namespace are found. The paths are sorted.
+    pub fn find(&self, name: &str, namespace: Option<&str>) -> Vec<PathBuf> {
+        let file_name: String = format!("{}{}", path_segment(name), DESCRIPTOR_SUFFIX);
+        let mut found: Vec<PathBuf> = vec!();
+        for context_dir in subdirectories(&self.root).iter() {
+            let namespace_dirs: Vec<PathBuf> = match namespace {
+                Some(namespace) => { vec!(context_dir.join(path_segment(namespace))) }
+                None => { subdirectories(context_dir) }
+            };
+            found.extend(namespace_dirs.iter()
+                .map(|namespace_dir| namespace_dir.join(&file_name))
+                .filter(|path| path.is_file()));
+        }
+        found.sort();
+        return found;
+    }
+}
+
+/// Returns true if the user-provided value refers to a deployment by its name rather than a deployment descriptor by its path.
+/// Names of the Kubernetes entities consist of lowercase alphanumeric characters, `-` and `.` only, values looking like
+/// a deployment descriptor file name are treated as paths.
+pub fn is_deployment_name(value: &str) -> bool {
+    return value.starts_with(|character: char| character.is_ascii_lowercase() || character.is_ascii_digit())
+        && value.chars().all(|character| character.is_ascii_lowercase() || character.is_ascii_digit() || character == '-' || character == '.')
+        && !value.ends_with(".h2ok")
+        && !value.ends_with(DESCRIPTOR_SUFFIX);
+}
+
+/// Kubeconfig context names may contain characters not allowed in file names, e.g. `/` in the names of EKS contexts.
+fn path_segment(value: &str) -> String {
+    return value.chars()
+        .map(|character| if character == '/' || character == '\\' || character == '\0' { '_' } else { character })
+        .collect::<String>()
+        .trim_start_matches('.')
+        .to_string();
+}
+
+fn subdirectories(directory: &Path) -> Vec<PathBuf> {
+    return match std::fs::read_dir(directory) {
+        Ok(entries) => {
+            entries.filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|path| path.is_dir())
+                .collect()
+        }
+        Err(_) => { vec!() }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::StateDir;
+
+    #[test]
+    fn test_find() {
+        let root: PathBuf = std::env::temp_dir().join(format!("h2ok-state-test-{}", std::process::id()));
+        let state_dir: StateDir = StateDir::new(root.clone());
+        let eks: PathBuf = state_dir.descriptor_path(Some("arn:aws:eks:eu-west-1:1234:cluster/h2o"), "h2o", "h2o-test");
+        assert_eq!(root.join("arn:aws:eks:eu-west-1:1234:cluster_h2o").join("h2o").join("h2o-test.json"), eks);
+        let in_cluster: PathBuf = state_dir.descriptor_path(None, "default", "h2o-test");
+        for path in [&eks, &in_cluster].iter() {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "{}").unwrap();
+        }
+
+        assert_eq!(vec!(eks.clone(), in_cluster.clone()), state_dir.find("h2o-test", None));
+        assert_eq!(vec!(in_cluster), state_dir.find("h2o-test", Some("default")));
+        assert!(state_dir.find("h2o-other", None).is_empty());
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert!(super::is_deployment_name("h2o-test"));
+        assert!(!super::is_deployment_name("h2o-test.h2ok"));
+        assert!(!super::is_deployment_name("./h2o-test"));
+    }
+}
@@ -50,6 +50,76 @@ Undeploys an existing H2O cluster from Kubernetes.*"#;
         .stdout(predicates::str::is_match(expected_output_pattern).unwrap());
 }
 
+#[test]
+fn test_wait_help() {
+    let mut cmd = Command::cargo_bin("h2ok").unwrap();
+    let assert: Assert = cmd.args(&["wait", "-h"])
+        .assert();
+
+    let expected_output_pattern: &str = r#"h2ok-wait.*
+Waits until an existing H2O deployment reaches the given condition.*"#;
+
+    assert.success()
+        .code(0)
+        .stdout(predicates::str::is_match(expected_output_pattern).unwrap());
+}
+
+#[test]
+fn test_preflight_help() {
+    let mut cmd = Command::cargo_bin("h2ok").unwrap();
+    let assert: Assert = cmd.args(&["preflight", "-h"])
+        .assert();
+
+    let expected_output_pattern: &str = r#"h2ok-preflight.*
+Checks whether an H2O cluster can be deployed.*"#;
+
+    assert.success()
+        .code(0)
+        .stdout(predicates::str::is_match(expected_output_pattern).unwrap());
+}
+
+#[test]
+fn test_apply_help() {
+    let mut cmd = Command::cargo_bin("h2ok").unwrap();
+    let assert: Assert = cmd.args(&["apply", "-h"])
+        .assert();
+
+    let expected_output_pattern: &str = r#"h2ok-apply.*
+Brings an H2O deployment to the state specified by a cluster spec file.*"#;
+
+    assert.success()
+        .code(0)
+        .stdout(predicates::str::is_match(expected_output_pattern).unwrap());
+}
+
+#[test]
+fn test_config_show_help() {
+    let mut cmd = Command::cargo_bin("h2ok").unwrap();
+    let assert: Assert = cmd.args(&["config", "show", "-h"])
+        .assert();
+
+    let expected_output_pattern: &str = r#"h2ok-config-show.*
+Displays the effective values of the 'deploy' arguments.*"#;
+
+    assert.success()
+        .code(0)
+        .stdout(predicates::str::is_match(expected_output_pattern).unwrap());
+}
+
+#[test]
+fn test_descriptor_validate_help() {
+    let mut cmd = Command::cargo_bin("h2ok").unwrap();
+    let assert: Assert = cmd.args(&["descriptor", "validate", "-h"])
+        .assert();
+
+    let expected_output_pattern: &str = r#"h2ok-descriptor-validate.*
+Checks deployment descriptors can be read by this version of h2ok.*"#;
+
+    assert.success()
+        .code(0)
+        .stdout(predicates::str::is_match(expected_output_pattern).unwrap());
+}
+
 
 #[test]
 fn test_deploy_undeploy() {
@@ -59,10 +129,11 @@ fn test_deploy_undeploy() {
 
     let output: Vec<u8> = assert_deploy.success()
         .code(0)
-        .stdout(predicates::str::is_match(".*\\.h2ok").unwrap())
+        .stdout(predicates::str::is_match(".*\\.json").unwrap())
         .get_output().clone().stdout;
 
-    let deployment_filename = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), String::from_utf8(output).unwrap().trim());
+    // The deployment descriptor is written to the state directory, its path is absolute.
+    let deployment_filename = String::from_utf8(output).unwrap().trim().to_string();
 
     let mut ingress_cmd: Command = Command::cargo_bin("h2ok").unwrap();
     let assert_ingress = ingress_cmd.args(&["ingress", "-f", &deployment_filename]).assert();
@@ -89,7 +160,7 @@ fn test_undeploy_piping() {
 
     let output = assert_deploy.success()
         .code(0)
-        .stdout(predicates::str::is_match(".*\\.h2ok").unwrap())
+        .stdout(predicates::str::is_match(".*\\.json").unwrap())
         .get_output().clone().stdout;
 
     let deployment_filename = String::from_utf8(output).unwrap();
@@ -117,5 +188,6 @@ fn test_undeploy_missing_deployment_descriptor() {
 
     assert_undeploy.failure()
         .code(1)
-        .stderr(predicates::str::is_match(r#"Unable to process user input: UserInputError \{ kind: UnreachableDeploymentDescriptor \}"#).unwrap());
+        .stderr(predicates::str::is_match(r#"Unable to process user input\. Deployment descriptor not found\."#).unwrap())
+        .stderr(predicates::str::contains("Hint: "));
 }